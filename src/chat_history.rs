@@ -1,10 +1,11 @@
 //! Chat History Management
 //! 聊天历史记录管理 - 完整的会话历史功能
 
+use crate::config::{AppConfig, GenerationParams};
+use crate::services::{attachment_block, ContentBlock, Usage};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
-use dirs;
 
 /// Chat message in history
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -13,6 +14,123 @@ pub struct ChatMessage {
     pub role: String,
     pub content: String,
     pub timestamp: u64,
+    /// The run producing this message was stopped before it finished
+    #[serde(default)]
+    pub cancelled: bool,
+    /// The answer stopped at the `max_tokens` limit and can be continued
+    #[serde(default)]
+    pub truncated: bool,
+    /// Provider that produced this message (assistant messages only)
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    /// Tokens used to produce this message, summed over all agent iterations
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    /// Estimated cost in USD (None if no price is configured for the model)
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// Images and documents sent with this message
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// Image or document attached to a message
+///
+/// The file is copied into the `attachments` directory next to the history file;
+/// `id` is its file name there.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Attachment {
+    pub id: String,
+    /// Original file name (shown in the UI and sent as the document title)
+    pub name: String,
+    pub media_type: String,
+    /// MCP resource the contents were read from (None for files and clipboard images)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<ResourceSource>,
+}
+
+/// Origin of an attachment read from an MCP server
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResourceSource {
+    pub server: String,
+    pub uri: String,
+}
+
+impl Attachment {
+    /// Maximum attachment size (larger files are rejected)
+    pub const MAX_BYTES: usize = 20 * 1024 * 1024;
+
+    /// Media type for a file name, if it's a supported attachment type
+    pub fn media_type_for(name: &str) -> Option<&'static str> {
+        let ext = name.rsplit('.').next()?.to_lowercase();
+        Some(match ext.as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            "pdf" => "application/pdf",
+            "txt" | "md" | "markdown" | "csv" | "json" | "log" | "rs" | "py" | "js" | "ts" | "toml" | "yaml" | "yml" => "text/plain",
+            _ => return None,
+        })
+    }
+
+    /// Copy file contents into the attachments directory
+    pub fn store(name: &str, media_type: &str, bytes: &[u8]) -> Result<Self> {
+        if bytes.len() > Self::MAX_BYTES {
+            return Err(ChatHistoryError::AttachmentTooLarge(name.to_string()));
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let id = match name.rsplit_once('.') {
+            Some((_, ext)) if !ext.is_empty() => format!("att-{}.{}", now, ext.to_lowercase()),
+            _ => format!("att-{}", now),
+        };
+        let attachment = Self {
+            id,
+            name: name.to_string(),
+            media_type: media_type.to_string(),
+            resource: None,
+        };
+        fs::write(attachment.path()?, bytes)?;
+        Ok(attachment)
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.media_type.starts_with("image/")
+    }
+
+    /// Path of the stored file
+    pub fn path(&self) -> Result<PathBuf> {
+        let mut path = ChatHistoryData::get_attachments_dir()?;
+        path.push(&self.id);
+        Ok(path)
+    }
+
+    /// `data:` URL of the file (for thumbnails)
+    pub fn data_url(&self) -> Result<String> {
+        use base64::Engine;
+        let bytes = fs::read(self.path()?)?;
+        Ok(format!(
+            "data:{};base64,{}",
+            self.media_type,
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        ))
+    }
+
+    /// Image or document content block for the AI request
+    pub fn content_block(&self) -> Result<ContentBlock> {
+        let bytes = fs::read(self.path()?)?;
+        Ok(attachment_block(&self.name, &self.media_type, &bytes))
+    }
+
+    /// Delete the stored file
+    pub fn remove(&self) {
+        if let Ok(path) = self.path() {
+            let _ = fs::remove_file(path);
+        }
+    }
 }
 
 /// Chat session (a conversation)
@@ -24,6 +142,26 @@ pub struct ChatSession {
     pub messages: Vec<ChatMessage>,
     pub created_at: u64,
     pub updated_at: u64,
+    /// Running token totals of all messages added to this session
+    #[serde(default)]
+    pub usage: Usage,
+    /// Running estimated cost in USD (requests without a configured price count as 0)
+    #[serde(default)]
+    pub cost: f64,
+    /// Generation parameters overriding the provider defaults for this session
+    #[serde(default)]
+    pub generation: GenerationParams,
+    /// Summary of older turns that no longer fit into the context (summarize policy)
+    #[serde(default)]
+    pub context_summary: Option<ContextSummary>,
+}
+
+/// AI-written summary of the oldest messages of a session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContextSummary {
+    pub text: String,
+    /// Id of the last message covered by the summary
+    pub through_message_id: String,
 }
 
 /// All chat history data
@@ -52,21 +190,27 @@ pub enum ChatHistoryError {
     Io(#[from] std::io::Error),
     #[error("JSON serialization error: {0}")]
     Json(#[from] serde_json::Error),
-    #[error("Configuration directory not found")]
-    ConfigDirNotFound,
+    #[error("Attachment too large: {0}")]
+    AttachmentTooLarge(String),
 }
 
 impl ChatHistoryData {
     /// Get the history file path
     fn get_history_path() -> Result<PathBuf> {
-        let mut path = dirs::config_dir()
-            .ok_or(ChatHistoryError::ConfigDirNotFound)?;
-        path.push("veld");
+        let mut path = AppConfig::get_config_dir();
         fs::create_dir_all(&path)?;
         path.push("chat_history.json");
         Ok(path)
     }
 
+    /// Get the attachments directory (next to the history file)
+    fn get_attachments_dir() -> Result<PathBuf> {
+        let mut path = AppConfig::get_config_dir();
+        path.push("attachments");
+        fs::create_dir_all(&path)?;
+        Ok(path)
+    }
+
     /// Load all chat history
     pub fn load() -> Result<Self> {
         let path = Self::get_history_path()?;
@@ -103,6 +247,10 @@ impl ChatHistoryData {
             messages: Vec::new(),
             created_at: now,
             updated_at: now,
+            usage: Usage::default(),
+            cost: 0.0,
+            generation: GenerationParams::default(),
+            context_summary: None,
         }
     }
 
@@ -124,6 +272,12 @@ impl ChatHistoryData {
 
     /// Add message to current session
     pub fn add_message(&mut self, message: ChatMessage) {
+        self.add_message_before(message, None);
+    }
+
+    /// Add message to current session right before the message with id `before`
+    /// (appended when `before` is None or not found)
+    pub fn add_message_before(&mut self, message: ChatMessage, before: Option<&str>) {
         if let Some(session) = self.get_current_session_mut() {
             let is_first_user_message = session.messages.is_empty() && message.role == "user";
             let title_preview = if is_first_user_message {
@@ -137,7 +291,14 @@ impl ChatHistoryData {
                 String::new()
             };
 
-            session.messages.push(message);
+            if let Some(usage) = &message.usage {
+                session.usage.add(usage);
+            }
+            session.cost += message.cost.unwrap_or(0.0);
+            let pos = before
+                .and_then(|id| session.messages.iter().position(|m| m.id == id))
+                .unwrap_or(session.messages.len());
+            session.messages.insert(pos, message);
             let now = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
@@ -151,6 +312,40 @@ impl ChatHistoryData {
         }
     }
 
+    /// Replace a message of the current session in place (e.g. an answer extended by "continue")
+    ///
+    /// `extra_usage` and `extra_cost` are what the change cost; they are added to the session totals.
+    pub fn update_message(&mut self, message: ChatMessage, extra_usage: Option<Usage>, extra_cost: Option<f64>) {
+        if let Some(session) = self.get_current_session_mut() {
+            let Some(existing) = session.messages.iter_mut().find(|m| m.id == message.id) else {
+                return;
+            };
+            *existing = message;
+            if let Some(usage) = &extra_usage {
+                session.usage.add(usage);
+            }
+            session.cost += extra_cost.unwrap_or(0.0);
+            session.updated_at = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+        }
+    }
+
+    /// Set the generation parameter overrides of the current session
+    pub fn set_generation_overrides(&mut self, generation: GenerationParams) {
+        if let Some(session) = self.get_current_session_mut() {
+            session.generation = generation;
+        }
+    }
+
+    /// Set the summary of older turns of the current session
+    pub fn set_context_summary(&mut self, summary: Option<ContextSummary>) {
+        if let Some(session) = self.get_current_session_mut() {
+            session.context_summary = summary;
+        }
+    }
+
     /// Switch to a different session
     pub fn switch_session(&mut self, session_id: &str) {
         self.current_session_id = Some(session_id.to_string());
@@ -167,6 +362,9 @@ impl ChatHistoryData {
 
     /// Delete a session
     pub fn delete_session(&mut self, session_id: &str) {
+        if let Some(session) = self.sessions.iter().find(|s| s.id == session_id) {
+            session.messages.iter().flat_map(|m| &m.attachments).for_each(Attachment::remove);
+        }
         self.sessions.retain(|s| s.id != session_id);
         if self.current_session_id.as_ref().map(|s| s.as_str()) == Some(session_id) {
             self.current_session_id = self.sessions.first().map(|s| s.id.clone());
@@ -176,8 +374,12 @@ impl ChatHistoryData {
     /// Clear messages in current session
     pub fn clear_current_session(&mut self) {
         if let Some(session) = self.get_current_session_mut() {
+            session.messages.iter().flat_map(|m| &m.attachments).for_each(Attachment::remove);
             session.messages.clear();
             session.title = "New Chat".to_string();
+            session.usage = Usage::default();
+            session.cost = 0.0;
+            session.context_summary = None;
         }
     }
 
@@ -188,3 +390,22 @@ impl ChatHistoryData {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiFormat;
+    use crate::services::replay::{self, Fixture};
+
+    #[tokio::test]
+    async fn test_attachment_stored_in_config_dir() {
+        let _guard = replay::testing::use_mock_provider(ApiFormat::Anthropic, Fixture::default()).await;
+
+        let attachment = Attachment::store("notes", "text/plain", b"hello").unwrap();
+        assert!(!attachment.id.contains('.'));
+        let path = attachment.path().unwrap();
+        assert!(path.starts_with(AppConfig::get_config_dir()));
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        attachment.remove();
+    }
+}
@@ -0,0 +1,151 @@
+//! Message attachments (images and documents)
+//! 消息附件 - 剪贴板图片、文件选择与缩略图
+
+use arboard::Clipboard;
+use dioxus::prelude::*;
+use crate::chat_history::Attachment;
+
+/// Store the clipboard image (if any) as a PNG attachment
+///
+/// Returns `Ok(None)` when the clipboard holds no image.
+pub fn paste_clipboard_image() -> Result<Option<Attachment>, String> {
+    let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
+    let image = match clipboard.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let png = encode_png(image.width as u32, image.height as u32, &image.bytes).map_err(|e| e.to_string())?;
+    let name = format!("clipboard-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    Attachment::store(&name, "image/png", &png)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Encode raw RGBA8 pixels as PNG
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>, png::EncodingError> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)?;
+    Ok(png)
+}
+
+/// Store a picked file as an attachment (unsupported types are rejected)
+fn store_file(name: &str, bytes: &[u8]) -> Result<Attachment, String> {
+    let media_type = Attachment::media_type_for(name)
+        .ok_or_else(|| format!("Unsupported file type: {}", name))?;
+    Attachment::store(name, media_type, bytes).map_err(|e| e.to_string())
+}
+
+/// Buttons for attaching files and pasting clipboard images, plus the pending attachments
+#[component]
+pub fn AttachmentPicker(
+    mut attachments: Signal<Vec<Attachment>>,
+    #[props(default)] disabled: bool,
+) -> Element {
+    let mut error = use_signal(|| None::<String>);
+
+    rsx! {
+        div {
+            class: "flex items-center gap-2 flex-wrap",
+            label {
+                class: "px-2 py-1 text-sm rounded-lg bg-bg-surface hover:bg-bg-secondary text-text-secondary hover:text-text-primary transition-colors cursor-pointer",
+                title: "Attach images or documents",
+                "📎"
+                input {
+                    r#type: "file",
+                    class: "hidden",
+                    multiple: true,
+                    disabled,
+                    accept: "image/png,image/jpeg,image/gif,image/webp,application/pdf,.txt,.md,.csv,.json",
+                    onchange: move |e| {
+                        let files = e.files();
+                        spawn(async move {
+                            for file in files {
+                                let name = file.name();
+                                let result = match file.read_bytes().await {
+                                    Ok(bytes) => store_file(&name, &bytes),
+                                    Err(e) => Err(format!("Failed to read {}: {}", name, e)),
+                                };
+                                match result {
+                                    Ok(attachment) => attachments.write().push(attachment),
+                                    Err(e) => error.set(Some(e)),
+                                }
+                            }
+                        });
+                    },
+                }
+            }
+            button {
+                class: "px-2 py-1 text-sm rounded-lg bg-bg-surface hover:bg-bg-secondary text-text-secondary hover:text-text-primary transition-colors",
+                title: "Paste image from clipboard",
+                disabled,
+                onclick: move |_| match paste_clipboard_image() {
+                    Ok(Some(attachment)) => {
+                        error.set(None);
+                        attachments.write().push(attachment);
+                    }
+                    Ok(None) => error.set(Some("No image in clipboard".to_string())),
+                    Err(e) => error.set(Some(e)),
+                },
+                "🖼"
+            }
+            for (index, attachment) in attachments().into_iter().enumerate() {
+                AttachmentThumbnail {
+                    key: "{attachment.id}",
+                    attachment: attachment.clone(),
+                    on_remove: move |_| {
+                        let removed = attachments.write().remove(index);
+                        removed.remove();
+                    },
+                }
+            }
+            if let Some(e) = error() {
+                span { class: "text-xs text-error", "{e}" }
+            }
+        }
+    }
+}
+
+/// Image thumbnail (or file chip for documents), with an optional remove button
+#[component]
+pub fn AttachmentThumbnail(
+    attachment: Attachment,
+    #[props(default)] on_remove: Option<EventHandler<()>>,
+) -> Element {
+    // Read once per attachment (list items are keyed by id)
+    let src = use_hook(|| if attachment.is_image() { attachment.data_url().ok() } else { None });
+    let title = match &attachment.resource {
+        Some(source) => format!("{}\n{}: {}", attachment.name, source.server, source.uri),
+        None => attachment.name.clone(),
+    };
+
+    rsx! {
+        div {
+            class: "relative group",
+            title: "{title}",
+            if let Some(src) = src {
+                img {
+                    class: "h-16 max-w-32 object-cover rounded-lg border border-border",
+                    src,
+                }
+            } else {
+                div {
+                    class: "h-16 px-3 flex items-center gap-1 rounded-lg border border-border bg-bg-surface text-xs text-text-secondary max-w-40",
+                    span { if attachment.resource.is_some() { "🔗" } else { "📄" } }
+                    span { class: "truncate", "{attachment.name}" }
+                }
+            }
+            if let Some(on_remove) = on_remove {
+                button {
+                    class: "absolute -top-1.5 -right-1.5 w-5 h-5 rounded-full bg-error text-white text-xs hidden group-hover:flex items-center justify-center",
+                    onclick: move |_| on_remove.call(()),
+                    "✕"
+                }
+            }
+        }
+    }
+}
@@ -2,9 +2,10 @@
 //! 会话管理处理器
 
 use dioxus::prelude::*;
-use crate::config::AppConfig;
-use crate::chat_history::{ChatHistoryData, ChatMessage as HistoryMessage};
+use crate::config::{AppConfig, GenerationParams};
+use crate::chat_history::{Attachment, ChatHistoryData, ChatMessage as HistoryMessage};
 use super::message_list::ChatMessage;
+use super::OutgoingMessage;
 
 /// Create new chat handler
 ///
@@ -90,6 +91,28 @@ pub fn use_delete_session_handler(
     }
 }
 
+/// Create handler that sets the current session's generation parameter overrides
+///
+/// Creates a session first if there is none, so the overrides aren't lost.
+pub fn use_update_generation_handler(
+    mut chat_history: Signal<ChatHistoryData>,
+    active_provider_id: Signal<String>,
+) -> impl FnMut(GenerationParams) + Clone {
+    move |generation: GenerationParams| {
+        let mut history = chat_history.write();
+        if history.get_current_session().is_none() {
+            history.create_new_session(&active_provider_id());
+        }
+        history.set_generation_overrides(generation);
+        let _ = history.save();
+
+        // Trigger UI update by cloning and dropping the borrow first
+        let history_clone = (*history).clone();
+        drop(history);
+        chat_history.set(history_clone);
+    }
+}
+
 /// Create switch provider handler
 pub fn use_switch_provider_handler(
     mut active_provider_id: Signal<String>,
@@ -105,14 +128,15 @@ pub fn use_switch_provider_handler(
 /// Create send message handler
 pub fn use_send_message_handler(
     mut input_text: Signal<String>,
-    tx: Coroutine<String>,
+    mut attachments: Signal<Vec<Attachment>>,
+    tx: Coroutine<OutgoingMessage>,
 ) -> impl FnMut() + Clone {
     move || {
         let text = input_text().trim().to_string();
-        if text.is_empty() {
+        if text.is_empty() && attachments().is_empty() {
             return;
         }
         input_text.set(String::new());
-        tx.send(text);
+        tx.send(OutgoingMessage::new(text, attachments.take()));
     }
 }
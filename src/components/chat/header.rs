@@ -1,20 +1,41 @@
 //! Chat header component
-//! 聊天头部组件 - 标题、提供商选择器、MCP 状态
+//! 聊天头部组件 - 标题、提供商选择器、MCP 状态、会话用量与生成参数
 
 use dioxus::prelude::*;
+use super::message_list::{format_cache_hit, format_cost, format_tokens, usage_tooltip};
+use crate::components::generation_params::GenerationParamsFields;
+use crate::components::ui::{PrimaryButton, SecondaryButton};
+use crate::config::GenerationParams;
+use crate::services::{McpServerStatus, Usage};
+use std::collections::BTreeMap;
 
-/// Chat header with title, provider selector, and MCP badges
+/// Chat header with title, provider selector, MCP badges, and session usage
 #[component]
 pub fn ChatHeader(
     current_session_title: String,
     active_provider_id: String,
     enabled_providers: Vec<crate::config::ProviderConfig>,
     enabled_mcp_servers: Vec<crate::config::McpServerConfig>,
+    /// Live status of the pooled MCP servers, by name
+    #[props(default)] mcp_status: BTreeMap<String, McpServerStatus>,
     sidebar_collapsed: bool,
+    /// Running token totals of the current session
+    #[props(default)] session_usage: Usage,
+    /// Running estimated cost of the current session (USD)
+    #[props(default)] session_cost: f64,
+    /// Generation parameter overrides of the current session
+    #[props(default)] generation_overrides: GenerationParams,
+    /// Generation defaults of the active provider (shown as placeholders)
+    #[props(default)] provider_generation: GenerationParams,
+    on_update_generation: EventHandler<GenerationParams>,
     on_toggle_sidebar: EventHandler<MouseEvent>,
     on_new_chat: EventHandler<MouseEvent>,
     on_switch_provider: EventHandler<String>,
 ) -> Element {
+    let mut show_params = use_signal(|| false);
+    let mut draft = use_signal(GenerationParams::default);
+    let has_overrides = !generation_overrides.is_empty();
+
     rsx! {
         div {
             class: "flex items-center justify-between px-4 py-3 border-b border-border relative z-10 shadow-custom",
@@ -66,9 +87,9 @@ pub fn ChatHeader(
                                         "MCP:"
                                     }
                                     for server in enabled_mcp_servers.iter() {
-                                        span {
-                                            class: "text-xs bg-success/10 text-success border border-success/30 rounded px-1.5 py-0.5 font-mono",
-                                            {server.name.clone()}
+                                        McpBadge {
+                                            name: server.name.clone(),
+                                            status: mcp_status.get(&server.name).cloned(),
                                         }
                                     }
                                 }
@@ -78,12 +99,102 @@ pub fn ChatHeader(
                 }
             }
 
-            // Right side - New Chat button
-            button {
-                class: "w-8 h-8 flex items-center justify-center rounded-lg bg-bg-surface hover:bg-bg-secondary text-text-secondary hover:text-text-primary transition-colors",
-                onclick: on_new_chat,
-                "＋"
+            // Right side - Session usage, generation parameters and New Chat button
+            div {
+                class: "flex items-center gap-3",
+                if !session_usage.is_empty() {
+                    div {
+                        class: "text-xs text-text-muted font-mono",
+                        title: usage_tooltip(&session_usage),
+                        {format!("↑{} ↓{}", format_tokens(session_usage.total_input_tokens()), format_tokens(session_usage.output_tokens))}
+                        if let Some(cached) = format_cache_hit(&session_usage) {
+                            span { class: "ml-1 text-success", "{cached}" }
+                        }
+                        if session_cost > 0.0 {
+                            span { class: "ml-2 text-text-secondary", {format_cost(session_cost)} }
+                        }
+                    }
+                }
+                // Generation parameters popover
+                div {
+                    class: "relative",
+                    button {
+                        class: if has_overrides {
+                            "w-8 h-8 flex items-center justify-center rounded-lg bg-primary/10 text-primary transition-colors"
+                        } else {
+                            "w-8 h-8 flex items-center justify-center rounded-lg bg-bg-surface hover:bg-bg-secondary text-text-secondary hover:text-text-primary transition-colors"
+                        },
+                        title: "Generation parameters for this chat",
+                        onclick: move |_| {
+                            if !show_params() {
+                                draft.set(generation_overrides.clone());
+                            }
+                            show_params.set(!show_params());
+                        },
+                        "⚙"
+                    }
+                    if show_params() {
+                        div {
+                            class: "absolute right-0 top-10 w-80 p-4 space-y-4 bg-bg-surface border border-border rounded-lg shadow-custom z-20",
+                            p {
+                                class: "text-sm font-medium text-text-primary",
+                                "Session parameters"
+                            }
+                            p {
+                                class: "text-xs text-text-muted",
+                                "Empty fields use the provider defaults."
+                            }
+                            GenerationParamsFields {
+                                params: draft,
+                                defaults: provider_generation.clone(),
+                            }
+                            div {
+                                class: "flex justify-end gap-2",
+                                SecondaryButton {
+                                    onclick: move |_| {
+                                        on_update_generation.call(GenerationParams::default());
+                                        show_params.set(false);
+                                    },
+                                    "Reset"
+                                }
+                                PrimaryButton {
+                                    onclick: move |_| {
+                                        on_update_generation.call(draft());
+                                        show_params.set(false);
+                                    },
+                                    "Apply"
+                                }
+                            }
+                        }
+                    }
+                }
+                button {
+                    class: "w-8 h-8 flex items-center justify-center rounded-lg bg-bg-surface hover:bg-bg-secondary text-text-secondary hover:text-text-primary transition-colors",
+                    onclick: on_new_chat,
+                    "＋"
+                }
             }
         }
     }
 }
+
+/// MCP server badge colored by its live status (None = not started yet)
+#[component]
+fn McpBadge(name: String, status: Option<McpServerStatus>) -> Element {
+    let (color, title) = match status {
+        Some(McpServerStatus::Running { tools }) => ("bg-success/10 text-success border-success/30", format!("Running · {} tools", tools)),
+        Some(McpServerStatus::Restarting { error, retry_in }) => (
+            "bg-error/10 text-error border-error/30",
+            format!("{} · restarting in {}s", error, retry_in.as_secs()),
+        ),
+        Some(McpServerStatus::Starting) | None => ("bg-warning/10 text-warning border-warning/30", "Starting…".to_string()),
+    };
+
+    rsx! {
+        span {
+            class: "text-xs {color} border rounded px-1.5 py-0.5 font-mono",
+            title: "{title}",
+            {name}
+        }
+    }
+}
@@ -3,12 +3,18 @@
 
 use dioxus::prelude::*;
 use dioxus::document;
-use crate::services::{chat_with_tools, AgentStep};
-use crate::chat_history::{ChatHistoryData, ChatMessage as HistoryMessage};
+use crate::config::{AppConfig, ContextPolicy, GenerationParams};
+use crate::services::{chat_with_tools, mcp_manager, AgentStep, ContentBlock, McpServerStatus, Usage};
+use crate::services::ai_client::MessageContent;
+use crate::services::context::{self, ContextPlan, MessageCost};
+use crate::chat_history::{ChatHistoryData, ChatMessage as HistoryMessage, ContextSummary};
 use super::message_list::ChatMessage;
+use super::{InsertedMessage, OutgoingMessage};
+use std::collections::BTreeMap;
 use std::time::SystemTime;
 use futures_util::stream::StreamExt;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 impl From<HistoryMessage> for ChatMessage {
     fn from(msg: HistoryMessage) -> Self {
@@ -17,6 +23,12 @@ impl From<HistoryMessage> for ChatMessage {
             role: msg.role,
             content: msg.content,
             timestamp: msg.timestamp,
+            cancelled: msg.cancelled,
+            truncated: msg.truncated,
+            provider_id: msg.provider_id,
+            usage: msg.usage,
+            cost: msg.cost,
+            attachments: msg.attachments,
         }
     }
 }
@@ -28,12 +40,21 @@ impl From<ChatMessage> for HistoryMessage {
             role: msg.role,
             content: msg.content,
             timestamp: msg.timestamp,
+            cancelled: msg.cancelled,
+            truncated: msg.truncated,
+            provider_id: msg.provider_id,
+            usage: msg.usage,
+            cost: msg.cost,
+            attachments: msg.attachments,
         }
     }
 }
 
 /// Hook for the chat coroutine that handles AI calls and streaming responses
 ///
+/// `active_run` holds the cancellation token of the in-flight run (None when idle);
+/// cancelling it stops the run and saves the partial transcript marked as cancelled.
+///
 /// # IMPORTANT: Agent Step Detection
 /// This hook contains agent step detection logic that must stay in sync with
 /// the placeholder detection in the message sync effect. If you modify the
@@ -41,47 +62,74 @@ impl From<ChatMessage> for HistoryMessage {
 pub fn use_chat_coroutine(
     messages: Signal<Vec<ChatMessage>>,
     chat_history: Signal<ChatHistoryData>,
-) -> Coroutine<String> {
-    use_coroutine(move |mut rx: UnboundedReceiver<String>| {
+    active_run: Signal<Option<CancellationToken>>,
+) -> Coroutine<OutgoingMessage> {
+    use_coroutine(move |mut rx: UnboundedReceiver<OutgoingMessage>| {
         let mut messages = messages.clone();
         let mut chat_history = chat_history.clone();
+        let mut active_run = active_run.clone();
         let mut msg_counter: u64 = 0;
         async move {
-            while let Some(text) = rx.next().await {
-                let text: String = text;
-
-                // Add user message
-                let now_millis = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
-                let now_secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-                msg_counter += 1;
-                let user_msg_id = format!("msg-{}-{}", now_millis, msg_counter);
-                let user_msg = ChatMessage {
-                    id: user_msg_id.clone(),
-                    role: "user".to_string(),
-                    content: text.clone(),
-                    timestamp: now_secs,
+            while let Some(outgoing) = rx.next().await {
+                let OutgoingMessage { text, attachments, continue_from, inserted } = outgoing;
+
+                // Cut-off answer to continue (instead of a new user message)
+                let continued = match continue_from {
+                    Some(id) => match messages.read().iter().find(|m| m.id == id && m.truncated) {
+                        Some(message) => Some(message.clone()),
+                        // Already continued, or no longer in this session
+                        None => continue,
+                    },
+                    None => None,
                 };
-                messages.push(user_msg.clone());
 
-                // Update history
-                chat_history.write().add_message(HistoryMessage {
-                    id: user_msg_id.clone(),
-                    role: "user".to_string(),
-                    content: text.clone(),
-                    timestamp: now_secs,
-                });
-                let history_clone = { (*chat_history.read()).clone() };
-                let _ = chat_history.read().save();
-                // Trigger UI update for session list
-                chat_history.set(history_clone);
-
-                // Build message history for API (exclude system errors)
-                let api_messages: Vec<crate::services::ChatMessage> = messages.read().iter().filter(|m| m.role != "system").map(|m| {
-                    crate::services::ChatMessage {
-                        role: m.role.clone(),
-                        content: m.content.clone(),
+                if continued.is_none() {
+                    // Add the user message, or the messages of a prompt
+                    let added = if inserted.is_empty() {
+                        vec![InsertedMessage { role: "user".to_string(), content: text, attachments }]
+                    } else {
+                        inserted
+                    };
+                    // Nothing to answer after an assistant message
+                    let answer = added.last().is_some_and(|m| m.role == "user");
+                    let now_secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+                    for InsertedMessage { role, content, attachments } in added {
+                        let now_millis = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
+                        msg_counter += 1;
+                        let msg = ChatMessage {
+                            id: format!("msg-{}-{}", now_millis, msg_counter),
+                            role,
+                            content,
+                            timestamp: now_secs,
+                            cancelled: false,
+                            truncated: false,
+                            provider_id: None,
+                            usage: None,
+                            cost: None,
+                            attachments,
+                        };
+                        messages.push(msg.clone());
+
+                        // Update history
+                        chat_history.write().add_message(msg.into());
+                    }
+                    let history_clone = { (*chat_history.read()).clone() };
+                    let _ = chat_history.read().save();
+                    // Trigger UI update for session list
+                    chat_history.set(history_clone);
+                    if !answer {
+                        continue;
                     }
-                }).collect();
+                }
+
+                // Message history for the API (exclude system errors)
+                let mut sent_messages: Vec<ChatMessage> = messages.read().iter().filter(|m| m.role != "system").cloned().collect();
+                if let Some(continued) = &continued {
+                    // The cut-off answer goes last so the model picks up where it stopped
+                    if let Some(pos) = sent_messages.iter().position(|m| m.id == continued.id) {
+                        sent_messages.truncate(pos + 1);
+                    }
+                }
 
                 // Create channel for streaming AgentStep updates
                 let (step_tx, mut step_rx) = mpsc::unbounded_channel::<AgentStep>();
@@ -92,12 +140,21 @@ pub fn use_chat_coroutine(
                 msg_counter += 1;
                 let assistant_msg_id = format!("msg-{}-{}", now_millis, msg_counter);
 
+                // Steps of a continued answer go right before that answer
+                let steps_before = continued.as_ref().map(|m| m.id.clone());
+
                 // Add initial placeholder message
-                messages.push(ChatMessage {
+                insert_message_before(&mut messages, steps_before.as_deref(), ChatMessage {
                     id: assistant_msg_id.clone(),
                     role: "assistant".to_string(),
                     content: "思考中...".to_string(),
                     timestamp: now_secs,
+                    cancelled: false,
+                    truncated: false,
+                    provider_id: None,
+                    usage: None,
+                    cost: None,
+                    attachments: Vec::new(),
                 });
 
                 // Track intermediate steps and final answer
@@ -105,13 +162,58 @@ pub fn use_chat_coroutine(
                 // triggering the use_effect sync that overwrites our message updates
                 let mut intermediate_steps = Vec::new();
                 let mut final_response = String::new();
+                // Answer bubble (id, timestamp) - created on first streamed delta or final answer
+                // (a continued answer streams into its existing bubble, after the cut-off text)
+                let mut answer_msg: Option<(String, u64)> = continued.as_ref().map(|m| (m.id.clone(), m.timestamp));
+                let answer_prefix = continued.as_ref().map(|m| m.content.trim_end().to_string()).unwrap_or_default();
+                let mut streamed_text = answer_prefix.clone();
+                // The answer stopped at max_tokens
+                let mut truncated = false;
+                if continued.is_some() {
+                    intermediate_steps.push("- ▶ 继续生成被截断的回答".to_string());
+                }
+                // Provider that answered (may be a fallback provider)
+                let mut answer_provider: Option<String> = None;
+                // Token usage and cost summed over every AI request of this run
+                let mut run_usage: Option<Usage> = None;
+                let mut run_cost: Option<f64> = None;
+                // Extended thinking of the current AI request, streamed into one Thinking step
+                let mut thinking_text = String::new();
+                let mut thinking_step: Option<usize> = None;
+                // Error category and message of a failed run
+                let mut failure: Option<(String, String)> = None;
+                // Providers tried by the current AI request when it fell back
+                let mut fallback_chain: Vec<String> = Vec::new();
 
                 eprintln!("=== STARTING AGENT TASK ===");
-                let api_messages_clone = api_messages.clone();
+                // Per-session generation parameter overrides
+                let generation = chat_history.read().get_current_session()
+                    .map(|s| s.generation.clone())
+                    .unwrap_or_default();
+
+                // A continued answer keeps the tokens and cost of its earlier part
+                let answer_totals = |usage: Option<Usage>, cost: Option<f64>| {
+                    let earlier = continued.as_ref();
+                    let usage = earlier.and_then(|m| m.usage).into_iter().chain(usage).reduce(|mut total, usage| {
+                        total.add(&usage);
+                        total
+                    });
+                    let cost = earlier.and_then(|m| m.cost).into_iter().chain(cost).reduce(|a, b| a + b);
+                    (usage, cost)
+                };
+
+                // Cancellation token for the Stop button
+                let cancel = CancellationToken::new();
+                active_run.set(Some(cancel.clone()));
+
+                // Fit the history into the context window (older turns dropped or summarized)
+                let (api_messages_clone, context_step) = fit_context(&sent_messages, &mut chat_history, &generation, &cancel).await;
+                intermediate_steps.extend(context_step);
 
                 // Spawn agent in background (but process steps in this coroutine context)
+                let agent_cancel = cancel.clone();
                 tokio::spawn(async move {
-                    let _ = chat_with_tools(api_messages_clone, step_tx).await;
+                    let _ = chat_with_tools(api_messages_clone, generation, step_tx, agent_cancel).await;
                 });
 
                 // Process steps as they arrive
@@ -122,6 +224,12 @@ pub fn use_chat_coroutine(
                     step_count += 1;
                     eprintln!("=== PROCESSING STEP {} ===", step_count);
 
+                    // Any other progress ends the current thinking block
+                    if !matches!(step, AgentStep::ThinkingDelta(_) | AgentStep::Provider { .. } | AgentStep::Usage { .. }) {
+                        thinking_text.clear();
+                        thinking_step = None;
+                    }
+
                     // ALERT: STEP FORMAT - If you modify these formats, update the placeholder detection
                     // in the message sync effect to match! Otherwise steps will flicker/disappear.
                     match step {
@@ -129,11 +237,17 @@ pub fn use_chat_coroutine(
                             intermediate_steps.push(format!("- 🔌 {}", msg));
                         }
                         AgentStep::Thinking { short, content } => {
-                            // Use collapsible details if there's content, otherwise just show short text
-                            if let Some(thought_content) = content {
-                                intermediate_steps.push(format!("- 🤔 {}\n<details><summary>查看思考内容</summary>\n{}\n</details>", short, thought_content));
-                            } else {
-                                intermediate_steps.push(format!("- 🤔 {}", short));
+                            intermediate_steps.push(format_thinking_step(&short, content.as_deref()));
+                        }
+                        AgentStep::ThinkingDelta(text) => {
+                            thinking_text.push_str(&text);
+                            let step = format_thinking_step("深度思考", Some(&thinking_text));
+                            match thinking_step {
+                                Some(index) => intermediate_steps[index] = step,
+                                None => {
+                                    thinking_step = Some(intermediate_steps.len());
+                                    intermediate_steps.push(step);
+                                }
                             }
                         }
                         AgentStep::ToolCall { name, .. } => {
@@ -142,10 +256,80 @@ pub fn use_chat_coroutine(
                         AgentStep::ToolResult { name, .. } => {
                             intermediate_steps.push(format!("- ✅ 完成: {}", name));
                         }
+                        AgentStep::Retrying { attempt, max_retries, delay_secs, reason } => {
+                            intermediate_steps.push(format!("- 🔁 {}，{}s 后重试 ({}/{})", reason, delay_secs, attempt, max_retries));
+                        }
+                        AgentStep::Fallback { from, to, reason } => {
+                            intermediate_steps.push(format!("- 🔀 {} 不可用 ({})，切换到 {}", from, reason, to));
+                            if fallback_chain.is_empty() {
+                                fallback_chain.push(from);
+                            }
+                            fallback_chain.push(to);
+                        }
+                        AgentStep::Provider { id, .. } => {
+                            // A provider answered; the chain only matters if the request fails
+                            fallback_chain.clear();
+                            answer_provider = Some(id);
+                            continue;
+                        }
+                        AgentStep::Truncated => {
+                            // Shown with the final answer; keep the streamed answer bubble
+                            intermediate_steps.push("- ✂️ 回答达到 max_tokens 上限，已被截断".to_string());
+                            truncated = true;
+                            continue;
+                        }
+                        AgentStep::Usage { usage, cost } => {
+                            run_usage.get_or_insert_with(Usage::default).add(&usage);
+                            if let Some(cost) = cost {
+                                *run_cost.get_or_insert(0.0) += cost;
+                            }
+                            continue;
+                        }
+                        AgentStep::Delta(text) => {
+                            // Stream text into the answer bubble (steps bubble stays as placeholder)
+                            streamed_text.push_str(&text);
+                            let (id, timestamp) = answer_msg.get_or_insert_with(|| {
+                                msg_counter += 1;
+                                new_message_id(msg_counter)
+                            }).clone();
+                            upsert_message(&mut messages, ChatMessage {
+                                id,
+                                role: "assistant".to_string(),
+                                content: streamed_text.clone(),
+                                timestamp,
+                                cancelled: false,
+                                truncated: false,
+                                provider_id: answer_provider.clone(),
+                                usage: None,
+                                cost: None,
+                                attachments: Vec::new(),
+                            });
+                            continue;
+                        }
                         AgentStep::Final(text) => {
-                            final_response = text.clone();
+                            final_response = format!("{}{}", answer_prefix, text);
                             // NOTE: Don't update chat_history yet - do it after the loop
                         }
+                        AgentStep::Error { category, message } => {
+                            // Saved with the error message after the loop
+                            intermediate_steps.push(format!("- ❌ 出错: {}", category));
+                            failure = Some((category, message));
+                        }
+                    }
+
+                    // Text streamed before a tool call is shown in the steps (as thinking content),
+                    // so drop the partial answer bubble once another step arrives
+                    // (a continued answer goes back to its cut-off text)
+                    if final_response.is_empty() {
+                        if let Some(original) = &continued {
+                            if streamed_text != answer_prefix {
+                                streamed_text = answer_prefix.clone();
+                                upsert_message(&mut messages, original.clone());
+                            }
+                        } else if let Some((id, _)) = answer_msg.take() {
+                            streamed_text.clear();
+                            messages.write().retain(|m| m.id != id);
+                        }
                     }
 
                     // When final response arrives, create a separate message bubble for it
@@ -160,32 +344,45 @@ pub fn use_chat_coroutine(
                             messages.set(updated);
                         }
 
-                        // Then, create a new message for the final response
-                        let now_millis_final = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
-                        let now_secs_final = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-                        msg_counter += 1;
-                        let final_msg_id = format!("msg-{}-{}", now_millis_final, msg_counter);
+                        // Then, create (or finish the streamed) message for the final response
+                        let (final_msg_id, now_secs_final) = answer_msg.take().unwrap_or_else(|| {
+                            msg_counter += 1;
+                            new_message_id(msg_counter)
+                        });
 
-                        messages.push(ChatMessage {
+                        let (answer_usage, answer_cost) = answer_totals(run_usage, run_cost);
+                        let final_msg = ChatMessage {
                             id: final_msg_id.clone(),
                             role: "assistant".to_string(),
                             content: final_response.clone(),
                             timestamp: now_secs_final,
-                        });
+                            cancelled: false,
+                            truncated,
+                            provider_id: answer_provider.clone(),
+                            usage: answer_usage,
+                            cost: answer_cost,
+                            attachments: Vec::new(),
+                        };
+                        upsert_message(&mut messages, final_msg.clone());
 
                         // Save both messages to history
-                        chat_history.write().add_message(HistoryMessage {
+                        chat_history.write().add_message_before(HistoryMessage {
                             id: assistant_msg_id.clone(),
                             role: "assistant".to_string(),
                             content: intermediate_steps.join("\n"),
                             timestamp: now_secs,
-                        });
-                        chat_history.write().add_message(HistoryMessage {
-                            id: final_msg_id.clone(),
-                            role: "assistant".to_string(),
-                            content: final_response.clone(),
-                            timestamp: now_secs_final,
-                        });
+                            cancelled: false,
+                            truncated: false,
+                            provider_id: answer_provider.clone(),
+                            usage: None,
+                            cost: None,
+                            attachments: Vec::new(),
+                        }, steps_before.as_deref());
+                        if continued.is_some() {
+                            chat_history.write().update_message(final_msg.into(), run_usage, run_cost);
+                        } else {
+                            chat_history.write().add_message(final_msg.into());
+                        }
                         let history_clone = { (*chat_history.read()).clone() };
                         let _ = chat_history.read().save();
                         chat_history.set(history_clone);
@@ -208,20 +405,272 @@ pub fn use_chat_coroutine(
                     } else {
                         eprintln!("=== WARNING: MESSAGE NOT FOUND IN LIST ===");
                         drop(current_msgs);
-                        messages.push(ChatMessage {
+                        insert_message_before(&mut messages, steps_before.as_deref(), ChatMessage {
                             id: assistant_msg_id.clone(),
                             role: "assistant".to_string(),
                             content: display_content,
                             timestamp: now_secs,
+                            cancelled: false,
+                            truncated: false,
+                            provider_id: answer_provider.clone(),
+                            usage: None,
+                            cost: None,
+                            attachments: Vec::new(),
                         });
                     }
                 }
                 eprintln!("=== STEP LOOP DONE ===");
+
+                // Stopped or failed before the final answer: keep what was produced so far
+                // (a failed run has already dropped its partial answer)
+                let stopped = cancel.is_cancelled();
+                if final_response.is_empty() && (stopped || failure.is_some()) {
+                    if stopped {
+                        intermediate_steps.push("- ⏹ 已停止".to_string());
+                    }
+                    // Usage goes on the partial answer if there is one, otherwise on the steps
+                    let (steps_usage, steps_cost) = if stopped && answer_msg.is_some() { (None, None) } else { (run_usage, run_cost) };
+                    let steps_msg = ChatMessage {
+                        id: assistant_msg_id.clone(),
+                        role: "assistant".to_string(),
+                        content: intermediate_steps.join("\n"),
+                        timestamp: now_secs,
+                        cancelled: stopped,
+                        truncated: false,
+                        provider_id: answer_provider.clone(),
+                        usage: steps_usage,
+                        cost: steps_cost,
+                        attachments: Vec::new(),
+                    };
+                    upsert_message(&mut messages, steps_msg.clone());
+                    chat_history.write().add_message_before(steps_msg.into(), steps_before.as_deref());
+
+                    if let Some((id, timestamp)) = answer_msg.take().filter(|_| stopped) {
+                        let (answer_usage, answer_cost) = answer_totals(run_usage, run_cost);
+                        let partial_msg = ChatMessage {
+                            id,
+                            role: "assistant".to_string(),
+                            content: streamed_text.clone(),
+                            timestamp,
+                            cancelled: true,
+                            truncated: false,
+                            provider_id: answer_provider.clone(),
+                            usage: answer_usage,
+                            cost: answer_cost,
+                            attachments: Vec::new(),
+                        };
+                        upsert_message(&mut messages, partial_msg.clone());
+                        if continued.is_some() {
+                            chat_history.write().update_message(partial_msg.into(), run_usage, run_cost);
+                        } else {
+                            chat_history.write().add_message(partial_msg.into());
+                        }
+                    }
+
+                    // Shown as an error notice; system messages are not sent to the model
+                    if let Some((category, message)) = failure.filter(|_| !stopped) {
+                        let mut content = format!("请求失败（{}）：{}", category, message);
+                        // Every provider of the fallback chain failed; their reasons are in the steps
+                        if !fallback_chain.is_empty() {
+                            content.push_str(&format!("。已依次尝试 {}，均不可用", fallback_chain.join(" → ")));
+                        }
+                        msg_counter += 1;
+                        let (id, timestamp) = new_message_id(msg_counter);
+                        let error_msg = ChatMessage {
+                            id,
+                            role: "system".to_string(),
+                            content,
+                            timestamp,
+                            cancelled: false,
+                            truncated: false,
+                            provider_id: answer_provider.clone(),
+                            usage: None,
+                            cost: None,
+                            attachments: Vec::new(),
+                        };
+                        upsert_message(&mut messages, error_msg.clone());
+                        chat_history.write().add_message(error_msg.into());
+                    }
+
+                    let history_clone = { (*chat_history.read()).clone() };
+                    let _ = chat_history.read().save();
+                    chat_history.set(history_clone);
+                }
+                active_run.set(None);
             }
         }
     })
 }
 
+/// Which messages of a session are sent to the model
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionContext {
+    pub plan: ContextPlan,
+    pub policy: ContextPolicy,
+    /// Ids of the messages outside the context (dropped or summarized)
+    pub excluded_ids: Vec<String>,
+}
+
+/// Fit a session's messages into the active provider's context budget
+///
+/// Returns None when no provider is usable.
+pub fn session_context(
+    messages: &[ChatMessage],
+    summary: Option<&ContextSummary>,
+    generation: &GenerationParams,
+) -> Option<SessionContext> {
+    let config = AppConfig::load().ok()?;
+    let provider = config.get_usable_provider()?;
+    let settings = &config.ai.context;
+
+    let sent: Vec<&ChatMessage> = messages.iter().filter(|m| m.role != "system").collect();
+    let costs: Vec<MessageCost> = sent.iter().map(|m| message_cost(m)).collect();
+    let reserved = match (settings.policy, summary) {
+        (ContextPolicy::SummarizeOlder, Some(summary)) => context::estimate_text_tokens(&summary.text),
+        (ContextPolicy::SummarizeOlder, None) => context::SUMMARY_MAX_TOKENS,
+        _ => 0,
+    };
+    let budget = context::context_budget(provider, settings, generation);
+    let plan = context::plan_context(&costs, budget, settings, reserved);
+
+    Some(SessionContext {
+        plan,
+        policy: settings.policy,
+        excluded_ids: sent[plan.excluded()].iter().map(|m| m.id.clone()).collect(),
+    })
+}
+
+/// Estimated token cost of a message (attachment sizes come from the stored files)
+fn message_cost(message: &ChatMessage) -> MessageCost {
+    let attachments = message.attachments.iter().map(|attachment| {
+        let size = attachment.path().ok()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map_or(0, |metadata| metadata.len());
+        (attachment.media_type.as_str(), size)
+    });
+    MessageCost::new(&message.role, &message.content, attachments)
+}
+
+/// Messages to send for this turn, fitted into the context window, plus a step describing the trimming
+///
+/// With the summarize policy, turns that no longer fit are summarized first; the
+/// summary is stored on the session and extended as more turns drop out. If
+/// summarizing fails, the dropped turns are simply left out.
+async fn fit_context(
+    messages: &[ChatMessage],
+    chat_history: &mut Signal<ChatHistoryData>,
+    generation: &GenerationParams,
+    cancel: &CancellationToken,
+) -> (Vec<crate::services::ChatMessage>, Option<String>) {
+    let api_messages: Vec<crate::services::ChatMessage> = messages.iter().map(api_message).collect();
+    let summary = chat_history.read().get_current_session().and_then(|s| s.context_summary.clone());
+    let Some(session) = session_context(messages, summary.as_ref(), generation) else {
+        return (api_messages, None);
+    };
+    if session.plan.is_over_budget() {
+        eprintln!(
+            "[Context] ~{} tokens exceed the budget of {} even after trimming",
+            session.plan.estimated_tokens, session.plan.budget
+        );
+    }
+    let excluded = session.plan.excluded();
+    if excluded.is_empty() {
+        return (api_messages, None);
+    }
+    let dropped_step = format!("- 🗜 上下文已满，较早的 {} 条消息未发送", excluded.len());
+    if session.policy != ContextPolicy::SummarizeOlder {
+        return (context::apply_plan(&api_messages, &session.plan, None), Some(dropped_step));
+    }
+
+    // Messages [0, covered) are already in the stored summary
+    let covered = summary.as_ref()
+        .and_then(|s| messages.iter().position(|m| m.id == s.through_message_id))
+        .map(|index| index + 1);
+    let summary_text = match (summary, covered) {
+        (Some(summary), Some(covered)) if covered >= excluded.end => Some(summary.text),
+        (summary, covered) => {
+            let previous = summary.as_ref().filter(|_| covered.is_some()).map(|s| s.text.as_str());
+            let from = covered.unwrap_or(excluded.start);
+            match context::summarize(previous, &api_messages[from..excluded.end], cancel).await {
+                Ok(text) => {
+                    chat_history.write().set_context_summary(Some(ContextSummary {
+                        text: text.clone(),
+                        through_message_id: messages[excluded.end - 1].id.clone(),
+                    }));
+                    let history_clone = { (*chat_history.read()).clone() };
+                    let _ = chat_history.read().save();
+                    chat_history.set(history_clone);
+                    Some(text)
+                }
+                Err(e) => {
+                    eprintln!("[Context] Failed to summarize older messages: {}", e);
+                    None
+                }
+            }
+        }
+    };
+
+    let step = match summary_text {
+        Some(_) => format!("- 🗜 上下文已满，较早的 {} 条消息已总结", excluded.len()),
+        None => dropped_step,
+    };
+    (context::apply_plan(&api_messages, &session.plan, summary_text.as_deref()), Some(step))
+}
+
+/// Convert a displayed message into an API message (attachments become content blocks)
+fn api_message(message: &ChatMessage) -> crate::services::ChatMessage {
+    let content = if message.attachments.is_empty() {
+        message.content.clone().into()
+    } else {
+        let mut blocks: Vec<ContentBlock> = message.attachments.iter().filter_map(|attachment| {
+            attachment.content_block()
+                .map_err(|e| eprintln!("Skipping attachment {}: {}", attachment.name, e))
+                .ok()
+        }).collect();
+        if !message.content.is_empty() {
+            blocks.push(ContentBlock::Text { text: message.content.clone() });
+        }
+        MessageContent::Blocks(blocks)
+    };
+    crate::services::ChatMessage {
+        role: message.role.clone(),
+        content,
+    }
+}
+
+/// Format a Thinking step, with collapsible details if there's content
+fn format_thinking_step(short: &str, content: Option<&str>) -> String {
+    match content {
+        Some(content) => format!("- 🤔 {}\n<details><summary>查看思考内容</summary>\n{}\n</details>", short, content),
+        None => format!("- 🤔 {}", short),
+    }
+}
+
+/// Generate a new message id and timestamp (secs)
+fn new_message_id(counter: u64) -> (String, u64) {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    (format!("msg-{}-{}", now.as_millis(), counter), now.as_secs())
+}
+
+/// Replace the message with the same id, or append it if it doesn't exist yet
+fn upsert_message(messages: &mut Signal<Vec<ChatMessage>>, message: ChatMessage) {
+    let mut updated = messages.read().clone();
+    if let Some(pos) = updated.iter().position(|m| m.id == message.id) {
+        updated[pos] = message;
+    } else {
+        updated.push(message);
+    }
+    messages.set(updated);
+}
+
+/// Insert a message right before the message with id `before` (pushed when None or not found)
+fn insert_message_before(messages: &mut Signal<Vec<ChatMessage>>, before: Option<&str>, message: ChatMessage) {
+    let mut updated = messages.read().clone();
+    let pos = before.and_then(|id| updated.iter().position(|m| m.id == id)).unwrap_or(updated.len());
+    updated.insert(pos, message);
+    messages.set(updated);
+}
+
 /// Hook for message sync with chat history
 ///
 /// # CRITICAL: Agent Step Detection
@@ -244,13 +693,19 @@ pub fn use_message_sync(
             let current_msgs: Vec<ChatMessage> = session.messages.iter().cloned().map(Into::into).collect();
 
             // Check if messages has an unsaved placeholder (agent in progress)
-            // Format: "- 🔌", "- 🤔", "- 🔧", "- ✅"
+            // Format: "- 🔌", "- 🤔", "- 🔧", "- ✅", "- 🔁", "- 🔀", "- ✂️", "- 🗜", "- ▶"
             let has_unsaved_placeholder = messages().iter().any(|m| {
                 m.content == "思考中..." ||
                 m.content.contains("- 🔌") ||
                 m.content.contains("- 🤔") ||
                 m.content.contains("- 🔧") ||
-                m.content.contains("- ✅")
+                m.content.contains("- ✅") ||
+                m.content.contains("- 🔁") ||
+                m.content.contains("- 🔀") ||
+                m.content.contains("- ✂️") ||
+                m.content.contains("- 🗜") ||
+                m.content.contains("- ▶") ||
+                m.content.contains("- ❌")
             });
 
             // Only sync if there's no in-progress agent
@@ -326,3 +781,23 @@ pub fn use_scroll_state_init(scroll_container_id: String) {
         ).as_str());
     });
 }
+
+/// Hook following the live status of the pooled MCP servers
+///
+/// Starts the enabled servers on first use, so their tools are ready before the first message.
+pub fn use_mcp_status() -> Signal<BTreeMap<String, McpServerStatus>> {
+    let mut status = use_signal(BTreeMap::new);
+    use_future(move || async move {
+        if let Ok(config) = AppConfig::load() {
+            mcp_manager::sync(&config.mcp, &config.network);
+        }
+        let mut rx = mcp_manager::status();
+        loop {
+            status.set(rx.borrow_and_update().clone());
+            if rx.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+    status
+}
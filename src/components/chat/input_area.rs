@@ -2,8 +2,15 @@
 //! 聊天输入区域组件
 
 use dioxus::prelude::*;
+use crate::chat_history::Attachment;
+use crate::components::attachments::AttachmentPicker;
+use crate::components::resource_picker::ResourcePicker;
+use crate::components::slash_commands::{use_slash_commands, SlashCommandMenu};
+use super::OutgoingMessage;
 
-/// Input area with text field and send button
+/// Input area with text field, attachments and send button (Stop button while a run is active)
+///
+/// Typing `/` completes the prompts of the connected MCP servers (`/server:prompt`).
 ///
 /// # IMPORTANT NOTE
 /// textarea must be direct child of flex (no wrapper div) to avoid 6px ghost height issue
@@ -12,11 +19,22 @@ pub fn InputArea(
     input_text: Signal<String>,
     has_api_key: bool,
     on_send: EventHandler<MouseEvent>,
-    tx: Coroutine<String>,
+    attachments: Signal<Vec<Attachment>>,
+    tx: Coroutine<OutgoingMessage>,
+    #[props(default)] is_running: bool,
+    #[props(default)] on_stop: EventHandler<MouseEvent>,
 ) -> Element {
+    let mut commands = use_slash_commands(input_text, move |messages| tx.send(OutgoingMessage::insert(messages)));
+
     rsx! {
         div {
             class: "px-4 py-3 border-t border-border relative z-10 shadow-custom",
+            SlashCommandMenu { commands, class: "absolute bottom-full left-4 right-4 mb-2" }
+            div {
+                class: "mb-2 flex items-start gap-2",
+                ResourcePicker { attachments, disabled: !has_api_key || is_running }
+                AttachmentPicker { attachments, disabled: !has_api_key || is_running }
+            }
             div {
                 class: "flex gap-2",
 
@@ -26,29 +44,42 @@ pub fn InputArea(
                     placeholder: if !has_api_key {
                         "Configure API key first..."
                     } else {
-                        "Type your message..."
+                        "Type your message, or / for MCP prompts..."
                     },
                     value: input_text(),
                     disabled: !has_api_key,
                     oninput: move |e| input_text.set(e.value()),
                     onkeydown: move |e| {
-                        if e.key() == Key::Enter && has_api_key {
+                        if commands.handle_key(&e.key()) {
+                            e.prevent_default();
+                            return;
+                        }
+                        if e.key() == Key::Enter && has_api_key && !is_running {
                             e.prevent_default();
                             let text = input_text().trim().to_string();
-                            if !text.is_empty() {
+                            if !text.is_empty() || !attachments().is_empty() {
                                 input_text.set(String::new());
-                                tx.send(text);
+                                tx.send(OutgoingMessage::new(text, attachments.take()));
                             }
                         }
                     },
                 }
 
-                button {
-                    class: "px-4 py-2 bg-primary text-white rounded-lg hover:bg-primary/90 transition-all disabled:opacity-50 disabled:cursor-not-allowed flex items-center gap-2 text-sm font-medium",
-                    disabled: !has_api_key || input_text().trim().is_empty(),
-                    onclick: on_send,
-                    span { "📤" }
-                    "Send"
+                if is_running {
+                    button {
+                        class: "px-4 py-2 bg-error text-white rounded-lg hover:bg-error/90 transition-all flex items-center gap-2 text-sm font-medium",
+                        onclick: on_stop,
+                        span { "⏹" }
+                        "Stop"
+                    }
+                } else {
+                    button {
+                        class: "px-4 py-2 bg-primary text-white rounded-lg hover:bg-primary/90 transition-all disabled:opacity-50 disabled:cursor-not-allowed flex items-center gap-2 text-sm font-medium",
+                        disabled: !has_api_key || (input_text().trim().is_empty() && attachments().is_empty()),
+                        onclick: on_send,
+                        span { "📤" }
+                        "Send"
+                    }
                 }
             }
         }
@@ -61,14 +92,20 @@ pub fn ChatInput(
     input_text: Signal<String>,
     has_api_key: bool,
     on_send: EventHandler<MouseEvent>,
-    tx: Coroutine<String>,
+    attachments: Signal<Vec<Attachment>>,
+    tx: Coroutine<OutgoingMessage>,
+    #[props(default)] is_running: bool,
+    #[props(default)] on_stop: EventHandler<MouseEvent>,
 ) -> Element {
     rsx! {
         InputArea {
             input_text: input_text.clone(),
             has_api_key,
             on_send,
+            attachments,
             tx,
+            is_running,
+            on_stop,
         }
     }
 }
@@ -2,7 +2,10 @@
 //! 消息列表组件 - 显示聊天消息
 
 use dioxus::prelude::*;
+use crate::chat_history::Attachment;
+use crate::components::attachments::AttachmentThumbnail;
 use crate::components::markdown::{MarkdownContent, PlainTextContent};
+use crate::services::Usage;
 
 /// Chat message for display
 #[derive(Clone, Debug, PartialEq)]
@@ -11,15 +14,37 @@ pub struct ChatMessage {
     pub role: String,
     pub content: String,
     pub timestamp: u64,
+    pub cancelled: bool,
+    /// Cut off at `max_tokens` (offers "continue")
+    pub truncated: bool,
+    pub provider_id: Option<String>,
+    pub usage: Option<Usage>,
+    pub cost: Option<f64>,
+    pub attachments: Vec<Attachment>,
 }
 
 /// Message list container
+///
+/// Messages in `excluded_ids` are outside the model's context; they are dimmed
+/// and followed by a divider. A cut-off answer of the latest turn offers
+/// "continue" (reported to `on_continue` with its id) while no run is in progress.
 #[component]
 pub fn MessageList(
     messages: Vec<ChatMessage>,
     has_api_key: bool,
     #[props(default)] scroll_container_id: String,
+    #[props(default)] excluded_ids: Vec<String>,
+    /// Excluded messages are replaced by a summary rather than dropped
+    #[props(default)] context_summarized: bool,
+    #[props(default)] is_running: bool,
+    on_continue: Option<EventHandler<String>>,
 ) -> Element {
+    let last_excluded = messages.iter().rposition(|m| excluded_ids.contains(&m.id));
+    let latest_turn = messages.iter().rposition(|m| m.role == "user").map_or(0, |i| i + 1);
+    let continuable = (latest_turn..messages.len())
+        .find(|&i| messages[i].truncated)
+        .filter(|_| !is_running);
+
     rsx! {
         div {
             id: scroll_container_id,
@@ -28,10 +53,36 @@ pub fn MessageList(
             if messages.is_empty() {
                 EmptyState { has_api_key }
             } else {
-                for msg in messages.into_iter() {
-                    MessageBubble { message: msg }
+                for (index, msg) in messages.into_iter().enumerate() {
+                    MessageBubble {
+                        dimmed: excluded_ids.contains(&msg.id),
+                        on_continue: on_continue.filter(|_| continuable == Some(index)),
+                        message: msg,
+                    }
+                    if Some(index) == last_excluded {
+                        ContextDivider { count: excluded_ids.len(), summarized: context_summarized }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Marks where the model's context starts
+#[component]
+fn ContextDivider(count: usize, summarized: bool) -> Element {
+    rsx! {
+        div {
+            class: "flex items-center gap-3 text-xs text-text-muted",
+            div { class: "flex-1 border-t border-dashed border-border" }
+            span {
+                if summarized {
+                    "🗜 {count} earlier messages are summarized for the model"
+                } else {
+                    "🗜 {count} earlier messages are outside the model's context"
                 }
             }
+            div { class: "flex-1 border-t border-dashed border-border" }
         }
     }
 }
@@ -66,17 +117,27 @@ pub fn EmptyState(has_api_key: bool) -> Element {
 }
 
 /// Individual message bubble
+///
+/// `dimmed` marks a message outside the model's context.
 #[component]
-fn MessageBubble(message: ChatMessage) -> Element {
+fn MessageBubble(
+    message: ChatMessage,
+    #[props(default)] dimmed: bool,
+    on_continue: Option<EventHandler<String>>,
+) -> Element {
+    let align = if message.role == "user" {
+        "justify-end"
+    } else if message.role == "system" {
+        "justify-center"
+    } else {
+        "justify-start"
+    };
+    let class = if dimmed { format!("flex {} opacity-50", align) } else { format!("flex {}", align) };
+
     rsx! {
         div {
-            class: if message.role == "user" {
-                "flex justify-end"
-            } else if message.role == "system" {
-                "flex justify-center"
-            } else {
-                "flex justify-start"
-            },
+            class,
+            title: if dimmed { "Outside the model's context" } else { "" },
 
             if message.role == "system" {
                 div {
@@ -87,11 +148,22 @@ fn MessageBubble(message: ChatMessage) -> Element {
                 UserMessageBubble {
                     content: message.content.clone(),
                     timestamp: message.timestamp,
+                    attachments: message.attachments.clone(),
                 }
             } else {
                 AssistantMessageBubble {
                     content: message.content.clone(),
                     timestamp: message.timestamp,
+                    cancelled: message.cancelled,
+                    truncated: message.truncated,
+                    on_continue: on_continue.map(|handler| {
+                        let id = message.id.clone();
+                        EventHandler::new(move |_: MouseEvent| handler.call(id.clone()))
+                    }),
+                    provider_id: message.provider_id.clone(),
+                    usage: message.usage,
+                    cost: message.cost,
+                    attachments: message.attachments.clone(),
                 }
             }
         }
@@ -100,10 +172,22 @@ fn MessageBubble(message: ChatMessage) -> Element {
 
 /// User message bubble
 #[component]
-fn UserMessageBubble(content: String, timestamp: u64) -> Element {
+fn UserMessageBubble(
+    content: String,
+    timestamp: u64,
+    #[props(default)] attachments: Vec<Attachment>,
+) -> Element {
     rsx! {
         div {
             class: "max-w-2xl",
+            if !attachments.is_empty() {
+                div {
+                    class: "flex flex-wrap gap-2 justify-end mb-1 mr-9",
+                    for attachment in attachments {
+                        AttachmentThumbnail { key: "{attachment.id}", attachment }
+                    }
+                }
+            }
             div {
                 class: "flex items-start gap-2 justify-end",
                 div {
@@ -129,7 +213,18 @@ fn UserMessageBubble(content: String, timestamp: u64) -> Element {
 
 /// Assistant message bubble
 #[component]
-fn AssistantMessageBubble(content: String, timestamp: u64) -> Element {
+fn AssistantMessageBubble(
+    content: String,
+    timestamp: u64,
+    #[props(default)] cancelled: bool,
+    /// Stopped at `max_tokens`; `on_continue` is set when it can be continued
+    #[props(default)] truncated: bool,
+    on_continue: Option<EventHandler<MouseEvent>>,
+    #[props(default)] provider_id: Option<String>,
+    #[props(default)] usage: Option<Usage>,
+    #[props(default)] cost: Option<f64>,
+    #[props(default)] attachments: Vec<Attachment>,
+) -> Element {
     rsx! {
         div {
             class: "max-w-2xl",
@@ -145,11 +240,48 @@ fn AssistantMessageBubble(content: String, timestamp: u64) -> Element {
                         content: content.clone(),
                         class: "text-sm text-text-primary".to_string()
                     }
+                    if !attachments.is_empty() {
+                        div {
+                            class: "flex flex-wrap gap-2 mt-2",
+                            for attachment in attachments {
+                                AttachmentThumbnail { key: "{attachment.id}", attachment }
+                            }
+                        }
+                    }
                 }
             }
             p {
                 class: "text-xs text-text-muted mt-1",
                 {format_timestamp(timestamp)}
+                if let Some(provider) = provider_id {
+                    span { class: "ml-2", "· {provider}" }
+                }
+                if let Some(usage) = usage {
+                    span {
+                        class: "ml-2",
+                        title: usage_tooltip(&usage),
+                        {format!("· ↑{} ↓{}", format_tokens(usage.total_input_tokens()), format_tokens(usage.output_tokens))}
+                        if let Some(cached) = format_cache_hit(&usage) {
+                            span { class: "ml-1 text-success", "{cached}" }
+                        }
+                    }
+                }
+                if let Some(cost) = cost {
+                    span { class: "ml-2", {format!("· {}", format_cost(cost))} }
+                }
+                if cancelled {
+                    span { class: "ml-2 text-warning", "⏹ Stopped" }
+                }
+                if truncated {
+                    span { class: "ml-2 text-warning", "✂️ Cut off at max tokens" }
+                    if let Some(on_continue) = on_continue {
+                        button {
+                            class: "ml-2 text-primary hover:underline",
+                            onclick: move |e| on_continue.call(e),
+                            "▶ Continue"
+                        }
+                    }
+                }
             }
         }
     }
@@ -166,3 +298,41 @@ fn format_timestamp(timestamp: u64) -> String {
         "??:??".to_string()
     }
 }
+
+/// Format a token count compactly (e.g. 950, 12.3k, 1.2M)
+pub fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..=999 => tokens.to_string(),
+        1_000..=999_999 => format!("{:.1}k", tokens as f64 / 1_000.0),
+        _ => format!("{:.1}M", tokens as f64 / 1_000_000.0),
+    }
+}
+
+/// Format an estimated cost in USD (more decimals for small amounts)
+pub fn format_cost(cost: f64) -> String {
+    if cost < 0.01 {
+        format!("${:.4}", cost)
+    } else {
+        format!("${:.2}", cost)
+    }
+}
+
+/// Detailed token breakdown shown on hover
+pub fn usage_tooltip(usage: &Usage) -> String {
+    format!(
+        "Input: {}\nOutput: {}\nCache write: {}\nCache read: {}\nCache hit rate: {:.0}%",
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cache_creation_input_tokens,
+        usage.cache_read_input_tokens,
+        usage.cache_hit_rate().unwrap_or(0.0) * 100.0
+    )
+}
+
+/// Short cache hit marker (e.g. "⚡82%"), None when nothing was read from the cache
+pub fn format_cache_hit(usage: &Usage) -> Option<String> {
+    if usage.cache_read_input_tokens == 0 {
+        return None;
+    }
+    usage.cache_hit_rate().map(|rate| format!("⚡{:.0}%", rate * 100.0))
+}
@@ -15,7 +15,7 @@ pub use input_area::{ChatInput, InputArea};
 pub use header::ChatHeader;
 
 // Re-export hooks
-pub use hooks::{use_chat_coroutine, use_message_sync, use_auto_scroll, use_scroll_state_init};
+pub use hooks::{use_chat_coroutine, use_message_sync, use_auto_scroll, use_scroll_state_init, use_mcp_status, session_context};
 
 // Re-export handlers
 pub use handlers::{
@@ -23,10 +23,48 @@ pub use handlers::{
     use_switch_session_handler,
     use_delete_session_handler,
     use_switch_provider_handler,
+    use_update_generation_handler,
     use_send_message_handler,
 };
 
 // Shared types
+/// Message sent from the input area to the chat coroutine
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutgoingMessage {
+    pub text: String,
+    pub attachments: Vec<crate::chat_history::Attachment>,
+    /// Id of a cut-off answer to continue instead of sending a new message
+    pub continue_from: Option<String>,
+    /// Messages to add instead of `text` (an MCP prompt); answered if the last one is from the user
+    pub inserted: Vec<InsertedMessage>,
+}
+
+impl OutgoingMessage {
+    /// New user message
+    pub fn new(text: String, attachments: Vec<crate::chat_history::Attachment>) -> Self {
+        OutgoingMessage { text, attachments, continue_from: None, inserted: Vec::new() }
+    }
+
+    /// Ask the model to continue an answer that stopped at `max_tokens`
+    pub fn continuation(message_id: String) -> Self {
+        OutgoingMessage { continue_from: Some(message_id), ..Self::new(String::new(), Vec::new()) }
+    }
+
+    /// Add prepared messages to the conversation
+    pub fn insert(messages: Vec<InsertedMessage>) -> Self {
+        OutgoingMessage { inserted: messages, ..Self::new(String::new(), Vec::new()) }
+    }
+}
+
+/// Message added to the conversation as is
+#[derive(Clone, Debug, PartialEq)]
+pub struct InsertedMessage {
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: String,
+    pub attachments: Vec<crate::chat_history::Attachment>,
+}
+
 /// Chat session for UI display
 #[derive(Clone, Debug, PartialEq)]
 pub struct UiSession {
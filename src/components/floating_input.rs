@@ -1,14 +1,20 @@
 use arboard::Clipboard;
 use dioxus::prelude::*;
+use crate::chat_history::Attachment;
+use crate::components::attachments::{paste_clipboard_image, AttachmentThumbnail};
+use crate::components::chat::OutgoingMessage;
+use crate::components::slash_commands::{use_slash_commands, SlashCommandMenu};
 
 #[component]
 pub fn FloatingInput(
     is_visible: bool,
     on_close: Callback<()>,
-    on_submit: Callback<String>,
+    on_submit: Callback<OutgoingMessage>,
 ) -> Element {
     let mut input_text = use_signal(String::new);
+    let mut attachments = use_signal(Vec::<Attachment>::new);
     let mut selected_tool = use_signal(|| "explain".to_string());
+    let mut commands = use_slash_commands(input_text, move |messages| on_submit.call(OutgoingMessage::insert(messages)));
 
     let tools = vec!["explain", "summarize", "translate", "code_gen", "refactor"];
 
@@ -52,21 +58,25 @@ pub fn FloatingInput(
                     input {
                         class: "input-field mb-4",
                         r#type: "text",
-                        placeholder: "Type your prompt...",
+                        placeholder: "Type your prompt, or / for MCP prompts...",
                         value: input_text(),
                         oninput: move |e| input_text.set(e.value()),
                         onkeydown: move |e| {
-                            if e.key() == Key::Escape {
+                            if commands.handle_key(&e.key()) {
+                                e.prevent_default();
+                            } else if e.key() == Key::Escape {
                                 on_close.call(());
                             } else if e.key() == Key::Enter {
-                                if !input_text().trim().is_empty() {
-                                    on_submit.call(input_text());
+                                if !input_text().trim().is_empty() || !attachments().is_empty() {
+                                    on_submit.call(OutgoingMessage::new(input_text(), attachments.take()));
                                     input_text.set(String::new());
                                 }
                             }
                         },
                     }
 
+                    SlashCommandMenu { commands, class: "-mt-2 mb-4" }
+
                     div { class: "flex gap-2 mb-4",
                         button {
                             class: "btn-secondary flex-1 p-2.5 text-sm",
@@ -74,10 +84,15 @@ pub fn FloatingInput(
                                 if let Ok(mut clipboard) = Clipboard::new() {
                                     if let Ok(text) = clipboard.get_text() {
                                         input_text.set(text);
+                                        return;
                                     }
                                 }
+                                // No text: try an image
+                                if let Ok(Some(attachment)) = paste_clipboard_image() {
+                                    attachments.write().push(attachment);
+                                }
                             },
-                            title: "Paste from clipboard",
+                            title: "Paste text or image from clipboard",
                             "📋 Paste"
                         }
                         button {
@@ -94,6 +109,21 @@ pub fn FloatingInput(
                         }
                     }
 
+                    if !attachments().is_empty() {
+                        div { class: "flex flex-wrap gap-2 mb-4",
+                            for (index, attachment) in attachments().into_iter().enumerate() {
+                                AttachmentThumbnail {
+                                    key: "{attachment.id}",
+                                    attachment: attachment.clone(),
+                                    on_remove: move |_| {
+                                        let removed = attachments.write().remove(index);
+                                        removed.remove();
+                                    },
+                                }
+                            }
+                        }
+                    }
+
                     div { class: "flex gap-3 justify-end",
                         button {
                             class: "btn-cancel",
@@ -103,8 +133,8 @@ pub fn FloatingInput(
                         button {
                             class: "btn-primary",
                             onclick: move |_| {
-                                if !input_text().trim().is_empty() {
-                                    on_submit.call(input_text());
+                                if !input_text().trim().is_empty() || !attachments().is_empty() {
+                                    on_submit.call(OutgoingMessage::new(input_text(), attachments.take()));
                                     input_text.set(String::new());
                                 }
                             },
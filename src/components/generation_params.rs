@@ -0,0 +1,66 @@
+//! Generation parameter fields
+//! 生成参数表单 - 提供商默认值与会话覆盖共用
+
+use dioxus::prelude::*;
+use crate::components::ui::{TextArea, TextField};
+use crate::config::GenerationParams;
+
+/// Form fields for editing [`GenerationParams`]
+///
+/// Empty fields mean "not set". `defaults` are shown as placeholders, so when
+/// editing session overrides the user sees the provider value that applies.
+#[component]
+pub fn GenerationParamsFields(
+    mut params: Signal<GenerationParams>,
+    #[props(default)] defaults: GenerationParams,
+) -> Element {
+    let placeholder = |value: Option<String>| value.unwrap_or_else(|| "default".to_string());
+    let current = params();
+
+    rsx! {
+        div {
+            class: "space-y-4",
+            div {
+                class: "grid grid-cols-2 gap-4",
+                TextField {
+                    label: "Max Tokens".to_string(),
+                    value: current.max_tokens.map(|v| v.to_string()).unwrap_or_default(),
+                    placeholder: defaults.max_tokens().to_string(),
+                    input_type: "number".to_string(),
+                    oninput: move |e: FormEvent| params.write().max_tokens = e.value().trim().parse().ok(),
+                }
+                TextField {
+                    label: "Thinking Budget".to_string(),
+                    value: current.thinking_budget.map(|v| v.to_string()).unwrap_or_default(),
+                    placeholder: defaults.thinking_budget.map(|v| v.to_string()).unwrap_or_else(|| "off".to_string()),
+                    input_type: "number".to_string(),
+                    helper: "Extended thinking tokens; empty = off (Ollama only switches thinking on)".to_string(),
+                    oninput: move |e: FormEvent| params.write().thinking_budget = e.value().trim().parse().ok(),
+                }
+                TextField {
+                    label: "Temperature".to_string(),
+                    value: current.temperature.map(|v| v.to_string()).unwrap_or_default(),
+                    placeholder: placeholder(defaults.temperature.map(|v| v.to_string())),
+                    input_type: "number".to_string(),
+                    oninput: move |e: FormEvent| params.write().temperature = e.value().trim().parse().ok(),
+                }
+                TextField {
+                    label: "Top P".to_string(),
+                    value: current.top_p.map(|v| v.to_string()).unwrap_or_default(),
+                    placeholder: placeholder(defaults.top_p.map(|v| v.to_string())),
+                    input_type: "number".to_string(),
+                    oninput: move |e: FormEvent| params.write().top_p = e.value().trim().parse().ok(),
+                }
+            }
+            TextArea {
+                label: "Stop Sequences (one per line)".to_string(),
+                value: current.stop_sequences.join("\n"),
+                rows: 2,
+                placeholder: defaults.stop_sequences.join("\n"),
+                oninput: move |e: FormEvent| {
+                    params.write().stop_sequences = e.value().lines().filter(|s| !s.is_empty()).map(String::from).collect();
+                },
+            }
+        }
+    }
+}
@@ -3,10 +3,11 @@
 
 use dioxus::prelude::*;
 use crate::theme::use_theme;
-use crate::config::AppConfig;
-use crate::chat_history::ChatHistoryData;
+use crate::config::{AppConfig, ContextPolicy};
+use crate::chat_history::{Attachment, ChatHistoryData};
 use crate::components::chat::*;
 use crate::components::chat::message_list::ChatMessage;
+use tokio_util::sync::CancellationToken;
 
 // Re-export for use in other modules
 pub use crate::components::chat::UiSession;
@@ -19,6 +20,11 @@ pub fn Home() -> Element {
     // Chat messages state
     let messages = use_signal(Vec::<ChatMessage>::new);
     let input_text = use_signal(String::new);
+    // Attachments waiting to be sent with the next message
+    let pending_attachments = use_signal(Vec::<Attachment>::new);
+
+    // Cancellation token of the in-flight AI run (None when idle)
+    let active_run = use_signal(|| None::<CancellationToken>);
 
     // Auto-scroll state
     let scroll_container_id = "chat-messages-container";
@@ -65,6 +71,9 @@ pub fn Home() -> Element {
             .unwrap_or_else(|| "claude".to_string())
     });
 
+    // Live status of the pooled MCP servers (shown in the header)
+    let mcp_status = use_mcp_status();
+
     // Sync messages with current session
     use_message_sync(messages.clone(), chat_history.clone());
 
@@ -72,7 +81,7 @@ pub fn Home() -> Element {
     use_auto_scroll(messages.clone(), last_message_count.clone(), scroll_container_id.to_string());
 
     // Chat coroutine for AI calls
-    let tx = use_chat_coroutine(messages.clone(), chat_history.clone());
+    let tx = use_chat_coroutine(messages.clone(), chat_history.clone(), active_run.clone());
 
     // Create handlers
     let new_chat_handler = use_new_chat_handler(
@@ -90,7 +99,9 @@ pub fn Home() -> Element {
 
     let switch_provider = use_switch_provider_handler(active_provider_id.clone());
 
-    let send_message_handler = use_send_message_handler(input_text.clone(), tx.clone());
+    let update_generation = use_update_generation_handler(chat_history.clone(), active_provider_id.clone());
+
+    let send_message_handler = use_send_message_handler(input_text.clone(), pending_attachments, tx.clone());
 
     // Wrapper handlers for EventHandler compatibility (create closures that clone the handler)
     let new_chat_for_sidebar = {
@@ -125,6 +136,35 @@ pub fn Home() -> Element {
             .unwrap_or_else(|| "New Chat".to_string())
     });
 
+    // Running token usage and cost of the current session
+    let session_usage = use_memo(move || {
+        chat_history().get_current_session()
+            .map(|s| (s.usage, s.cost))
+            .unwrap_or_default()
+    });
+
+    // Per-session generation overrides and the active provider's defaults
+    let generation_overrides = use_memo(move || {
+        chat_history().get_current_session()
+            .map(|s| s.generation.clone())
+            .unwrap_or_default()
+    });
+    // Messages of the current session that no longer fit into the model's context
+    let active_context = use_memo(move || {
+        let history = chat_history();
+        let session = history.get_current_session()?;
+        let messages: Vec<ChatMessage> = session.messages.iter().cloned().map(ChatMessage::from).collect();
+        let context = session_context(&messages, session.context_summary.as_ref(), &session.generation)?;
+        let summarized = context.policy == ContextPolicy::SummarizeOlder && session.context_summary.is_some();
+        Some((context.excluded_ids, summarized))
+    });
+    let (excluded_ids, context_summarized) = active_context().unwrap_or_default();
+
+    let provider_generation = enabled_providers.iter()
+        .find(|p| p.id == active_provider_id())
+        .map(|p| p.generation.clone())
+        .unwrap_or_default();
+
     // Get sessions list for rendering (clone to owned Vec to fix lifetime issues)
     let sessions_list = sessions().clone();
 
@@ -155,7 +195,13 @@ pub fn Home() -> Element {
                     active_provider_id: active_provider_id(),
                     enabled_providers: enabled_providers.clone(),
                     enabled_mcp_servers: enabled_mcp_servers.clone(),
+                    mcp_status: mcp_status(),
                     sidebar_collapsed: sidebar_collapsed(),
+                    session_usage: session_usage().0,
+                    session_cost: session_usage().1,
+                    generation_overrides: generation_overrides(),
+                    provider_generation,
+                    on_update_generation: update_generation,
                     on_toggle_sidebar: move |_| sidebar_collapsed.set(!sidebar_collapsed()),
                     on_new_chat: new_chat_for_header,
                     on_switch_provider: switch_provider,
@@ -166,6 +212,13 @@ pub fn Home() -> Element {
                     messages: messages.read().clone(),
                     has_api_key,
                     scroll_container_id: scroll_container_id.to_string(),
+                    excluded_ids,
+                    context_summarized,
+                    is_running: active_run.read().is_some(),
+                    on_continue: {
+                        let tx = tx.clone();
+                        move |id: String| tx.send(OutgoingMessage::continuation(id))
+                    },
                 }
 
                 // Input area
@@ -173,7 +226,14 @@ pub fn Home() -> Element {
                     input_text: input_text.clone(),
                     has_api_key,
                     on_send: send_message,
+                    attachments: pending_attachments,
                     tx: tx.clone(),
+                    is_running: active_run.read().is_some(),
+                    on_stop: move |_| {
+                        if let Some(cancel) = active_run() {
+                            cancel.cancel();
+                        }
+                    },
                 }
             }
         }
@@ -7,6 +7,10 @@ pub mod result_viewer;
 pub mod about;
 pub mod markdown;
 pub mod chat;
+pub mod generation_params;
+pub mod attachments;
+pub mod resource_picker;
+pub mod slash_commands;
 
 // UI component library
 pub mod ui;
@@ -0,0 +1,278 @@
+//! MCP resource picker
+//! MCP 资源选择器 - 浏览已连接服务的资源并作为附件发送
+
+use dioxus::prelude::*;
+use crate::chat_history::{Attachment, ResourceSource};
+use crate::components::ui::{FormSection, Modal, ModalContent, ModalHeader, PrimaryButton};
+use crate::services::mcp_client::McpResourceContents;
+use crate::services::mcp_manager::{self, ResourceUpdate, ServerResources};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Binary media types that can be sent to the model
+const BINARY_TYPES: [&str; 5] = ["image/png", "image/jpeg", "image/gif", "image/webp", "application/pdf"];
+
+/// Store resource contents as an attachment (text becomes a text document)
+pub fn store_contents(name: &str, contents: &McpResourceContents) -> Result<Attachment, String> {
+    use base64::Engine;
+    let (media_type, bytes) = match (&contents.text, &contents.blob) {
+        (Some(text), _) => ("text/plain", text.as_bytes().to_vec()),
+        (None, Some(blob)) => {
+            let mime_type = contents.mime_type.as_deref().unwrap_or("application/octet-stream");
+            let media_type = BINARY_TYPES
+                .into_iter()
+                .find(|t| *t == mime_type)
+                .ok_or_else(|| format!("Unsupported resource type: {} ({})", name, mime_type))?;
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(blob)
+                .map_err(|e| format!("Invalid resource {}: {}", contents.uri, e))?;
+            (media_type, bytes)
+        }
+        (None, None) => return Err(format!("Resource {} has no contents", contents.uri)),
+    };
+    Attachment::store(name, media_type, &bytes).map_err(|e| e.to_string())
+}
+
+/// Read a resource and store its contents as attachments
+///
+/// A resource with several contents (e.g. a directory) becomes one attachment
+/// per content, named after its URI.
+async fn read_attachments(server: &str, name: &str, uri: &str) -> Result<Vec<Attachment>, String> {
+    let contents = mcp_manager::read_resource(server, uri).await?;
+    if contents.is_empty() {
+        return Err(format!("Resource {} is empty", uri));
+    }
+
+    let mut attachments: Vec<Attachment> = Vec::new();
+    for part in &contents {
+        let part_name = match contents.len() {
+            1 => name,
+            _ => part.uri.rsplit('/').find(|s| !s.is_empty()).unwrap_or(&part.uri),
+        };
+        match store_contents(part_name, part) {
+            Ok(mut attachment) => {
+                attachment.resource = Some(ResourceSource { server: server.to_string(), uri: uri.to_string() });
+                attachments.push(attachment);
+            }
+            Err(e) => {
+                attachments.iter().for_each(Attachment::remove);
+                return Err(e);
+            }
+        }
+    }
+    Ok(attachments)
+}
+
+/// Attach a resource and follow its changes until the message is sent
+async fn attach(mut attachments: Signal<Vec<Attachment>>, server: String, name: String, uri: String) -> Result<(), String> {
+    let read = read_attachments(&server, &name, &uri).await?;
+    attachments.write().extend(read);
+    if let Err(e) = mcp_manager::subscribe_resource(&server, &uri).await {
+        eprintln!("[MCP] Failed to subscribe to {}: {}", uri, e);
+    }
+    Ok(())
+}
+
+/// Re-read a changed resource that is still attached to the draft; unsubscribe once it isn't
+async fn refresh(mut attachments: Signal<Vec<Attachment>>, server: String, uri: String) {
+    let source = ResourceSource { server, uri };
+    let is_source = |attachment: &Attachment| attachment.resource.as_ref() == Some(&source);
+    let Some(name) = attachments.peek().iter().find(|a| is_source(a)).map(|a| a.name.clone()) else {
+        if let Err(e) = mcp_manager::unsubscribe_resource(&source.server, &source.uri).await {
+            eprintln!("[MCP] Failed to unsubscribe from {}: {}", source.uri, e);
+        }
+        return;
+    };
+
+    let fresh = match read_attachments(&source.server, &name, &source.uri).await {
+        Ok(fresh) => fresh,
+        Err(e) => {
+            eprintln!("[MCP] Failed to refresh {}: {}", source.uri, e);
+            return;
+        }
+    };
+    let mut pending = attachments.write();
+    // Removed (or sent) while it was being read
+    let Some(at) = pending.iter().position(is_source) else {
+        fresh.iter().for_each(Attachment::remove);
+        return;
+    };
+    pending.iter().filter(|a| is_source(a)).for_each(Attachment::remove);
+    pending.retain(|a| !is_source(a));
+    pending.splice(at..at, fresh);
+}
+
+fn load(mut listing: Signal<Option<Vec<ServerResources>>>) {
+    listing.set(None);
+    spawn(async move { listing.set(Some(mcp_manager::resources().await)); });
+}
+
+/// Button opening a picker of the resources of connected MCP servers
+///
+/// Picked resources are read and added to the pending attachments; while the
+/// message is a draft, subscribed resources are re-read when they change.
+#[component]
+pub fn ResourcePicker(
+    attachments: Signal<Vec<Attachment>>,
+    #[props(default)] disabled: bool,
+) -> Element {
+    let mut show = use_signal(|| false);
+    let listing = use_signal(|| None::<Vec<ServerResources>>);
+    // Template being filled in: (server, name, URI)
+    let mut template = use_signal(|| None::<(String, String, String)>);
+    let mut busy = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
+
+    use_future(move || async move {
+        let mut updates = mcp_manager::resource_updates();
+        loop {
+            match updates.recv().await {
+                Ok(ResourceUpdate::Updated { server, uri }) => refresh(attachments, server, uri).await,
+                Ok(ResourceUpdate::ListChanged { .. }) if show() => load(listing),
+                Ok(ResourceUpdate::ListChanged { .. }) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let mut pick = move |server: String, name: String, uri: String| {
+        busy.set(true);
+        error.set(None);
+        spawn(async move {
+            match attach(attachments, server, name, uri).await {
+                Ok(()) => {
+                    template.set(None);
+                    show.set(false);
+                }
+                Err(e) => error.set(Some(e)),
+            }
+            busy.set(false);
+        });
+    };
+
+    rsx! {
+        button {
+            class: "px-2 py-1 text-sm rounded-lg bg-bg-surface hover:bg-bg-secondary text-text-secondary hover:text-text-primary transition-colors",
+            title: "Attach MCP resources",
+            disabled,
+            onclick: move |_| {
+                error.set(None);
+                template.set(None);
+                show.set(true);
+                load(listing);
+            },
+            "📚"
+        }
+        Modal {
+            show: show(),
+            onclose: move |_| show.set(false),
+            max_width: "640px",
+            ModalHeader {
+                title: "MCP Resources",
+                subtitle: "Attach resources of connected servers as context",
+                icon: "📚",
+                show_close: true,
+                onclose: move |_| show.set(false),
+            }
+            ModalContent {
+                class: "max-h-[60vh] overflow-y-auto",
+                match listing() {
+                    None => rsx! {
+                        p { class: "text-sm text-text-secondary", "Loading resources..." }
+                    },
+                    Some(servers) if servers.is_empty() => rsx! {
+                        p { class: "text-sm text-text-secondary", "No connected MCP server offers resources" }
+                    },
+                    Some(servers) => rsx! {
+                        for ServerResources { server, resources, templates } in servers {
+                            div {
+                                key: "{server}",
+                                class: "space-y-1",
+                                h4 { class: "text-sm font-semibold text-text-primary", "{server}" }
+                                for resource in resources {
+                                    ResourceRow {
+                                        key: "{resource.uri}",
+                                        icon: "📄",
+                                        name: resource.name.clone(),
+                                        detail: resource.description.clone().unwrap_or_else(|| resource.uri.clone()),
+                                        disabled: busy(),
+                                        onclick: {
+                                            let server = server.clone();
+                                            move |_| pick(server.clone(), resource.name.clone(), resource.uri.clone())
+                                        },
+                                    }
+                                }
+                                for resource_template in templates {
+                                    ResourceRow {
+                                        key: "{resource_template.uri_template}",
+                                        icon: "🧩",
+                                        name: resource_template.name.clone(),
+                                        detail: resource_template.description.clone().unwrap_or_else(|| resource_template.uri_template.clone()),
+                                        disabled: busy(),
+                                        onclick: {
+                                            let server = server.clone();
+                                            move |_| template.set(Some((
+                                                server.clone(),
+                                                resource_template.name.clone(),
+                                                resource_template.uri_template.clone(),
+                                            )))
+                                        },
+                                    }
+                                }
+                            }
+                        }
+                    },
+                }
+                if let Some((server, name, uri)) = template() {
+                    FormSection {
+                        title: "Resource URI",
+                        description: "Replace the {{parameters}} of the template",
+                        div {
+                            class: "flex gap-2",
+                            input {
+                                class: "input-field flex-1 font-mono text-sm",
+                                value: "{uri}",
+                                oninput: move |e| {
+                                    if let Some(t) = template.write().as_mut() {
+                                        t.2 = e.value();
+                                    }
+                                },
+                            }
+                            PrimaryButton {
+                                disabled: busy() || uri.contains('{'),
+                                onclick: {
+                                    let uri = uri.clone();
+                                    move |_| pick(server.clone(), name.clone(), uri.clone())
+                                },
+                                "Attach"
+                            }
+                        }
+                    }
+                }
+                if busy() {
+                    p { class: "text-xs text-text-secondary", "Reading resource..." }
+                }
+                if let Some(e) = error() {
+                    p { class: "text-xs text-error", "{e}" }
+                }
+            }
+        }
+    }
+}
+
+/// Resource or template in the picker list
+#[component]
+fn ResourceRow(icon: String, name: String, detail: String, disabled: bool, onclick: EventHandler<MouseEvent>) -> Element {
+    rsx! {
+        button {
+            class: "w-full flex items-start gap-2 px-3 py-2 rounded-lg text-left hover:bg-bg-secondary transition-colors disabled:opacity-50",
+            disabled,
+            onclick: move |e| onclick.call(e),
+            span { "{icon}" }
+            div {
+                class: "min-w-0",
+                p { class: "text-sm text-text-primary truncate", "{name}" }
+                p { class: "text-xs text-text-secondary truncate", "{detail}" }
+            }
+        }
+    }
+}
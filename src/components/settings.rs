@@ -2,9 +2,9 @@
 //! 设置页面组件 - 使用 UI 组件库重构
 
 use dioxus::prelude::*;
-use crate::config::{AppConfig, ProviderConfig, ProviderType, McpServerConfig};
+use crate::config::{ApiFormat, AppConfig, AuthScheme, GenerationParams, ProviderConfig, ProviderType, McpServerConfig, McpTransport};
 use crate::components::ui::*;
-use crate::components::settings_tabs::{AiProvidersTab, McpServersTab, AppearanceTab, ShortcutsTab};
+use crate::components::settings_tabs::{AiProvidersTab, McpServersTab, AppearanceTab, NetworkTab, ShortcutsTab};
 
 /// Settings tab
 /// 设置标签页
@@ -12,6 +12,7 @@ use crate::components::settings_tabs::{AiProvidersTab, McpServersTab, Appearance
 pub enum SettingsTab {
     AI,
     MCP,
+    Network,
     Appearance,
     Shortcuts,
 }
@@ -21,6 +22,7 @@ impl SettingsTab {
         match self {
             SettingsTab::AI => "ai",
             SettingsTab::MCP => "mcp",
+            SettingsTab::Network => "network",
             SettingsTab::Appearance => "appearance",
             SettingsTab::Shortcuts => "shortcuts",
         }
@@ -54,12 +56,28 @@ pub fn Settings() -> Element {
     let form_api_key = use_signal(|| String::new());
     let form_base_url = use_signal(|| String::new());
     let form_model = use_signal(|| String::new());
+    let form_api_format = use_signal(|| ApiFormat::Anthropic);
+    let form_auth_scheme = use_signal(|| AuthScheme::XApiKey);
+    let form_supports_tools = use_signal(|| true);
+    let form_prompt_caching = use_signal(|| true);
+    // Retry/timeout overrides (empty = provider default)
+    let form_max_retries = use_signal(|| String::new());
+    let form_connect_timeout = use_signal(|| String::new());
+    let form_read_timeout = use_signal(|| String::new());
+    let form_prices = use_signal(|| String::new());
+    let form_extra_headers = use_signal(|| String::new());
+    let form_record_fixture = use_signal(|| String::new());
+    let form_context_window = use_signal(|| String::new());
+    let form_generation = use_signal(GenerationParams::default);
 
     // Form states for MCP servers
     let editing_server = use_signal(|| Option::<String>::None);
     let server_form_name = use_signal(|| String::new());
     let server_form_command = use_signal(|| String::new());
     let server_form_args = use_signal(|| String::new());
+    let server_form_transport = use_signal(McpTransport::default);
+    let server_form_url = use_signal(|| String::new());
+    let server_form_headers = use_signal(|| String::new());
 
     rsx! {
         div {
@@ -82,6 +100,13 @@ pub fn Settings() -> Element {
                     icon: "⚡".to_string(),
                     onclick: move |_| active_tab.set(SettingsTab::MCP),
                 }
+                NavTab {
+                    label: "Network".to_string(),
+                    value: "network".to_string(),
+                    active_value: active_tab().as_str().to_string(),
+                    icon: "🌐".to_string(),
+                    onclick: move |_| active_tab.set(SettingsTab::Network),
+                }
                 NavTab {
                     label: "Appearance".to_string(),
                     value: "appearance".to_string(),
@@ -111,10 +136,25 @@ pub fn Settings() -> Element {
                     form_api_key.clone(),
                     form_base_url.clone(),
                     form_model.clone(),
+                    form_api_format.clone(),
+                    form_auth_scheme.clone(),
+                    form_supports_tools.clone(),
+                    form_prompt_caching.clone(),
+                    form_max_retries.clone(),
+                    form_connect_timeout.clone(),
+                    form_read_timeout.clone(),
+                    form_prices.clone(),
+                    form_extra_headers.clone(),
+                    form_record_fixture.clone(),
+                    form_context_window.clone(),
+                    form_generation.clone(),
                     editing_server.clone(),
                     server_form_name.clone(),
                     server_form_command.clone(),
                     server_form_args.clone(),
+                    server_form_transport.clone(),
+                    server_form_url.clone(),
+                    server_form_headers.clone(),
                 )}
             }
         }
@@ -134,10 +174,25 @@ fn render_active_tab(
     form_api_key: Signal<String>,
     form_base_url: Signal<String>,
     form_model: Signal<String>,
+    form_api_format: Signal<ApiFormat>,
+    form_auth_scheme: Signal<AuthScheme>,
+    form_supports_tools: Signal<bool>,
+    form_prompt_caching: Signal<bool>,
+    form_max_retries: Signal<String>,
+    form_connect_timeout: Signal<String>,
+    form_read_timeout: Signal<String>,
+    form_prices: Signal<String>,
+    form_extra_headers: Signal<String>,
+    form_record_fixture: Signal<String>,
+    form_context_window: Signal<String>,
+    form_generation: Signal<GenerationParams>,
     editing_server: Signal<Option<String>>,
     server_form_name: Signal<String>,
     server_form_command: Signal<String>,
     server_form_args: Signal<String>,
+    server_form_transport: Signal<McpTransport>,
+    server_form_url: Signal<String>,
+    server_form_headers: Signal<String>,
 ) -> Element {
     match active_tab {
         SettingsTab::AI => rsx! {
@@ -150,6 +205,18 @@ fn render_active_tab(
                 form_api_key: form_api_key.clone(),
                 form_base_url: form_base_url.clone(),
                 form_model: form_model.clone(),
+                form_api_format: form_api_format.clone(),
+                form_auth_scheme: form_auth_scheme.clone(),
+                form_supports_tools: form_supports_tools.clone(),
+                form_prompt_caching: form_prompt_caching.clone(),
+                form_max_retries: form_max_retries.clone(),
+                form_connect_timeout: form_connect_timeout.clone(),
+                form_read_timeout: form_read_timeout.clone(),
+                form_prices: form_prices.clone(),
+                form_extra_headers: form_extra_headers.clone(),
+                form_record_fixture: form_record_fixture.clone(),
+                form_context_window: form_context_window.clone(),
+                form_generation: form_generation.clone(),
             }
         },
         SettingsTab::MCP => rsx! {
@@ -159,8 +226,14 @@ fn render_active_tab(
                 server_form_name: server_form_name.clone(),
                 server_form_command: server_form_command.clone(),
                 server_form_args: server_form_args.clone(),
+                server_form_transport: server_form_transport.clone(),
+                server_form_url: server_form_url.clone(),
+                server_form_headers: server_form_headers.clone(),
             }
         },
+        SettingsTab::Network => rsx! {
+            NetworkTab {}
+        },
         SettingsTab::Appearance => rsx! {
             AppearanceTab {}
         },
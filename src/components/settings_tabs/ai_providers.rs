@@ -2,8 +2,10 @@
 //! AI 提供商配置标签页
 
 use dioxus::prelude::*;
-use crate::config::{AppConfig, ProviderConfig, ProviderType};
+use crate::config::{ApiFormat, AppConfig, AuthScheme, CachedModels, ContextConfig, ContextPolicy, GenerationParams, KeySource, ModelCache, ModelPrice, ProviderConfig, ProviderType, SecretStore};
+use crate::components::generation_params::GenerationParamsFields;
 use crate::components::ui::*;
+use crate::services::{AiClient, ConnectionTest};
 
 /// AI Providers tab content
 #[component]
@@ -17,8 +19,23 @@ pub fn AiProvidersTab(
     mut form_api_key: Signal<String>,
     mut form_base_url: Signal<String>,
     mut form_model: Signal<String>,
+    mut form_api_format: Signal<ApiFormat>,
+    mut form_auth_scheme: Signal<AuthScheme>,
+    mut form_supports_tools: Signal<bool>,
+    mut form_prompt_caching: Signal<bool>,
+    mut form_max_retries: Signal<String>,
+    mut form_connect_timeout: Signal<String>,
+    mut form_read_timeout: Signal<String>,
+    mut form_prices: Signal<String>,
+    mut form_extra_headers: Signal<String>,
+    mut form_record_fixture: Signal<String>,
+    mut form_context_window: Signal<String>,
+    mut form_generation: Signal<GenerationParams>,
 ) -> Element {
     let providers_list = providers();
+    // Last connection test per provider, and providers with a test in flight
+    let mut connection_tests = use_signal(AiClient::connection_tests);
+    let mut testing = use_signal(Vec::<String>::new);
     let is_adding_mode = move || editing_provider().as_ref().map_or(false, |id| id.is_empty());
 
     rsx! {
@@ -31,8 +48,8 @@ pub fn AiProvidersTab(
 
             // API compatibility notice
             InfoCard {
-                title: "Anthropic-Compatible API Required".to_string(),
-                message: "All providers must use the Anthropic Claude API format (Messages API).".to_string(),
+                title: "Supported API Formats".to_string(),
+                message: "Providers can speak the Anthropic Messages API, the OpenAI-compatible Chat Completions API (OpenAI, DeepSeek, OpenRouter, vLLM, llama.cpp...) or the native Ollama API. Choose the format per provider; local Ollama servers need no API key.".to_string(),
                 icon: "ℹ️".to_string(),
                 variant: InfoCardVariant::Info,
             }
@@ -49,6 +66,18 @@ pub fn AiProvidersTab(
                         form_api_key.set(String::new());
                         form_base_url.set(String::new());
                         form_model.set(String::new());
+                        form_api_format.set(ApiFormat::Anthropic);
+                        form_auth_scheme.set(AuthScheme::XApiKey);
+                        form_supports_tools.set(true);
+                        form_prompt_caching.set(true);
+                        form_max_retries.set(String::new());
+                        form_connect_timeout.set(String::new());
+                        form_read_timeout.set(String::new());
+                        form_prices.set(String::new());
+                        form_extra_headers.set(String::new());
+                        form_record_fixture.set(String::new());
+                        form_context_window.set(String::new());
+                        form_generation.set(GenerationParams::default());
                     },
                     "＋ Add Provider"
                 }
@@ -61,6 +90,20 @@ pub fn AiProvidersTab(
                     ProviderListItem {
                         provider: provider.clone(),
                         key: "{provider.id}",
+                        test: connection_tests().get(&provider.id).cloned(),
+                        testing: testing().contains(&provider.id),
+                        ontest: {
+                            let provider = provider.clone();
+                            move |_| {
+                                let provider = provider.clone();
+                                testing.write().push(provider.id.clone());
+                                spawn(async move {
+                                    let result = AiClient::test_connection(&provider).await;
+                                    connection_tests.write().insert(provider.id.clone(), result);
+                                    testing.write().retain(|id| *id != provider.id);
+                                });
+                            }
+                        },
                         onedit: {
                             let pid = provider.id.clone();
                             let pname = provider.name.clone();
@@ -68,6 +111,18 @@ pub fn AiProvidersTab(
                             let papi_key = provider.api_key.clone().unwrap_or_default();
                             let pbase_url = provider.base_url.clone().unwrap_or_default();
                             let pmodel = provider.model.clone().unwrap_or_default();
+                            let papi_format = provider.api_format;
+                            let pauth_scheme = provider.auth_scheme();
+                            let psupports_tools = provider.supports_tools();
+                            let pprompt_caching = provider.prompt_caching();
+                            let pmax_retries = provider.max_retries.map(|n| n.to_string()).unwrap_or_default();
+                            let pconnect_timeout = provider.connect_timeout_secs.map(|n| n.to_string()).unwrap_or_default();
+                            let pread_timeout = provider.read_timeout_secs.map(|n| n.to_string()).unwrap_or_default();
+                            let pprices = format_prices(&provider.prices);
+                            let pextra_headers = format_headers(&provider.extra_headers);
+                            let precord_fixture = provider.record_fixture.clone().unwrap_or_default();
+                            let pcontext_window = provider.context_window.map(|n| n.to_string()).unwrap_or_default();
+                            let pgeneration = provider.generation.clone();
                             move |_| {
                                 editing_provider.set(Some(pid.clone()));
                                 form_id.set(pid.clone());
@@ -76,6 +131,18 @@ pub fn AiProvidersTab(
                                 form_api_key.set(papi_key.clone());
                                 form_base_url.set(pbase_url.clone());
                                 form_model.set(pmodel.clone());
+                                form_api_format.set(papi_format);
+                                form_auth_scheme.set(pauth_scheme.clone());
+                                form_supports_tools.set(psupports_tools);
+                                form_prompt_caching.set(pprompt_caching);
+                                form_max_retries.set(pmax_retries.clone());
+                                form_connect_timeout.set(pconnect_timeout.clone());
+                                form_read_timeout.set(pread_timeout.clone());
+                                form_prices.set(pprices.clone());
+                                form_extra_headers.set(pextra_headers.clone());
+                                form_record_fixture.set(precord_fixture.clone());
+                                form_context_window.set(pcontext_window.clone());
+                                form_generation.set(pgeneration.clone());
                             }
                         },
                         ondelete: {
@@ -95,6 +162,15 @@ pub fn AiProvidersTab(
                 }
             }
 
+            // Fallback order
+            FallbackChainSection { providers: providers_list.clone() }
+
+            // Encrypted API key storage
+            SecretsSection {}
+
+            // Long session handling
+            ContextSection {}
+
             // Edit/Add modal
             ProviderModal {
                 show: editing_provider().is_some(),
@@ -106,6 +182,18 @@ pub fn AiProvidersTab(
                 form_api_key: form_api_key.clone(),
                 form_base_url: form_base_url.clone(),
                 form_model: form_model.clone(),
+                form_api_format: form_api_format.clone(),
+                form_auth_scheme: form_auth_scheme.clone(),
+                form_supports_tools: form_supports_tools.clone(),
+                form_prompt_caching: form_prompt_caching.clone(),
+                form_max_retries: form_max_retries.clone(),
+                form_connect_timeout: form_connect_timeout.clone(),
+                form_read_timeout: form_read_timeout.clone(),
+                form_prices: form_prices.clone(),
+                form_extra_headers: form_extra_headers.clone(),
+                form_record_fixture: form_record_fixture.clone(),
+                form_context_window: form_context_window.clone(),
+                form_generation: form_generation.clone(),
                 onsave: {
                     let mut providers = providers.clone();
                     move |provider_config| {
@@ -124,14 +212,300 @@ pub fn AiProvidersTab(
     }
 }
 
+/// Ordered fallback providers, tried when the active provider fails with a retryable error
+#[component]
+fn FallbackChainSection(providers: Vec<ProviderConfig>) -> Element {
+    let mut fallback_ids = use_signal(|| {
+        AppConfig::load()
+            .map(|c| c.ai.fallback_providers)
+            .unwrap_or_default()
+    });
+
+    let mut save = move |ids: Vec<String>| {
+        if let Ok(mut config) = AppConfig::load() {
+            config.set_fallback_providers(ids.clone());
+        }
+        fallback_ids.set(ids);
+    };
+
+    let chain: Vec<ProviderConfig> = fallback_ids()
+        .iter()
+        .filter_map(|id| providers.iter().find(|p| p.id == *id).cloned())
+        .collect();
+    let available: Vec<ProviderConfig> = providers
+        .iter()
+        .filter(|p| !fallback_ids().contains(&p.id))
+        .cloned()
+        .collect();
+    let last_index = chain.len().saturating_sub(1);
+
+    rsx! {
+        FormSection {
+            title: "Fallback Chain".to_string(),
+            description: "When the active provider is down or rate limited, these providers are tried in order".to_string(),
+            div {
+                class: "space-y-2",
+                for (index, provider) in chain.into_iter().enumerate() {
+                    div {
+                        key: "{provider.id}",
+                        class: "flex items-center gap-3 px-3 py-2 bg-bg-surface border border-border rounded-md text-sm",
+                        span { class: "text-text-muted font-mono", "{index + 1}." }
+                        span { class: "flex-1 text-text-primary", "{provider.name}" }
+                        if !provider.is_usable() {
+                            StatusBadge {
+                                status: StatusType::Warning,
+                                text: "Not usable".to_string(),
+                                small: true,
+                            }
+                        }
+                        button {
+                            class: "px-2 text-text-secondary hover:text-text-primary disabled:opacity-30",
+                            disabled: index == 0,
+                            onclick: move |_| {
+                                let mut ids = fallback_ids();
+                                ids.swap(index, index.saturating_sub(1));
+                                save(ids);
+                            },
+                            "↑"
+                        }
+                        button {
+                            class: "px-2 text-text-secondary hover:text-text-primary disabled:opacity-30",
+                            disabled: index == last_index,
+                            onclick: move |_| {
+                                let mut ids = fallback_ids();
+                                ids.swap(index, index + 1);
+                                save(ids);
+                            },
+                            "↓"
+                        }
+                        button {
+                            class: "px-2 text-text-secondary hover:text-error",
+                            onclick: {
+                                let id = provider.id.clone();
+                                move |_| {
+                                    let mut ids = fallback_ids();
+                                    ids.retain(|i| *i != id);
+                                    save(ids);
+                                }
+                            },
+                            "✕"
+                        }
+                    }
+                }
+                if !available.is_empty() {
+                    select {
+                        class: "input-field",
+                        value: "",
+                        onchange: move |e| {
+                            let id = e.value();
+                            if !id.is_empty() {
+                                let mut ids = fallback_ids();
+                                ids.push(id);
+                                save(ids);
+                            }
+                        },
+                        option { value: "", "＋ Add fallback provider..." }
+                        for provider in available {
+                            option { key: "{provider.id}", value: "{provider.id}", "{provider.name}" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How long sessions are fitted into the model's context window
+#[component]
+fn ContextSection() -> Element {
+    let mut context = use_signal(|| {
+        AppConfig::load()
+            .map(|c| c.ai.context)
+            .unwrap_or_default()
+    });
+
+    let mut save = move |updated: ContextConfig| {
+        if let Ok(mut config) = AppConfig::load() {
+            config.update_context(updated.clone());
+        }
+        context.set(updated);
+    };
+
+    let current = context();
+
+    rsx! {
+        FormSection {
+            title: "Context Window".to_string(),
+            description: "When a session no longer fits into the model's context, older turns are left out. They stay in the history, dimmed in the chat.".to_string(),
+            div {
+                class: "space-y-3",
+                select {
+                    class: "input-field",
+                    value: format!("{:?}", current.policy),
+                    onchange: move |e| {
+                        let policy = match e.value().as_str() {
+                            "SummarizeOlder" => ContextPolicy::SummarizeOlder,
+                            "PinFirst" => ContextPolicy::PinFirst,
+                            _ => ContextPolicy::SlidingWindow,
+                        };
+                        save(ContextConfig { policy, ..context() });
+                    },
+                    option { value: "SlidingWindow", {ContextPolicy::SlidingWindow.label()} }
+                    option { value: "SummarizeOlder", {ContextPolicy::SummarizeOlder.label()} }
+                    option { value: "PinFirst", {ContextPolicy::PinFirst.label()} }
+                }
+                div {
+                    class: "grid grid-cols-2 gap-4",
+                    TextField {
+                        label: "Max Context Tokens".to_string(),
+                        icon: "📏".to_string(),
+                        value: current.max_context_tokens.map(|n| n.to_string()).unwrap_or_default(),
+                        placeholder: "From the model".to_string(),
+                        input_type: "number".to_string(),
+                        oninput: move |e: FormEvent| save(ContextConfig { max_context_tokens: e.value().trim().parse().ok(), ..context() }),
+                    }
+                    if current.policy == ContextPolicy::PinFirst {
+                        TextField {
+                            label: "Pinned Messages".to_string(),
+                            icon: "📌".to_string(),
+                            value: current.pinned_messages.map(|n| n.to_string()).unwrap_or_default(),
+                            placeholder: ContextConfig::DEFAULT_PINNED_MESSAGES.to_string(),
+                            input_type: "number".to_string(),
+                            oninput: move |e: FormEvent| save(ContextConfig { pinned_messages: e.value().trim().parse().ok(), ..context() }),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Create, unlock and manage the passphrase-protected secrets store
+#[component]
+fn SecretsSection() -> Element {
+    let mut exists = use_signal(SecretStore::exists);
+    let mut unlocked = use_signal(SecretStore::is_unlocked);
+    let mut names = use_signal(SecretStore::names);
+    let mut passphrase = use_signal(String::new);
+    let mut confirm = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    // Deriving the key from the passphrase takes a few seconds
+    let mut deriving = use_signal(|| false);
+
+    let mut refresh = move || {
+        exists.set(SecretStore::exists());
+        unlocked.set(SecretStore::is_unlocked());
+        names.set(SecretStore::names());
+        passphrase.set(String::new());
+        confirm.set(String::new());
+    };
+    let mut report = move |result: Result<(), crate::config::SecretError>| match result {
+        Ok(()) => {
+            error.set(None);
+            refresh();
+        }
+        Err(e) => error.set(Some(e.to_string())),
+    };
+
+    rsx! {
+        FormSection {
+            title: "Secrets Store".to_string(),
+            description: "Optional encrypted storage for API keys, referenced as secret:NAME. Unlock it with your passphrase each session".to_string(),
+            div {
+                class: "space-y-3",
+                if unlocked() {
+                    div {
+                        class: "flex items-center justify-between",
+                        StatusBadge { status: StatusType::Ready, text: "🔓 Unlocked".to_string(), small: true }
+                        SecondaryButton {
+                            class: "px-3 py-1 text-sm".to_string(),
+                            onclick: move |_| {
+                                SecretStore::lock();
+                                refresh();
+                            },
+                            "🔒 Lock"
+                        }
+                    }
+                    if names().is_empty() {
+                        p { class: "text-xs text-text-muted", "No secrets yet. Use \"Move to secrets store\" when editing a provider." }
+                    }
+                    for name in names() {
+                        div {
+                            key: "{name}",
+                            class: "flex items-center gap-3 px-3 py-2 bg-bg-surface border border-border rounded-md text-sm",
+                            span { class: "flex-1 font-mono text-text-primary", "secret:{name}" }
+                            button {
+                                class: "px-2 text-text-secondary hover:text-error",
+                                title: "Delete secret",
+                                onclick: {
+                                    let name = name.clone();
+                                    move |_| report(SecretStore::remove(&name))
+                                },
+                                "✕"
+                            }
+                        }
+                    }
+                } else {
+                    TextField {
+                        label: (if exists() { "Passphrase" } else { "New Passphrase" }).to_string(),
+                        icon: "🔐".to_string(),
+                        value: passphrase(),
+                        input_type: "password".to_string(),
+                        oninput: move |e: FormEvent| passphrase.set(e.value()),
+                    }
+                    if !exists() {
+                        TextField {
+                            label: "Confirm Passphrase".to_string(),
+                            value: confirm(),
+                            input_type: "password".to_string(),
+                            oninput: move |e: FormEvent| confirm.set(e.value()),
+                        }
+                    }
+                    div {
+                        class: "flex justify-end",
+                        PrimaryButton {
+                            disabled: passphrase().is_empty() || deriving(),
+                            onclick: move |_| {
+                                let (create, secret) = (!exists(), passphrase());
+                                if create && secret != confirm() {
+                                    error.set(Some("Passphrases don't match".to_string()));
+                                    return;
+                                }
+                                deriving.set(true);
+                                spawn(async move {
+                                    // Keep the key derivation off the UI thread
+                                    let result = tokio::task::spawn_blocking(move || {
+                                        if create { SecretStore::create(&secret) } else { SecretStore::unlock(&secret) }
+                                    })
+                                    .await
+                                    .unwrap_or_else(|e| Err(std::io::Error::other(e).into()));
+                                    deriving.set(false);
+                                    report(result);
+                                });
+                            },
+                            if deriving() { "Deriving key..." } else if exists() { "🔓 Unlock" } else { "Create Store" }
+                        }
+                    }
+                }
+                if let Some(e) = error() {
+                    p { class: "text-xs text-error", "{e}" }
+                }
+            }
+        }
+    }
+}
+
 /// Provider list item component
 #[component]
 fn ProviderListItem(
     provider: ProviderConfig,
+    #[props(default)] test: Option<ConnectionTest>,
+    #[props(default)] testing: bool,
+    #[props(optional)] ontest: Option<EventHandler<MouseEvent>>,
     #[props(optional)] onedit: Option<EventHandler<MouseEvent>>,
     #[props(optional)] ondelete: Option<EventHandler<MouseEvent>>,
 ) -> Element {
-    let is_usable = provider.enabled && provider.api_key.as_ref().map_or(false, |k| !k.is_empty());
+    let is_usable = provider.is_usable();
 
     rsx! {
         div {
@@ -148,6 +522,18 @@ fn ProviderListItem(
                         provider_type: format!("{:?}", provider.provider_type),
                         small: true,
                     }
+                    if provider.api_format != ApiFormat::Anthropic {
+                        Tag {
+                            variant: BadgeVariant::Info,
+                            {provider.api_format.label()}
+                        }
+                    }
+                    if let Some(source) = provider.key_source() {
+                        Tag {
+                            variant: if source.is_plain() { BadgeVariant::Warning } else { BadgeVariant::Default },
+                            {format!("{} {}", source.icon(), source.label())}
+                        }
+                    }
                     StatusBadge {
                         status: if is_usable { StatusType::Ready }
                                   else if provider.enabled { StatusType::Warning }
@@ -157,6 +543,16 @@ fn ProviderListItem(
                                else { "".to_string() },
                         small: true,
                     }
+                    if let Some(test) = &test {
+                        StatusBadge {
+                            status: if test.is_ok() { StatusType::Ready } else { StatusType::Error },
+                            text: match &test.error {
+                                None => format!("✓ {} ms", test.latency.as_millis()),
+                                Some((category, _)) => format!("✗ {}", category),
+                            },
+                            small: true,
+                        }
+                    }
                 }
                 div {
                     class: "flex flex-wrap gap-x-4 gap-y-1 text-sm text-text-secondary",
@@ -173,6 +569,20 @@ fn ProviderListItem(
                         }
                     }
                 }
+                if let Some(test) = &test {
+                    div {
+                        class: "flex flex-wrap gap-x-4 gap-y-1 mt-1 text-xs font-mono text-text-muted",
+                        span { class: "truncate max-w-sm", "POST {test.endpoint}" }
+                        span { "Auth: {test.auth_scheme}" }
+                        span {
+                            {format!("Status: {}", test.status.map(|s| s.to_string()).unwrap_or_else(|| "no response".to_string()))}
+                        }
+                        span { {format!("{} ms", test.latency.as_millis())} }
+                    }
+                    if let Some((category, message)) = &test.error {
+                        p { class: "mt-1 text-xs text-error break-all", "{category}: {message}" }
+                    }
+                }
             }
             div {
                 class: "flex items-center gap-2",
@@ -195,6 +605,16 @@ fn ProviderListItem(
                     }
                     span { "Enabled" }
                 }
+                SecondaryButton {
+                    class: "px-3 py-1 text-sm".to_string(),
+                    disabled: testing,
+                    onclick: move |e| {
+                        if let Some(handler) = ontest {
+                            handler.call(e);
+                        }
+                    },
+                    if testing { "Testing..." } else { "Test" }
+                }
                 SecondaryButton {
                     class: "px-3 py-1 text-sm".to_string(),
                     onclick: move |e| {
@@ -231,8 +651,90 @@ fn ProviderModal(
     form_api_key: Signal<String>,
     form_base_url: Signal<String>,
     form_model: Signal<String>,
+    form_api_format: Signal<ApiFormat>,
+    form_auth_scheme: Signal<AuthScheme>,
+    form_supports_tools: Signal<bool>,
+    form_prompt_caching: Signal<bool>,
+    form_max_retries: Signal<String>,
+    form_connect_timeout: Signal<String>,
+    form_read_timeout: Signal<String>,
+    form_prices: Signal<String>,
+    form_extra_headers: Signal<String>,
+    form_record_fixture: Signal<String>,
+    form_context_window: Signal<String>,
+    form_generation: Signal<GenerationParams>,
     onsave: EventHandler<ProviderConfig>,
 ) -> Element {
+    // Provider configuration as currently entered in the form
+    let form_provider = move || ProviderConfig {
+        id: if !form_id().is_empty() { form_id() } else { format!("{:?}", form_provider_type()).to_lowercase() },
+        name: if form_name().is_empty() {
+            format!("{:?}", form_provider_type())
+        } else {
+            form_name()
+        },
+        provider_type: form_provider_type(),
+        api_key: if form_api_key().is_empty() { None } else { Some(form_api_key()) },
+        base_url: if form_base_url().is_empty() { None } else { Some(form_base_url()) },
+        model: if form_model().is_empty() { None } else { Some(form_model()) },
+        enabled: true,
+        api_format: form_api_format(),
+        auth_scheme: (form_auth_scheme() != form_provider_type().default_auth_scheme()).then(|| form_auth_scheme()),
+        supports_tools: if form_supports_tools() { None } else { Some(false) },
+        prompt_caching: if form_prompt_caching() { None } else { Some(false) },
+        max_retries: form_max_retries().trim().parse().ok(),
+        connect_timeout_secs: form_connect_timeout().trim().parse().ok(),
+        read_timeout_secs: form_read_timeout().trim().parse().ok(),
+        prices: parse_prices(&form_prices()),
+        extra_headers: parse_headers(&form_extra_headers()),
+        record_fixture: Some(form_record_fixture().trim().to_string()).filter(|p| !p.is_empty()),
+        context_window: form_context_window().trim().parse().ok(),
+        generation: form_generation(),
+    };
+
+    // Models offered by the provider (cached in models.json)
+    let mut available_models = use_signal(|| None::<CachedModels>);
+    let mut models_error = use_signal(|| None::<String>);
+    let mut models_loading = use_signal(|| false);
+    // Set when saving a model that isn't in the list; a second click saves anyway
+    let mut model_warning = use_signal(|| None::<String>);
+    let key_source = (!form_api_key().trim().is_empty()).then(|| KeySource::parse(&form_api_key()));
+    let mut key_error = use_signal(|| None::<String>);
+
+    let mut fetch_models = move || {
+        models_loading.set(true);
+        // Read the form inside the task so the effect below doesn't subscribe to every field
+        spawn(async move {
+            let provider = form_provider();
+            match AiClient::list_models(&provider).await {
+                Ok(models) => {
+                    if let Err(e) = ModelCache::store(&provider.id, models) {
+                        eprintln!("[Settings] Failed to cache models: {}", e);
+                    }
+                    models_error.set(None);
+                    available_models.set(ModelCache::load().get(&provider.id).cloned());
+                }
+                Err(e) => models_error.set(Some(e.to_string())),
+            }
+            models_loading.set(false);
+        });
+    };
+
+    // Show the cached listing when the modal opens; local Ollama servers are cheap to query
+    use_effect(use_reactive!(|show| {
+        if show {
+            models_error.set(None);
+            model_warning.set(None);
+            let is_ollama = form_provider_type() == ProviderType::Ollama;
+            spawn(async move {
+                available_models.set(ModelCache::load().get(&form_provider().id).cloned());
+            });
+            if is_ollama {
+                fetch_models();
+            }
+        }
+    }));
+
     rsx! {
         Modal {
             show,
@@ -251,7 +753,7 @@ fn ProviderModal(
                     TextField {
                         label: "Provider Type".to_string(),
                         value: format!("{:?}", form_provider_type()),
-                        placeholder: "Claude, Kimi, MiniMax, GLM...".to_string(),
+                        placeholder: "Claude, Kimi, MiniMax, GLM, OpenAI, Ollama, Mock...".to_string(),
                         oninput: move |e: FormEvent| {
                             let type_str = e.value();
                             form_provider_type.set(match type_str.as_str() {
@@ -260,14 +762,50 @@ fn ProviderModal(
                                 "MiniMax" => ProviderType::MiniMax,
                                 "GLM" => ProviderType::GLM,
                                 "UltraThink" => ProviderType::UltraThink,
+                                "OpenAI" => ProviderType::OpenAI,
+                                "Ollama" => ProviderType::Ollama,
+                                "Mock" => ProviderType::Mock,
                                 _ => ProviderType::Claude,
                             });
                             let ptype = form_provider_type();
                             form_base_url.set(ptype.default_base_url().to_string());
                             form_model.set(ptype.default_model().to_string());
+                            form_api_format.set(ptype.default_api_format());
+                            form_auth_scheme.set(ptype.default_auth_scheme());
                         },
                     }
                 }
+                FormSection {
+                    title: "API Format".to_string(),
+                    description: "Wire protocol spoken by the endpoint".to_string(),
+                    select {
+                        class: "input-field",
+                        value: format!("{:?}", form_api_format()),
+                        onchange: move |e| {
+                            form_api_format.set(match e.value().as_str() {
+                                "OpenAi" => ApiFormat::OpenAi,
+                                "Ollama" => ApiFormat::Ollama,
+                                _ => ApiFormat::Anthropic,
+                            });
+                        },
+                        option { value: "Anthropic", {ApiFormat::Anthropic.label()} }
+                        option { value: "OpenAi", {ApiFormat::OpenAi.label()} }
+                        option { value: "Ollama", {ApiFormat::Ollama.label()} }
+                    }
+                }
+                FormSection {
+                    title: "Model".to_string(),
+                    description: "Pick from the provider's model list or type a model id".to_string(),
+                    ModelPicker {
+                        model: form_model,
+                        models: available_models().map(|cached| cached.models).unwrap_or_default(),
+                        fetched_at: available_models().map(|cached| cached.fetched_at),
+                        loading: models_loading(),
+                        error: models_error(),
+                        on_refresh: move |_| fetch_models(),
+                        on_change: move |_| model_warning.set(None),
+                    }
+                }
                 TextField {
                     label: "Display Name".to_string(),
                     icon: "📝".to_string(),
@@ -279,31 +817,180 @@ fn ProviderModal(
                     label: "API Key".to_string(),
                     icon: "🔑".to_string(),
                     value: form_api_key(),
-                    placeholder: "sk-ant-...".to_string(),
-                    helper: "(required for requests)".to_string(),
-                    input_type: "password".to_string(),
-                    oninput: move |e: FormEvent| form_api_key.set(e.value()),
+                    placeholder: "sk-ant-... or env:ANTHROPIC_API_KEY".to_string(),
+                    helper: match &key_source {
+                        Some(source) => format!("Source: {} {}", source.icon(), source.label()),
+                        None if form_provider_type().requires_api_key() => "(required for requests) A key, or a reference: env:NAME, file:/path, secret:NAME".to_string(),
+                        None => "(optional for local servers)".to_string(),
+                    },
+                    // References aren't secret, so show them
+                    input_type: if key_source.as_ref().is_some_and(|s| !s.is_plain()) { "text" } else { "password" }.to_string(),
+                    oninput: move |e: FormEvent| {
+                        key_error.set(None);
+                        form_api_key.set(e.value());
+                    },
+                }
+                if key_source.as_ref().is_some_and(|s| s.is_plain()) {
+                    div {
+                        class: "flex items-center justify-between gap-3 text-xs text-warning",
+                        span { "⚠️ Stored in plain text in config.json" }
+                        SecondaryButton {
+                            class: "px-3 py-1 text-xs".to_string(),
+                            disabled: !SecretStore::is_unlocked(),
+                            onclick: move |_| {
+                                let name = form_provider().id;
+                                match SecretStore::set(&name, form_api_key().trim()) {
+                                    Ok(()) => form_api_key.set(format!("secret:{}", name)),
+                                    Err(e) => key_error.set(Some(e.to_string())),
+                                }
+                            },
+                            if SecretStore::is_unlocked() { "🔐 Move to secrets store" } else { "🔐 Unlock the secrets store to move it" }
+                        }
+                    }
+                }
+                if let Some(e) = key_error() {
+                    p { class: "text-xs text-error", "{e}" }
+                }
+                FormSection {
+                    title: "Authentication".to_string(),
+                    description: "How the API key is sent".to_string(),
+                    div {
+                        class: "flex items-center gap-2",
+                        select {
+                            class: "input-field flex-1",
+                            value: match form_auth_scheme() {
+                                AuthScheme::Bearer => "bearer",
+                                AuthScheme::XApiKey => "x_api_key",
+                                AuthScheme::Header(_) => "header",
+                                AuthScheme::None => "none",
+                            },
+                            onchange: move |e| {
+                                form_auth_scheme.set(match e.value().as_str() {
+                                    "x_api_key" => AuthScheme::XApiKey,
+                                    "header" => AuthScheme::Header("api-key".to_string()),
+                                    "none" => AuthScheme::None,
+                                    _ => AuthScheme::Bearer,
+                                });
+                            },
+                            option { value: "bearer", "Authorization: Bearer <key>" }
+                            option { value: "x_api_key", "x-api-key: <key>" }
+                            option { value: "header", "Custom header" }
+                            option { value: "none", "None" }
+                        }
+                        if let AuthScheme::Header(name) = form_auth_scheme() {
+                            input {
+                                class: "input-field flex-1 font-mono",
+                                value: name,
+                                placeholder: "Header name, e.g. api-key",
+                                oninput: move |e| form_auth_scheme.set(AuthScheme::Header(e.value())),
+                            }
+                        }
+                    }
+                    if form_auth_scheme() != form_provider_type().default_auth_scheme() {
+                        p {
+                            class: "text-xs text-text-muted mt-1",
+                            {format!("Default for {:?}: {}", form_provider_type(), form_provider_type().default_auth_scheme().label())}
+                        }
+                    }
                 }
             }
             AdvancedSection {
+                TextField {
+                    label: "Base URL".to_string(),
+                    icon: "🌐".to_string(),
+                    value: form_base_url(),
+                    placeholder: "Auto-filled".to_string(),
+                    class: "text-text-secondary".to_string(),
+                    oninput: move |e: FormEvent| form_base_url.set(e.value()),
+                }
+                label {
+                    class: "flex items-center gap-2 cursor-pointer text-sm text-text-secondary hover:text-text-primary transition-colors",
+                    input {
+                        r#type: "checkbox",
+                        checked: form_supports_tools(),
+                        onchange: move |e| form_supports_tools.set(e.checked()),
+                        class: "w-4 h-4 text-primary bg-bg-surface border-border rounded focus:ring-primary focus:ring-2",
+                    }
+                    span { "Native tool calling (uncheck if the provider doesn't support tool_use)" }
+                }
+                if form_api_format() == ApiFormat::Anthropic {
+                    label {
+                        class: "flex items-center gap-2 cursor-pointer text-sm text-text-secondary hover:text-text-primary transition-colors",
+                        input {
+                            r#type: "checkbox",
+                            checked: form_prompt_caching(),
+                            onchange: move |e| form_prompt_caching.set(e.checked()),
+                            class: "w-4 h-4 text-primary bg-bg-surface border-border rounded focus:ring-primary focus:ring-2",
+                        }
+                        span { "Prompt caching (cache system prompt, tools and recent turns)" }
+                    }
+                }
                 div {
-                    class: "grid grid-cols-2 gap-4",
+                    class: "grid grid-cols-3 gap-4",
                     TextField {
-                        label: "Base URL".to_string(),
-                        icon: "🌐".to_string(),
-                        value: form_base_url(),
-                        placeholder: "Auto-filled".to_string(),
-                        class: "text-text-secondary".to_string(),
-                        oninput: move |e: FormEvent| form_base_url.set(e.value()),
+                        label: "Max Retries".to_string(),
+                        icon: "🔁".to_string(),
+                        value: form_max_retries(),
+                        placeholder: "2".to_string(),
+                        input_type: "number".to_string(),
+                        oninput: move |e: FormEvent| form_max_retries.set(e.value()),
                     }
                     TextField {
-                        label: "Model".to_string(),
-                        icon: "⚙️".to_string(),
-                        value: form_model(),
-                        placeholder: "Auto-filled".to_string(),
-                        class: "text-text-secondary".to_string(),
-                        oninput: move |e: FormEvent| form_model.set(e.value()),
+                        label: "Connect Timeout (s)".to_string(),
+                        icon: "⏱️".to_string(),
+                        value: form_connect_timeout(),
+                        placeholder: "15".to_string(),
+                        input_type: "number".to_string(),
+                        oninput: move |e: FormEvent| form_connect_timeout.set(e.value()),
                     }
+                    TextField {
+                        label: "Read Timeout (s)".to_string(),
+                        icon: "⏱️".to_string(),
+                        value: form_read_timeout(),
+                        placeholder: "120".to_string(),
+                        input_type: "number".to_string(),
+                        oninput: move |e: FormEvent| form_read_timeout.set(e.value()),
+                    }
+                }
+                TextField {
+                    label: "Context Window (tokens)".to_string(),
+                    icon: "📏".to_string(),
+                    value: form_context_window(),
+                    placeholder: ProviderConfig { context_window: None, ..form_provider() }.context_window().to_string(),
+                    input_type: "number".to_string(),
+                    helper: "Leave empty to use the model's known window; older turns are trimmed to fit".to_string(),
+                    oninput: move |e: FormEvent| form_context_window.set(e.value()),
+                }
+                GenerationParamsFields { params: form_generation }
+                TextArea {
+                    label: "Prices (USD per 1M tokens, one model per line)".to_string(),
+                    value: form_prices(),
+                    rows: 3,
+                    placeholder: "model = input, output[, cache write, cache read]".to_string(),
+                    helper: "Used to estimate the cost of each chat. Models without a price show tokens only.".to_string(),
+                    oninput: move |e: FormEvent| form_prices.set(e.value()),
+                }
+                TextArea {
+                    label: "Extra Headers (one per line)".to_string(),
+                    value: form_extra_headers(),
+                    rows: 2,
+                    placeholder: "Header-Name: value".to_string(),
+                    helper: "Sent with every request to this provider (e.g. gateway or organization headers)".to_string(),
+                    oninput: move |e: FormEvent| form_extra_headers.set(e.value()),
+                }
+                TextField {
+                    label: "Record Fixture".to_string(),
+                    icon: "⏺".to_string(),
+                    value: form_record_fixture(),
+                    placeholder: "fixtures/session.json".to_string(),
+                    helper: "Append every request and response to this file, for replay with a Mock provider".to_string(),
+                    oninput: move |e: FormEvent| form_record_fixture.set(e.value()),
+                }
+            }
+            if let Some(model) = model_warning() {
+                p {
+                    class: "px-6 pb-2 text-sm text-warning",
+                    "⚠️ \"{model}\" is not in this provider's model list. Check for typos, or refresh the list."
                 }
             }
             ModalFooter {
@@ -313,24 +1000,163 @@ fn ProviderModal(
                 }
                 PrimaryButton {
                     onclick: move |_| {
-                        let provider = ProviderConfig {
-                            id: if !form_id().is_empty() { form_id() } else { format!("{:?}", form_provider_type()).to_lowercase() },
-                            name: if form_name().is_empty() {
-                                format!("{:?}", form_provider_type())
-                            } else {
-                                form_name()
-                            },
-                            provider_type: form_provider_type(),
-                            api_key: if form_api_key().is_empty() { None } else { Some(form_api_key()) },
-                            base_url: if form_base_url().is_empty() { None } else { Some(form_base_url()) },
-                            model: if form_model().is_empty() { None } else { Some(form_model()) },
-                            enabled: true,
-                        };
+                        let provider = form_provider();
+                        let model = provider.model.clone().unwrap_or_default();
+                        let unknown = ModelCache::load().is_unknown_model(&provider.id, &model);
+                        if unknown && model_warning().is_none() {
+                            model_warning.set(Some(model));
+                            return;
+                        }
                         onsave.call(provider);
                     },
-                    "💾 Save Provider"
+                    if model_warning().is_some() { "💾 Save Anyway" } else { "💾 Save Provider" }
                 }
             }
         }
     }
 }
+
+/// Searchable model picker: free text input filtering the provider's model list
+#[component]
+fn ModelPicker(
+    mut model: Signal<String>,
+    models: Vec<String>,
+    fetched_at: Option<u64>,
+    loading: bool,
+    error: Option<String>,
+    on_refresh: EventHandler<()>,
+    on_change: EventHandler<()>,
+) -> Element {
+    let mut open = use_signal(|| false);
+    let query = model().to_lowercase();
+    let matches: Vec<String> = models
+        .iter()
+        .filter(|m| m.to_lowercase().contains(&query))
+        .take(50)
+        .cloned()
+        .collect();
+    let total = models.len();
+
+    rsx! {
+        div {
+            class: "relative",
+            div {
+                class: "flex items-center gap-2",
+                input {
+                    class: "input-field flex-1 font-mono",
+                    value: model(),
+                    placeholder: "Search or enter a model id",
+                    onfocus: move |_| open.set(true),
+                    oninput: move |e| {
+                        model.set(e.value());
+                        open.set(true);
+                        on_change.call(());
+                    },
+                    onkeydown: move |e| {
+                        if e.key() == Key::Escape || e.key() == Key::Enter {
+                            open.set(false);
+                        }
+                    },
+                }
+                SecondaryButton {
+                    disabled: loading,
+                    onclick: move |_| on_refresh.call(()),
+                    if loading { "Loading..." } else { "🔄 Fetch models" }
+                }
+            }
+            if open() && !matches.is_empty() {
+                div {
+                    class: "absolute z-20 left-0 right-0 mt-1 max-h-56 overflow-y-auto bg-bg-surface border border-border rounded-lg shadow-custom",
+                    for m in matches {
+                        button {
+                            key: "{m}",
+                            class: "block w-full text-left px-3 py-1.5 text-sm font-mono text-text-primary hover:bg-bg-secondary",
+                            onclick: {
+                                let m = m.clone();
+                                move |_| {
+                                    model.set(m.clone());
+                                    open.set(false);
+                                    on_change.call(());
+                                }
+                            },
+                            "{m}"
+                        }
+                    }
+                }
+            }
+            if let Some(error) = error {
+                p { class: "text-xs text-error mt-1", "Could not fetch models: {error}" }
+            } else if let Some(fetched_at) = fetched_at {
+                p {
+                    class: "text-xs text-text-muted mt-1",
+                    {format!("{} models · fetched {}", total, format_fetched_at(fetched_at))}
+                }
+            }
+        }
+    }
+}
+
+/// Format a fetch timestamp as local date and time
+fn format_fetched_at(timestamp: u64) -> String {
+    use chrono::{DateTime, Local, Utc};
+    DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+        .map(|utc| DateTime::<Local>::from(utc).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Format extra headers as `Name: value` lines
+pub(super) fn format_headers(headers: &std::collections::HashMap<String, String>) -> String {
+    let mut lines: Vec<String> = headers.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+    lines.sort();
+    lines.join("\n")
+}
+
+/// Parse `Name: value` header lines (lines without a name are skipped)
+pub(super) fn parse_headers(text: &str) -> std::collections::HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let name = name.trim();
+            (!name.is_empty()).then(|| (name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Format a price table as `model = input, output[, cache write, cache read]` lines
+fn format_prices(prices: &std::collections::HashMap<String, ModelPrice>) -> String {
+    let mut lines: Vec<String> = prices
+        .iter()
+        .map(|(model, p)| {
+            let mut values = vec![p.input, p.output];
+            if p.cache_write.is_some() || p.cache_read.is_some() {
+                values.push(p.cache_write.unwrap_or(p.input));
+                values.push(p.cache_read.unwrap_or(p.input));
+            }
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            format!("{} = {}", model, values.join(", "))
+        })
+        .collect();
+    lines.sort();
+    lines.join("\n")
+}
+
+/// Parse price table lines (invalid lines are skipped)
+fn parse_prices(text: &str) -> std::collections::HashMap<String, ModelPrice> {
+    text.lines()
+        .filter_map(|line| {
+            let (model, values) = line.split_once('=')?;
+            let values: Vec<f64> = values
+                .split(',')
+                .map(|v| v.trim().parse().ok())
+                .collect::<Option<_>>()?;
+            let price = ModelPrice {
+                input: *values.first()?,
+                output: *values.get(1)?,
+                cache_write: values.get(2).copied(),
+                cache_read: values.get(3).copied(),
+            };
+            Some((model.trim().to_string(), price))
+        })
+        .filter(|(model, _)| !model.is_empty())
+        .collect()
+}
@@ -2,8 +2,11 @@
 //! MCP 服务器配置标签页
 
 use dioxus::prelude::*;
-use crate::config::{AppConfig, McpServerConfig};
+use crate::config::{AppConfig, McpServerConfig, McpTransport};
+use crate::services::mcp_manager;
 use crate::components::ui::*;
+use super::ai_providers::{format_headers, parse_headers};
+use std::collections::HashMap;
 
 /// MCP Servers tab content
 #[component]
@@ -14,6 +17,9 @@ pub fn McpServersTab(
     mut server_form_name: Signal<String>,
     mut server_form_command: Signal<String>,
     mut server_form_args: Signal<String>,
+    mut server_form_transport: Signal<McpTransport>,
+    mut server_form_url: Signal<String>,
+    mut server_form_headers: Signal<String>,
 ) -> Element {
     let servers_list = mcp_servers();
     let server_is_adding = move || editing_server().as_ref().map_or(false, |id| id.is_empty());
@@ -40,6 +46,9 @@ pub fn McpServersTab(
                         server_form_name.set(String::new());
                         server_form_command.set(String::new());
                         server_form_args.set(String::new());
+                        server_form_transport.set(McpTransport::Stdio);
+                        server_form_url.set(String::new());
+                        server_form_headers.set(String::new());
                     },
                     "＋ Add Server"
                 }
@@ -62,9 +71,15 @@ pub fn McpServersTab(
                                 SecondaryButton {
                                     class: "px-2 py-1 text-xs".to_string(),
                                     onclick: {
-                                        let sname = server.name.clone();
+                                        let server = server.clone();
                                         move |_| {
-                                            editing_server.set(Some(sname.clone()));
+                                            editing_server.set(Some(server.name.clone()));
+                                            server_form_name.set(server.name.clone());
+                                            server_form_command.set(server.command.clone());
+                                            server_form_args.set(server.args.join("\n"));
+                                            server_form_transport.set(server.transport.clone());
+                                            server_form_url.set(server.transport.url().unwrap_or_default().to_string());
+                                            server_form_headers.set(server.transport.headers().map(format_headers).unwrap_or_default());
                                         }
                                     },
                                     "Edit"
@@ -82,6 +97,7 @@ pub fn McpServersTab(
                                                 if let Err(e) = config.save() {
                                                     eprintln!("[Settings] Failed to save server deletion: {}", e);
                                                 }
+                                                mcp_manager::sync(&config.mcp, &config.network);
                                                 servers.set(config.mcp.servers.clone());
                                             }
                                         }
@@ -92,14 +108,25 @@ pub fn McpServersTab(
                         }
                         div {
                             class: "grid grid-cols-2 gap-4 text-sm",
-                            div {
-                                class: "text-text-muted",
-                                "Command: {server.command}"
-                            }
-                            if !server.args.is_empty() {
+                            if let Some(url) = server.transport.url() {
+                                div {
+                                    class: "text-text-muted truncate",
+                                    "URL: {url}"
+                                }
+                                div {
+                                    class: "text-text-muted",
+                                    {server.transport.label()}
+                                }
+                            } else {
                                 div {
                                     class: "text-text-muted",
-                                    "Args: {join_args(&server.args)}"
+                                    "Command: {server.command}"
+                                }
+                                if !server.args.is_empty() {
+                                    div {
+                                        class: "text-text-muted",
+                                        "Args: {join_args(&server.args)}"
+                                    }
                                 }
                             }
                         }
@@ -121,6 +148,7 @@ pub fn McpServersTab(
                                                 if let Err(err) = config.save() {
                                                     eprintln!("[Settings] Failed to save server toggle: {}", err);
                                                 }
+                                                mcp_manager::sync(&config.mcp, &config.network);
                                                 servers.set(config.mcp.servers.clone());
                                             }
                                         }
@@ -154,18 +182,57 @@ pub fn McpServersTab(
                         placeholder: "My Server".to_string(),
                         oninput: move |e: FormEvent| server_form_name.set(e.value()),
                     }
-                    TextField {
-                        label: "Command".to_string(),
-                        value: server_form_command(),
-                        placeholder: "npx".to_string(),
-                        oninput: move |e: FormEvent| server_form_command.set(e.value()),
+                    FormSection {
+                        title: "Transport".to_string(),
+                        description: "How to reach the server".to_string(),
+                        select {
+                            class: "input-field",
+                            value: match server_form_transport() {
+                                McpTransport::Stdio => "stdio",
+                                McpTransport::StreamableHttp { .. } => "streamable_http",
+                                McpTransport::Sse { .. } => "sse",
+                            },
+                            onchange: move |e| {
+                                server_form_transport.set(match e.value().as_str() {
+                                    "streamable_http" => McpTransport::StreamableHttp { url: String::new(), headers: HashMap::new() },
+                                    "sse" => McpTransport::Sse { url: String::new(), headers: HashMap::new() },
+                                    _ => McpTransport::Stdio,
+                                });
+                            },
+                            option { value: "stdio", "Local process (stdio)" }
+                            option { value: "streamable_http", "Streamable HTTP" }
+                            option { value: "sse", "HTTP + SSE (legacy)" }
+                        }
                     }
-                    TextArea {
-                        label: "Args (one per line)".to_string(),
-                        value: server_form_args(),
-                        rows: 3,
-                        placeholder: "arg1\narg2".to_string(),
-                        oninput: move |e: FormEvent| server_form_args.set(e.value()),
+                    if server_form_transport() == McpTransport::Stdio {
+                        TextField {
+                            label: "Command".to_string(),
+                            value: server_form_command(),
+                            placeholder: "npx".to_string(),
+                            oninput: move |e: FormEvent| server_form_command.set(e.value()),
+                        }
+                        TextArea {
+                            label: "Args (one per line)".to_string(),
+                            value: server_form_args(),
+                            rows: 3,
+                            placeholder: "arg1\narg2".to_string(),
+                            oninput: move |e: FormEvent| server_form_args.set(e.value()),
+                        }
+                    } else {
+                        TextField {
+                            label: "URL".to_string(),
+                            value: server_form_url(),
+                            placeholder: "https://mcp.example.com/mcp".to_string(),
+                            oninput: move |e: FormEvent| server_form_url.set(e.value()),
+                        }
+                        TextArea {
+                            label: "Headers (one per line)".to_string(),
+                            value: server_form_headers(),
+                            rows: 2,
+                            placeholder: "Authorization: Bearer secret:mcp-token".to_string(),
+                            helper: "Values may reference env:NAME, file:/path or secret:NAME".to_string(),
+                            oninput: move |e: FormEvent| server_form_headers.set(e.value()),
+                        }
                     }
                 }
                 ModalFooter {
@@ -176,8 +243,16 @@ pub fn McpServersTab(
                     PrimaryButton {
                         onclick: move |_| {
                             let args: Vec<String> = server_form_args().lines().map(|s| s.to_string()).filter(|s| !s.is_empty()).collect();
+                            let url = server_form_url().trim().to_string();
+                            let headers = parse_headers(&server_form_headers());
+                            let transport = match server_form_transport() {
+                                McpTransport::Stdio => McpTransport::Stdio,
+                                McpTransport::StreamableHttp { .. } => McpTransport::StreamableHttp { url, headers },
+                                McpTransport::Sse { .. } => McpTransport::Sse { url, headers },
+                            };
                             let new_server = McpServerConfig {
                                 name: if server_form_name().is_empty() { "New Server".to_string() } else { server_form_name() },
+                                transport,
                                 command: server_form_command(),
                                 args,
                                 env: None,
@@ -188,11 +263,13 @@ pub fn McpServersTab(
                                 if server_is_adding() {
                                     config.mcp.servers.push(new_server);
                                 } else if let Some(s) = config.mcp.servers.iter_mut().find(|s| s.name == editing_server().unwrap_or_default()) {
-                                    *s = new_server;
+                                    // Environment and enabled state are not edited here
+                                    *s = McpServerConfig { env: s.env.take(), enabled: s.enabled, ..new_server };
                                 }
                                 if let Err(e) = config.save() {
                                     eprintln!("[Settings] Failed to save server update: {}", e);
                                 }
+                                mcp_manager::sync(&config.mcp, &config.network);
                                 mcp_servers.set(config.mcp.servers.clone());
                             }
 
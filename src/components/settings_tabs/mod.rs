@@ -4,10 +4,12 @@
 pub mod ai_providers;
 pub mod mcp_servers;
 pub mod appearance;
+pub mod network;
 pub mod shortcuts;
 
 // Re-export tab components
 pub use ai_providers::AiProvidersTab;
 pub use mcp_servers::McpServersTab;
 pub use appearance::AppearanceTab;
+pub use network::NetworkTab;
 pub use shortcuts::ShortcutsTab;
@@ -0,0 +1,117 @@
+//! Network tab component
+//! 网络设置标签页 - 代理、超时与 CA 证书
+
+use dioxus::prelude::*;
+use crate::config::{AppConfig, NetworkConfig};
+use crate::services::mcp_manager;
+use crate::components::ui::*;
+
+/// Network tab content
+#[component]
+pub fn NetworkTab() -> Element {
+    let mut network = use_signal(|| AppConfig::load().map(|c| c.network).unwrap_or_default());
+    let mut saved = use_signal(|| false);
+
+    let current = network();
+    let mut edit = move |update: &dyn Fn(&mut NetworkConfig)| {
+        update(&mut *network.write());
+        saved.set(false);
+    };
+    let optional = |value: String| {
+        let value = value.trim().to_string();
+        (!value.is_empty()).then_some(value)
+    };
+
+    rsx! {
+        div {
+            class: "space-y-6",
+            h1 {
+                class: "text-2xl font-semibold text-text-primary",
+                "Network"
+            }
+
+            section {
+                class: "bg-bg-surface border border-border rounded-lg p-6 space-y-4",
+                h2 {
+                    class: "text-lg text-text-primary mb-4",
+                    "Proxy"
+                }
+                TextField {
+                    label: "Proxy URL".to_string(),
+                    icon: "🌐".to_string(),
+                    value: current.proxy_url.clone().unwrap_or_default(),
+                    placeholder: "http://proxy.example.com:8080".to_string(),
+                    helper: "Empty = use the system proxy settings (HTTPS_PROXY etc.)".to_string(),
+                    oninput: move |e: FormEvent| edit(&|n| n.proxy_url = optional(e.value())),
+                }
+                TextField {
+                    label: "No Proxy".to_string(),
+                    value: current.no_proxy.join(", "),
+                    placeholder: "localhost, 127.0.0.1, .internal.example.com".to_string(),
+                    helper: "Comma-separated hosts, domains or CIDR ranges that bypass the proxy".to_string(),
+                    oninput: move |e: FormEvent| {
+                        let hosts: Vec<String> = e.value().split(',').map(|h| h.trim().to_string()).filter(|h| !h.is_empty()).collect();
+                        edit(&|n| n.no_proxy = hosts.clone());
+                    },
+                }
+            }
+
+            section {
+                class: "bg-bg-surface border border-border rounded-lg p-6 space-y-4",
+                h2 {
+                    class: "text-lg text-text-primary mb-4",
+                    "Timeouts & Certificates"
+                }
+                div {
+                    class: "grid grid-cols-2 gap-4",
+                    TextField {
+                        label: "Connect Timeout (s)".to_string(),
+                        icon: "⏱️".to_string(),
+                        value: current.connect_timeout_secs.map(|n| n.to_string()).unwrap_or_default(),
+                        placeholder: NetworkConfig::DEFAULT_CONNECT_TIMEOUT_SECS.to_string(),
+                        input_type: "number".to_string(),
+                        oninput: move |e: FormEvent| edit(&|n| n.connect_timeout_secs = e.value().trim().parse().ok()),
+                    }
+                    TextField {
+                        label: "Read Timeout (s)".to_string(),
+                        icon: "⏱️".to_string(),
+                        value: current.read_timeout_secs.map(|n| n.to_string()).unwrap_or_default(),
+                        placeholder: NetworkConfig::DEFAULT_READ_TIMEOUT_SECS.to_string(),
+                        input_type: "number".to_string(),
+                        oninput: move |e: FormEvent| edit(&|n| n.read_timeout_secs = e.value().trim().parse().ok()),
+                    }
+                }
+                p {
+                    class: "text-xs text-text-muted",
+                    "Defaults for all providers; a provider's own timeouts take precedence."
+                }
+                TextField {
+                    label: "Extra CA Bundle".to_string(),
+                    icon: "🔒".to_string(),
+                    value: current.ca_bundle_path.clone().unwrap_or_default(),
+                    placeholder: "/etc/ssl/certs/corporate-ca.pem".to_string(),
+                    helper: "PEM file with additional root certificates to trust".to_string(),
+                    oninput: move |e: FormEvent| edit(&|n| n.ca_bundle_path = optional(e.value())),
+                }
+            }
+
+            div {
+                class: "flex items-center justify-end gap-3",
+                if saved() {
+                    span { class: "text-sm text-success", "✓ Saved" }
+                }
+                PrimaryButton {
+                    onclick: move |_| {
+                        if let Ok(mut config) = AppConfig::load() {
+                            config.update_network(network());
+                            // Reconnect remote MCP servers with the new settings
+                            mcp_manager::sync(&config.mcp, &config.network);
+                            saved.set(true);
+                        }
+                    },
+                    "💾 Save"
+                }
+            }
+        }
+    }
+}
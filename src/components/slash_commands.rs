@@ -0,0 +1,313 @@
+//! Slash commands for MCP prompts
+//! 斜杠命令 - 将 MCP 提示词作为 /服务:提示词 命令，自动补全并生成参数表单
+
+use dioxus::prelude::*;
+use std::collections::HashMap;
+use crate::chat_history::{Attachment, ResourceSource};
+use crate::components::chat::InsertedMessage;
+use crate::components::resource_picker::store_contents;
+use crate::components::ui::{CancelButton, FormSection, Modal, ModalContent, ModalFooter, ModalHeader, PrimaryButton};
+use crate::services::mcp_client::{McpPrompt, McpPromptContent, McpPromptMessage, McpResourceContents};
+use crate::services::mcp_manager;
+
+/// MCP prompt invoked as `/server:prompt`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptCommand {
+    pub server: String,
+    pub prompt: McpPrompt,
+}
+
+impl PromptCommand {
+    pub fn command(&self) -> String {
+        format!("/{}:{}", self.server, self.prompt.name)
+    }
+}
+
+/// Commands completing the input, prefix matches first
+///
+/// The menu only opens for a single word starting with `/`.
+fn matching(commands: &[PromptCommand], input: &str) -> Vec<PromptCommand> {
+    let Some(query) = input.strip_prefix('/') else {
+        return Vec::new();
+    };
+    if query.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+    let query = query.to_lowercase();
+    let name = |c: &PromptCommand| format!("{}:{}", c.server, c.prompt.name).to_lowercase();
+    let mut matches: Vec<PromptCommand> = commands.iter().filter(|c| name(c).contains(&query)).cloned().collect();
+    matches.sort_by_key(|c| !name(c).starts_with(&query));
+    matches
+}
+
+/// Text and attachment of one prompt message (None for content that can't be sent)
+fn prompt_content(server: &str, content: McpPromptContent) -> Result<Option<(Option<String>, Option<Attachment>)>, String> {
+    Ok(Some(match content {
+        McpPromptContent::Text { text } => (Some(text), None),
+        McpPromptContent::Image { data, mime_type } => {
+            let name = format!("prompt-image.{}", mime_type.rsplit('/').next().unwrap_or("bin"));
+            let image = McpResourceContents { uri: name.clone(), mime_type: Some(mime_type), text: None, blob: Some(data) };
+            (None, Some(store_contents(&name, &image)?))
+        }
+        McpPromptContent::Resource { resource } => {
+            let name = resource.uri.rsplit('/').find(|s| !s.is_empty()).unwrap_or(&resource.uri).to_string();
+            let mut attachment = store_contents(&name, &resource)?;
+            attachment.resource = Some(ResourceSource { server: server.to_string(), uri: resource.uri });
+            (None, Some(attachment))
+        }
+        McpPromptContent::Unsupported => {
+            eprintln!("[MCP] Skipping unsupported prompt content from {}", server);
+            return Ok(None);
+        }
+    }))
+}
+
+/// Conversation messages of a prompt; consecutive messages of one role are merged
+fn inserted_messages(server: &str, messages: Vec<McpPromptMessage>) -> Result<Vec<InsertedMessage>, String> {
+    let mut inserted: Vec<InsertedMessage> = Vec::new();
+    for McpPromptMessage { role, content } in messages {
+        let (text, attachment) = match prompt_content(server, content) {
+            Ok(Some(content)) => content,
+            Ok(None) => continue,
+            Err(e) => {
+                inserted.iter().flat_map(|m| &m.attachments).for_each(Attachment::remove);
+                return Err(e);
+            }
+        };
+        if inserted.last().is_none_or(|last| last.role != role) {
+            inserted.push(InsertedMessage { role, content: String::new(), attachments: Vec::new() });
+        }
+        let message = inserted.last_mut().expect("pushed above");
+        if let Some(text) = text {
+            if !message.content.is_empty() {
+                message.content.push_str("\n\n");
+            }
+            message.content.push_str(&text);
+        }
+        message.attachments.extend(attachment);
+    }
+    Ok(inserted)
+}
+
+/// Slash command state of one input
+#[derive(Clone, Copy, PartialEq)]
+pub struct SlashCommands {
+    input_text: Signal<String>,
+    commands: Signal<Vec<PromptCommand>>,
+    highlighted: Signal<usize>,
+    /// Input for which the menu was closed with Escape
+    dismissed: Signal<Option<String>>,
+    /// Command whose argument form is open, with the values entered so far
+    form: Signal<Option<(PromptCommand, HashMap<String, String>)>>,
+    busy: Signal<bool>,
+    error: Signal<Option<String>>,
+    on_insert: Callback<Vec<InsertedMessage>>,
+}
+
+/// Hook offering the prompts of the connected MCP servers as slash commands in `input_text`
+///
+/// `on_insert` receives the messages of a prompt once it has been run.
+pub fn use_slash_commands(
+    input_text: Signal<String>,
+    on_insert: impl FnMut(Vec<InsertedMessage>) + 'static,
+) -> SlashCommands {
+    let mut commands = use_signal(Vec::new);
+    // Reload whenever a server starts, stops or restarts
+    use_future(move || async move {
+        let mut status = mcp_manager::status();
+        loop {
+            let prompts = mcp_manager::prompts().await;
+            commands.set(
+                prompts
+                    .into_iter()
+                    .flat_map(|s| {
+                        let server = s.server;
+                        s.prompts.into_iter().map(move |prompt| PromptCommand { server: server.clone(), prompt })
+                    })
+                    .collect(),
+            );
+            if status.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    SlashCommands {
+        input_text,
+        commands,
+        highlighted: use_signal(|| 0),
+        dismissed: use_signal(|| None),
+        form: use_signal(|| None),
+        busy: use_signal(|| false),
+        error: use_signal(|| None),
+        on_insert: use_callback(on_insert),
+    }
+}
+
+impl SlashCommands {
+    /// Commands completing the input (empty while the menu is closed)
+    pub fn matches(&self) -> Vec<PromptCommand> {
+        let input = (self.input_text)();
+        if (self.dismissed)().as_ref() == Some(&input) {
+            return Vec::new();
+        }
+        matching(&(self.commands)(), &input)
+    }
+
+    /// Handle a key pressed in the input; returns true if the menu used it
+    pub fn handle_key(&mut self, key: &Key) -> bool {
+        let matches = self.matches();
+        if matches.is_empty() {
+            return false;
+        }
+        let highlighted = (self.highlighted)().min(matches.len() - 1);
+        match key {
+            Key::ArrowDown => self.highlighted.set((highlighted + 1) % matches.len()),
+            Key::ArrowUp => self.highlighted.set((highlighted + matches.len() - 1) % matches.len()),
+            Key::Enter | Key::Tab => self.select(matches[highlighted].clone()),
+            Key::Escape => self.dismissed.set(Some((self.input_text)())),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Complete the input with a command and run it, asking for its arguments first if it has any
+    pub fn select(&mut self, command: PromptCommand) {
+        self.highlighted.set(0);
+        self.error.set(None);
+        self.input_text.set(command.command());
+        if command.prompt.arguments.is_empty() {
+            self.run(command, HashMap::new());
+        } else {
+            self.form.set(Some((command, HashMap::new())));
+        }
+    }
+
+    /// Get the prompt and hand its messages to `on_insert`
+    fn run(&self, command: PromptCommand, mut arguments: HashMap<String, String>) {
+        let mut this = *self;
+        arguments.retain(|_, value| !value.trim().is_empty());
+        this.busy.set(true);
+        spawn(async move {
+            let result = match mcp_manager::get_prompt(&command.server, &command.prompt.name, &arguments).await {
+                Ok(messages) => inserted_messages(&command.server, messages),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(messages) => {
+                    this.form.set(None);
+                    this.input_text.set(String::new());
+                    this.on_insert.call(messages);
+                }
+                Err(e) => this.error.set(Some(e)),
+            }
+            this.busy.set(false);
+        });
+    }
+}
+
+/// Completion list for the input, and the argument form of the selected command
+#[component]
+pub fn SlashCommandMenu(commands: SlashCommands, #[props(default)] class: String) -> Element {
+    let mut commands = commands;
+    let matches = commands.matches();
+    let highlighted = (commands.highlighted)().min(matches.len().saturating_sub(1));
+    let busy = (commands.busy)();
+    let error = (commands.error)();
+    let form = (commands.form)();
+
+    rsx! {
+        // Errors of a command with arguments are shown in its form
+        if !matches.is_empty() || (form.is_none() && (busy || error.is_some())) {
+            div {
+                class: "bg-bg-surface border border-border rounded-lg shadow-lg p-1 max-h-64 overflow-y-auto {class}",
+                for (index, command) in matches.into_iter().enumerate() {
+                    CommandRow {
+                        key: "{command.command()}",
+                        command: command.clone(),
+                        highlighted: index == highlighted,
+                        onclick: move |_| commands.select(command.clone()),
+                    }
+                }
+                if form.is_none() {
+                    if busy {
+                        p { class: "px-3 py-1.5 text-xs text-text-secondary", "Running prompt..." }
+                    }
+                    if let Some(e) = error.clone() {
+                        p { class: "px-3 py-1.5 text-xs text-error", "{e}" }
+                    }
+                }
+            }
+        }
+        if let Some((command, values)) = form {
+            Modal {
+                show: true,
+                onclose: move |_| commands.form.set(None),
+                max_width: "520px",
+                ModalHeader {
+                    title: command.command(),
+                    subtitle: command.prompt.description.clone().unwrap_or_default(),
+                    icon: "💬",
+                    show_close: true,
+                    onclose: move |_| commands.form.set(None),
+                }
+                ModalContent {
+                    for argument in command.prompt.arguments.clone() {
+                        FormSection {
+                            key: "{argument.name}",
+                            title: if argument.required { format!("{} *", argument.name) } else { argument.name.clone() },
+                            description: argument.description.clone().unwrap_or_default(),
+                            input {
+                                class: "input-field",
+                                value: values.get(&argument.name).cloned().unwrap_or_default(),
+                                oninput: move |e| {
+                                    if let Some((_, values)) = commands.form.write().as_mut() {
+                                        values.insert(argument.name.clone(), e.value());
+                                    }
+                                },
+                            }
+                        }
+                    }
+                    if let Some(e) = error {
+                        p { class: "text-xs text-error", "{e}" }
+                    }
+                }
+                ModalFooter {
+                    CancelButton {
+                        onclick: move |_| commands.form.set(None),
+                        "Cancel"
+                    }
+                    PrimaryButton {
+                        disabled: busy || command.prompt.arguments.iter().any(|a| {
+                            a.required && values.get(&a.name).is_none_or(|v| v.trim().is_empty())
+                        }),
+                        onclick: {
+                            let (command, values) = (command.clone(), values.clone());
+                            move |_| commands.run(command.clone(), values.clone())
+                        },
+                        if busy { "Running..." } else { "Run" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Command in the completion list
+#[component]
+fn CommandRow(command: PromptCommand, highlighted: bool, onclick: EventHandler<MouseEvent>) -> Element {
+    let background = if highlighted { "bg-bg-secondary" } else { "hover:bg-bg-secondary" };
+    rsx! {
+        button {
+            class: "w-full flex items-baseline gap-3 px-3 py-1.5 rounded-md text-left transition-colors {background}",
+            // Keep the focus in the input
+            onmousedown: move |e| e.prevent_default(),
+            onclick: move |e| onclick.call(e),
+            span { class: "font-mono text-sm text-text-primary shrink-0", "{command.command()}" }
+            if let Some(description) = &command.prompt.description {
+                span { class: "text-xs text-text-secondary truncate", "{description}" }
+            }
+        }
+    }
+}
+
@@ -2,10 +2,17 @@
 //! Provides unified configuration loading, saving, and management
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use dirs;
 
+pub mod models;
+pub mod secrets;
+
+pub use models::{CachedModels, ModelCache};
+pub use secrets::{KeySource, SecretError, SecretStore};
+
 /// Application configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -14,6 +21,29 @@ pub struct AppConfig {
     pub mcp: McpConfig,
     pub shortcuts: ShortcutConfig,
     pub ui: UiConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+}
+
+/// HTTP transport settings shared by all providers
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Proxy for all requests, e.g. `http://proxy.corp:8080` (None = system proxy settings)
+    pub proxy_url: Option<String>,
+    /// Hosts that bypass the proxy (domains, IPs or CIDR ranges)
+    pub no_proxy: Vec<String>,
+    /// Default connection timeout in seconds (providers may override; None = 15)
+    pub connect_timeout_secs: Option<u64>,
+    /// Default max silence between response chunks in seconds (providers may override; None = 120)
+    pub read_timeout_secs: Option<u64>,
+    /// PEM bundle with extra CA certificates to trust (e.g. a corporate CA)
+    pub ca_bundle_path: Option<String>,
+}
+
+impl NetworkConfig {
+    pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 15;
+    pub const DEFAULT_READ_TIMEOUT_SECS: u64 = 120;
 }
 
 /// Theme configuration
@@ -27,6 +57,54 @@ pub struct ThemeConfig {
 pub struct AiConfig {
     pub providers: Vec<ProviderConfig>,
     pub active_provider: Option<String>,
+    /// Provider ids tried in order when the active provider fails with a retryable error
+    #[serde(default)]
+    pub fallback_providers: Vec<String>,
+    /// How long sessions are fitted into the model's context window
+    #[serde(default)]
+    pub context: ContextConfig,
+}
+
+/// What happens to older turns when a session exceeds the context budget
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextPolicy {
+    /// Drop the oldest turns
+    #[default]
+    SlidingWindow,
+    /// Replace the oldest turns with an AI-written summary
+    SummarizeOlder,
+    /// Keep the first messages (e.g. the task description) and drop the turns after them
+    PinFirst,
+}
+
+impl ContextPolicy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ContextPolicy::SlidingWindow => "Sliding window",
+            ContextPolicy::SummarizeOlder => "Summarize older turns",
+            ContextPolicy::PinFirst => "Pin first messages",
+        }
+    }
+}
+
+/// Context window management for long sessions
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub policy: ContextPolicy,
+    /// Messages always kept by the pin-first policy (None = 2)
+    pub pinned_messages: Option<usize>,
+    /// Token budget for the conversation (None = derived from the model's context window)
+    pub max_context_tokens: Option<u64>,
+}
+
+impl ContextConfig {
+    pub const DEFAULT_PINNED_MESSAGES: usize = 2;
+
+    pub fn pinned_messages(&self) -> usize {
+        self.pinned_messages.unwrap_or(Self::DEFAULT_PINNED_MESSAGES)
+    }
 }
 
 /// MCP (Model Context Protocol) configuration
@@ -42,6 +120,7 @@ impl Default for McpConfig {
             servers: vec![
                 McpServerConfig {
                     name: "Context7".to_string(),
+                    transport: McpTransport::Stdio,
                     command: "npx".to_string(),
                     args: vec!["-y".to_string(), "@upstash/context7-mcp@latest".to_string()],
                     env: None,
@@ -56,22 +135,246 @@ impl Default for McpConfig {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct McpServerConfig {
     pub name: String,
+    /// How to reach the server (stdio servers use `command`, `args` and `env`)
+    #[serde(default)]
+    pub transport: McpTransport,
+    #[serde(default)]
     pub command: String,
+    #[serde(default)]
     pub args: Vec<String>,
     pub env: Option<std::collections::HashMap<String, String>>,
     pub enabled: bool,
 }
 
+/// Transport of an MCP server
+///
+/// Header values may be key references (`env:NAME`, `file:/path`, `secret:NAME`),
+/// optionally after a scheme, e.g. `Bearer secret:mcp-token`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum McpTransport {
+    /// Local process speaking line-delimited JSON-RPC on stdin/stdout
+    #[default]
+    Stdio,
+    /// Streamable HTTP: every message is POSTed to `url`
+    StreamableHttp {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    /// Legacy HTTP+SSE: an event stream at `url` names the endpoint messages are POSTed to
+    Sse {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+}
+
+impl McpTransport {
+    pub fn label(&self) -> &'static str {
+        match self {
+            McpTransport::Stdio => "Local process (stdio)",
+            McpTransport::StreamableHttp { .. } => "Streamable HTTP",
+            McpTransport::Sse { .. } => "HTTP + SSE (legacy)",
+        }
+    }
+
+    /// URL of an HTTP transport
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            McpTransport::Stdio => None,
+            McpTransport::StreamableHttp { url, .. } | McpTransport::Sse { url, .. } => Some(url),
+        }
+    }
+
+    /// Headers of an HTTP transport
+    pub fn headers(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            McpTransport::Stdio => None,
+            McpTransport::StreamableHttp { headers, .. } | McpTransport::Sse { headers, .. } => Some(headers),
+        }
+    }
+}
+
 /// Individual AI provider configuration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProviderConfig {
     pub id: String,
     pub name: String,
     pub provider_type: ProviderType,
+    /// API key, or a reference: `env:NAME`, `file:/path` or `secret:NAME`
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub model: Option<String>,
     pub enabled: bool,
+    /// Wire protocol spoken by the provider endpoint
+    #[serde(default)]
+    pub api_format: ApiFormat,
+    /// Native `tool_use` support (None = assume supported).
+    /// Set to false to fall back to prompt-based tool calls.
+    #[serde(default)]
+    pub supports_tools: Option<bool>,
+    /// Retries on rate limits, overload, 5xx and connection errors (None = 2)
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    /// Connection timeout in seconds (None = network default)
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Max silence between response chunks in seconds (None = network default)
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+    /// Per-model prices used to estimate cost (model name -> price)
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+    /// Default generation parameters (sessions may override them)
+    #[serde(default)]
+    pub generation: GenerationParams,
+    /// How the API key is sent (None = provider type default)
+    #[serde(default)]
+    pub auth_scheme: Option<AuthScheme>,
+    /// Extra HTTP headers sent with every request to this provider
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    /// Mark system prompt, tools and recent turns with `cache_control` (None = enabled).
+    /// Only used by the Anthropic format; OpenAI-compatible APIs cache automatically.
+    #[serde(default)]
+    pub prompt_caching: Option<bool>,
+    /// Append every request/response exchange to this fixture file (record mode)
+    #[serde(default)]
+    pub record_fixture: Option<String>,
+    /// Context window of the model in tokens (None = estimated from the model name)
+    #[serde(default)]
+    pub context_window: Option<u64>,
+}
+
+/// Sampling and output parameters sent with each request
+///
+/// Every field is optional: None leaves the value to the provider (or, when used as
+/// a per-session override, falls back to the provider default).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GenerationParams {
+    /// Maximum output tokens (None = 4096)
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub stop_sequences: Vec<String>,
+    /// Extended thinking budget in tokens (None = disabled; Ollama only turns thinking on)
+    pub thinking_budget: Option<u32>,
+}
+
+impl GenerationParams {
+    pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+    pub fn max_tokens(&self) -> u32 {
+        self.max_tokens.unwrap_or(Self::DEFAULT_MAX_TOKENS)
+    }
+
+    /// These parameters with every value set in `overrides` taking precedence
+    pub fn merged(&self, overrides: &GenerationParams) -> GenerationParams {
+        GenerationParams {
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            stop_sequences: if overrides.stop_sequences.is_empty() {
+                self.stop_sequences.clone()
+            } else {
+                overrides.stop_sequences.clone()
+            },
+            thinking_budget: overrides.thinking_budget.or(self.thinking_budget),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == GenerationParams::default()
+    }
+}
+
+/// Model price in USD per million tokens
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Cache write price (None = same as input)
+    #[serde(default)]
+    pub cache_write: Option<f64>,
+    /// Cache read price (None = same as input)
+    #[serde(default)]
+    pub cache_read: Option<f64>,
+}
+
+impl ProviderConfig {
+    /// Whether tool definitions should be sent as native Messages API `tools`
+    pub fn supports_tools(&self) -> bool {
+        self.supports_tools.unwrap_or(true)
+    }
+
+    pub fn auth_scheme(&self) -> AuthScheme {
+        self.auth_scheme
+            .clone()
+            .unwrap_or_else(|| self.provider_type.default_auth_scheme())
+    }
+
+    pub fn prompt_caching(&self) -> bool {
+        self.prompt_caching.unwrap_or(true)
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(2)
+    }
+
+    pub fn connect_timeout(&self, network: &NetworkConfig) -> std::time::Duration {
+        let secs = self.connect_timeout_secs.or(network.connect_timeout_secs);
+        std::time::Duration::from_secs(secs.unwrap_or(NetworkConfig::DEFAULT_CONNECT_TIMEOUT_SECS))
+    }
+
+    pub fn read_timeout(&self, network: &NetworkConfig) -> std::time::Duration {
+        let secs = self.read_timeout_secs.or(network.read_timeout_secs);
+        std::time::Duration::from_secs(secs.unwrap_or(NetworkConfig::DEFAULT_READ_TIMEOUT_SECS))
+    }
+
+    /// Configured API key or key reference, ignoring empty strings
+    pub fn api_key(&self) -> Option<&str> {
+        self.api_key.as_deref().filter(|k| !k.trim().is_empty())
+    }
+
+    /// Where the API key comes from (None = no key configured)
+    pub fn key_source(&self) -> Option<KeySource> {
+        self.api_key().map(KeySource::parse)
+    }
+
+    /// Read the API key from its source; references are only resolved here
+    pub fn resolve_api_key(&self) -> std::result::Result<Option<String>, SecretError> {
+        self.key_source().map(|source| source.resolve()).transpose()
+    }
+
+    /// Context window of the configured model in tokens
+    pub fn context_window(&self) -> u64 {
+        self.context_window.unwrap_or_else(|| {
+            if self.provider_type == ProviderType::Ollama {
+                // Sent as `num_ctx`; the model's own limit is unknown, so keep Ollama's default
+                return 4_096;
+            }
+            let model = self.model.as_deref().unwrap_or_default().to_lowercase();
+            match model.as_str() {
+                m if m.contains("claude") || m.contains("minimax") => 200_000,
+                m if m.contains("gpt-4.1") => 1_000_000,
+                m if m.contains("gpt-5") => 400_000,
+                m if m.contains("kimi") => 256_000,
+                _ => 128_000,
+            }
+        })
+    }
+
+    /// Price table entry for a model, if configured
+    pub fn price_for(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices.get(model)
+    }
+
+    /// Enabled and has an API key (unless the provider type doesn't need one)
+    pub fn is_usable(&self) -> bool {
+        self.enabled && (self.api_key().is_some() || !self.provider_type.requires_api_key())
+    }
 }
 
 /// AI provider types
@@ -82,6 +385,12 @@ pub enum ProviderType {
     MiniMax,
     GLM,
     UltraThink,
+    /// Any OpenAI-compatible endpoint (OpenAI, DeepSeek, OpenRouter, vLLM, llama.cpp...)
+    OpenAI,
+    /// Local Ollama server (native `/api/chat`)
+    Ollama,
+    /// Replays scripted or recorded responses from a fixture file (base URL = fixture path)
+    Mock,
 }
 
 impl ProviderType {
@@ -92,6 +401,9 @@ impl ProviderType {
             ProviderType::MiniMax => "https://api.minimaxi.com/anthropic",
             ProviderType::GLM => "https://open.bigmodel.cn/api/anthropic",
             ProviderType::UltraThink => "https://api.ultrathink.ai",
+            ProviderType::OpenAI => "https://api.openai.com/v1",
+            ProviderType::Ollama => "http://localhost:11434",
+            ProviderType::Mock => "fixtures/mock.json",
         }
     }
 
@@ -102,6 +414,87 @@ impl ProviderType {
             ProviderType::MiniMax => "MiniMax-M2.1",
             ProviderType::GLM => "GLM-4.7",
             ProviderType::UltraThink => "ultrathink-v1",
+            ProviderType::OpenAI => "gpt-4o",
+            ProviderType::Ollama => "llama3.2",
+            ProviderType::Mock => "mock",
+        }
+    }
+
+    pub fn default_api_format(&self) -> ApiFormat {
+        match self {
+            ProviderType::OpenAI => ApiFormat::OpenAi,
+            ProviderType::Ollama => ApiFormat::Ollama,
+            _ => ApiFormat::Anthropic,
+        }
+    }
+
+    /// Local servers (and the mock provider) accept requests without an API key
+    pub fn requires_api_key(&self) -> bool {
+        !matches!(self, ProviderType::Ollama | ProviderType::Mock)
+    }
+
+    /// How the official endpoint expects the API key
+    pub fn default_auth_scheme(&self) -> AuthScheme {
+        match self {
+            ProviderType::Claude => AuthScheme::XApiKey,
+            _ => AuthScheme::Bearer,
+        }
+    }
+}
+
+/// How the API key is sent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <key>`
+    Bearer,
+    /// `x-api-key: <key>` (official Anthropic API)
+    XApiKey,
+    /// Key in a custom header, e.g. `api-key` for Azure-style gateways
+    Header(String),
+    /// No authentication header
+    None,
+}
+
+impl AuthScheme {
+    /// Header name and value for a key (None = nothing to send)
+    pub fn header(&self, api_key: &str) -> Option<(String, String)> {
+        match self {
+            AuthScheme::Bearer => Some(("Authorization".to_string(), format!("Bearer {}", api_key))),
+            AuthScheme::XApiKey => Some(("x-api-key".to_string(), api_key.to_string())),
+            AuthScheme::Header(name) if !name.trim().is_empty() => Some((name.trim().to_string(), api_key.to_string())),
+            AuthScheme::Header(_) | AuthScheme::None => None,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            AuthScheme::Bearer => "Authorization: Bearer".to_string(),
+            AuthScheme::XApiKey => "x-api-key".to_string(),
+            AuthScheme::Header(name) => format!("Header: {}", name),
+            AuthScheme::None => "None".to_string(),
+        }
+    }
+}
+
+/// API wire format spoken by a provider
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum ApiFormat {
+    /// Anthropic Messages API (`/v1/messages`)
+    #[default]
+    Anthropic,
+    /// OpenAI-compatible Chat Completions API (`/v1/chat/completions`)
+    OpenAi,
+    /// Ollama native API (`/api/chat`, NDJSON streaming)
+    Ollama,
+}
+
+impl ApiFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ApiFormat::Anthropic => "Anthropic Messages",
+            ApiFormat::OpenAi => "OpenAI Chat Completions",
+            ApiFormat::Ollama => "Ollama",
         }
     }
 }
@@ -158,8 +551,11 @@ pub enum ConfigError {
 pub type Result<T> = std::result::Result<T, ConfigError>;
 
 impl AppConfig {
-    /// Get the configuration directory path
-    fn get_config_dir() -> PathBuf {
+    /// Get the configuration directory path (`VELD_CONFIG_DIR` overrides it, e.g. in CI)
+    pub(crate) fn get_config_dir() -> PathBuf {
+        if let Some(dir) = std::env::var_os("VELD_CONFIG_DIR") {
+            return PathBuf::from(dir);
+        }
         let mut path = dirs::config_dir()
             .unwrap_or_else(|| std::path::PathBuf::from("."));
         path.push("veld");
@@ -189,6 +585,18 @@ impl AppConfig {
                         base_url: Some(ProviderType::Claude.default_base_url().to_string()),
                         model: Some(ProviderType::Claude.default_model().to_string()),
                         enabled: true,
+                        api_format: ApiFormat::Anthropic,
+                        supports_tools: None,
+                        max_retries: None,
+                        connect_timeout_secs: None,
+                        read_timeout_secs: None,
+                        prices: HashMap::new(),
+                        generation: GenerationParams::default(),
+                        auth_scheme: None,
+                        extra_headers: HashMap::new(),
+                        prompt_caching: None,
+                        record_fixture: None,
+                        context_window: None,
                     },
                     ProviderConfig {
                         id: "kimi".to_string(),
@@ -198,6 +606,18 @@ impl AppConfig {
                         base_url: Some(ProviderType::Kimi.default_base_url().to_string()),
                         model: Some(ProviderType::Kimi.default_model().to_string()),
                         enabled: true,
+                        api_format: ApiFormat::Anthropic,
+                        supports_tools: None,
+                        max_retries: None,
+                        connect_timeout_secs: None,
+                        read_timeout_secs: None,
+                        prices: HashMap::new(),
+                        generation: GenerationParams::default(),
+                        auth_scheme: None,
+                        extra_headers: HashMap::new(),
+                        prompt_caching: None,
+                        record_fixture: None,
+                        context_window: None,
                     },
                     ProviderConfig {
                         id: "minimax".to_string(),
@@ -207,6 +627,18 @@ impl AppConfig {
                         base_url: Some(ProviderType::MiniMax.default_base_url().to_string()),
                         model: Some(ProviderType::MiniMax.default_model().to_string()),
                         enabled: true,
+                        api_format: ApiFormat::Anthropic,
+                        supports_tools: None,
+                        max_retries: None,
+                        connect_timeout_secs: None,
+                        read_timeout_secs: None,
+                        prices: HashMap::new(),
+                        generation: GenerationParams::default(),
+                        auth_scheme: None,
+                        extra_headers: HashMap::new(),
+                        prompt_caching: None,
+                        record_fixture: None,
+                        context_window: None,
                     },
                     ProviderConfig {
                         id: "glm".to_string(),
@@ -216,6 +648,18 @@ impl AppConfig {
                         base_url: Some(ProviderType::GLM.default_base_url().to_string()),
                         model: Some(ProviderType::GLM.default_model().to_string()),
                         enabled: true,
+                        api_format: ApiFormat::Anthropic,
+                        supports_tools: None,
+                        max_retries: None,
+                        connect_timeout_secs: None,
+                        read_timeout_secs: None,
+                        prices: HashMap::new(),
+                        generation: GenerationParams::default(),
+                        auth_scheme: None,
+                        extra_headers: HashMap::new(),
+                        prompt_caching: None,
+                        record_fixture: None,
+                        context_window: None,
                     },
                     ProviderConfig {
                         id: "ultrathink".to_string(),
@@ -225,14 +669,29 @@ impl AppConfig {
                         base_url: Some(ProviderType::UltraThink.default_base_url().to_string()),
                         model: Some(ProviderType::UltraThink.default_model().to_string()),
                         enabled: true,
+                        api_format: ApiFormat::Anthropic,
+                        supports_tools: None,
+                        max_retries: None,
+                        connect_timeout_secs: None,
+                        read_timeout_secs: None,
+                        prices: HashMap::new(),
+                        generation: GenerationParams::default(),
+                        auth_scheme: None,
+                        extra_headers: HashMap::new(),
+                        prompt_caching: None,
+                        record_fixture: None,
+                        context_window: None,
                     },
                 ],
                 active_provider: Some("claude".to_string()),
+                fallback_providers: Vec::new(),
+                context: ContextConfig::default(),
             },
             mcp: McpConfig {
                 servers: vec![
                     McpServerConfig {
                         name: "Context7".to_string(),
+                        transport: McpTransport::Stdio,
                         command: "npx".to_string(),
                         args: vec!["-y".to_string(), "@upstash/context7-mcp@latest".to_string()],
                         env: None,
@@ -247,6 +706,7 @@ impl AppConfig {
                 quick_explain: Some("Ctrl+Shift+E".to_string()),
             },
             ui: UiConfig::default(),
+            network: NetworkConfig::default(),
         }
     }
 
@@ -341,11 +801,56 @@ impl AppConfig {
     }
 
     /// Get the active provider only if it's actually usable (enabled + has API key)
-    /// Returns None if active provider is missing, disabled, or has no API key
+    /// Returns None if active provider is missing, disabled, or lacks a required API key
     pub fn get_usable_provider(&self) -> Option<&ProviderConfig> {
         let active_id = self.ai.active_provider.as_ref()?;
         self.ai.providers.iter()
-            .find(|p| p.id == *active_id && p.enabled && p.api_key.as_ref().map_or(false, |k| !k.is_empty()))
+            .find(|p| p.id == *active_id && p.is_usable())
+    }
+
+    /// Usable fallback providers in order (excluding the active provider)
+    pub fn get_fallback_providers(&self) -> Vec<&ProviderConfig> {
+        let active_id = self.ai.active_provider.as_deref();
+        self.ai.fallback_providers.iter()
+            .filter(|id| Some(id.as_str()) != active_id)
+            .filter_map(|id| self.ai.providers.iter().find(|p| p.id == *id && p.is_usable()))
+            .collect()
+    }
+
+    /// Set the ordered fallback provider list
+    pub fn set_fallback_providers(&mut self, provider_ids: Vec<String>) {
+        self.ai.fallback_providers = provider_ids;
+        // Save in background thread
+        let config = self.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = config.save() {
+                eprintln!("[Config] Failed to save fallback providers: {}", e);
+            }
+        });
+    }
+
+    /// Update context window management settings
+    pub fn update_context(&mut self, context: ContextConfig) {
+        self.ai.context = context;
+        // Save in background thread
+        let config = self.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = config.save() {
+                eprintln!("[Config] Failed to save context config: {}", e);
+            }
+        });
+    }
+
+    /// Update network settings (the shared HTTP client is rebuilt on the next request)
+    pub fn update_network(&mut self, network: NetworkConfig) {
+        self.network = network;
+        // Save in background thread
+        let config = self.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = config.save() {
+                eprintln!("[Config] Failed to save network config: {}", e);
+            }
+        });
     }
 
     /// Update MCP configuration
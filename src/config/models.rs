@@ -0,0 +1,71 @@
+//! Cached provider model listings
+//! 模型列表缓存 - 保存各提供商的可用模型，供选择与校验
+
+use super::{AppConfig, ConfigError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Models fetched from one provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CachedModels {
+    pub models: Vec<String>,
+    /// Unix timestamp (secs) of the fetch
+    pub fetched_at: u64,
+}
+
+/// Model listings by provider id, stored in `models.json` next to the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelCache {
+    #[serde(default)]
+    pub providers: HashMap<String, CachedModels>,
+}
+
+impl ModelCache {
+    fn get_cache_path() -> PathBuf {
+        let mut path = AppConfig::get_config_dir();
+        path.push("models.json");
+        path
+    }
+
+    /// Load the cache (empty if missing or unreadable)
+    pub fn load() -> Self {
+        fs::read_to_string(Self::get_cache_path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(AppConfig::get_config_dir()).map_err(ConfigError::Io)?;
+        let json = serde_json::to_string_pretty(self).map_err(ConfigError::Json)?;
+        fs::write(Self::get_cache_path(), json).map_err(ConfigError::Io)?;
+        Ok(())
+    }
+
+    pub fn get(&self, provider_id: &str) -> Option<&CachedModels> {
+        self.providers.get(provider_id)
+    }
+
+    /// Replace a provider's listing and persist the cache
+    pub fn store(provider_id: &str, models: Vec<String>) -> Result<()> {
+        let fetched_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut cache = Self::load();
+        cache
+            .providers
+            .insert(provider_id.to_string(), CachedModels { models, fetched_at });
+        cache.save()
+    }
+
+    /// Whether `model` is known to be missing from the provider's cached listing
+    ///
+    /// Returns false when there is no (or an empty) listing to check against.
+    pub fn is_unknown_model(&self, provider_id: &str, model: &str) -> bool {
+        self.get(provider_id)
+            .is_some_and(|cached| !cached.models.is_empty() && !cached.models.iter().any(|m| m == model))
+    }
+}
@@ -0,0 +1,330 @@
+//! API key references and the encrypted secrets store
+//! 密钥管理 - 环境变量/文件引用与口令加密的密钥库
+
+use super::AppConfig;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use thiserror::Error;
+
+/// Errors resolving a key reference or using the secrets store
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("Environment variable {0} is not set")]
+    EnvNotSet(String),
+    #[error("Failed to read key file {path}: {source}")]
+    File { path: String, source: std::io::Error },
+    #[error("Key source {0} is empty")]
+    Empty(String),
+    #[error("Secrets store is locked")]
+    Locked,
+    #[error("Secrets store has not been created")]
+    NoStore,
+    #[error("Secrets store already exists")]
+    StoreExists,
+    #[error("Secret not found: {0}")]
+    NotFound(String),
+    #[error("Wrong passphrase or corrupted secrets store")]
+    WrongPassphrase,
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid secrets store: {0}")]
+    Format(String),
+}
+
+pub type Result<T> = std::result::Result<T, SecretError>;
+
+/// Where an API key comes from, parsed from the configured value
+///
+/// `env:NAME`, `file:/path` and `secret:NAME` are references resolved when a request
+/// is made; anything else is a key stored in plain text in the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeySource {
+    Plain(String),
+    Env(String),
+    File(String),
+    Secret(String),
+}
+
+impl KeySource {
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if let Some(name) = value.strip_prefix("env:") {
+            KeySource::Env(name.trim().to_string())
+        } else if let Some(path) = value.strip_prefix("file:") {
+            KeySource::File(path.trim().to_string())
+        } else if let Some(name) = value.strip_prefix("secret:") {
+            KeySource::Secret(name.trim().to_string())
+        } else {
+            KeySource::Plain(value.to_string())
+        }
+    }
+
+    /// Read the key from its source
+    pub fn resolve(&self) -> Result<String> {
+        let key = match self {
+            KeySource::Plain(key) => key.clone(),
+            KeySource::Env(name) => std::env::var(name).map_err(|_| SecretError::EnvNotSet(name.clone()))?,
+            KeySource::File(path) => fs::read_to_string(expand_home(path))
+                .map_err(|source| SecretError::File { path: path.clone(), source })?,
+            KeySource::Secret(name) => SecretStore::get(name)?,
+        };
+        let key = key.trim().to_string();
+        if key.is_empty() {
+            return Err(SecretError::Empty(self.label()));
+        }
+        Ok(key)
+    }
+
+    /// Short description for the settings UI (never includes the key itself)
+    pub fn label(&self) -> String {
+        match self {
+            KeySource::Plain(_) => "plain text in config".to_string(),
+            KeySource::Env(name) => format!("env ${}", name),
+            KeySource::File(path) => format!("file {}", path),
+            KeySource::Secret(name) => format!("secrets store ({})", name),
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            KeySource::Plain(_) => "⚠️",
+            KeySource::Env(_) => "🌿",
+            KeySource::File(_) => "📄",
+            KeySource::Secret(_) => "🔐",
+        }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        matches!(self, KeySource::Plain(_))
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Encrypted secrets file (`secrets.json` next to the config)
+///
+/// Secrets are a JSON map sealed with AES-256-GCM under a key derived from the
+/// passphrase with PBKDF2-HMAC-SHA256. A fresh nonce is used for every save.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedStore {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Decrypted store, kept in memory while unlocked
+struct UnlockedStore {
+    key: [u8; 32],
+    salt: Vec<u8>,
+    iterations: u32,
+    secrets: HashMap<String, String>,
+}
+
+static UNLOCKED: LazyLock<Mutex<Option<UnlockedStore>>> = LazyLock::new(Default::default);
+
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    let iterations = NonZeroU32::new(iterations.max(1)).unwrap();
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    key
+}
+
+fn aead_key(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&aead::AES_256_GCM, key).expect("AES-256 key is 32 bytes"))
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| SecretError::Format("system random number generator failed".to_string()))?;
+    Ok(bytes)
+}
+
+fn seal(store: &UnlockedStore) -> Result<SealedStore> {
+    let nonce = random_bytes::<{ aead::NONCE_LEN }>()?;
+    let mut data = serde_json::to_vec(&store.secrets).map_err(|e| SecretError::Format(e.to_string()))?;
+    aead_key(&store.key)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| SecretError::Format("encryption failed".to_string()))?;
+    Ok(SealedStore {
+        version: 1,
+        iterations: store.iterations,
+        salt: BASE64.encode(&store.salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(data),
+    })
+}
+
+fn open(sealed: &SealedStore, passphrase: &str) -> Result<UnlockedStore> {
+    let decode = |value: &str| BASE64.decode(value).map_err(|e| SecretError::Format(e.to_string()));
+    let salt = decode(&sealed.salt)?;
+    let nonce: [u8; aead::NONCE_LEN] = decode(&sealed.nonce)?
+        .try_into()
+        .map_err(|_| SecretError::Format("bad nonce length".to_string()))?;
+    let mut data = decode(&sealed.ciphertext)?;
+
+    let key = derive_key(passphrase, &salt, sealed.iterations);
+    let plaintext = aead_key(&key)
+        .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| SecretError::WrongPassphrase)?;
+    let secrets = serde_json::from_slice(plaintext).map_err(|e| SecretError::Format(e.to_string()))?;
+    Ok(UnlockedStore { key, salt, iterations: sealed.iterations, secrets })
+}
+
+/// Passphrase-protected store for API keys, referenced as `secret:NAME`
+///
+/// The store is optional; it stays locked until [`SecretStore::unlock`] is called
+/// and the passphrase is never written to disk.
+pub struct SecretStore;
+
+impl SecretStore {
+    fn get_store_path() -> PathBuf {
+        let mut path = AppConfig::get_config_dir();
+        path.push("secrets.json");
+        path
+    }
+
+    pub fn exists() -> bool {
+        Self::get_store_path().exists()
+    }
+
+    pub fn is_unlocked() -> bool {
+        UNLOCKED.lock().unwrap().is_some()
+    }
+
+    /// Create an empty store protected by `passphrase` and leave it unlocked
+    pub fn create(passphrase: &str) -> Result<()> {
+        if Self::exists() {
+            return Err(SecretError::StoreExists);
+        }
+        let salt = random_bytes::<16>()?.to_vec();
+        let store = UnlockedStore {
+            key: derive_key(passphrase, &salt, PBKDF2_ITERATIONS),
+            salt,
+            iterations: PBKDF2_ITERATIONS,
+            secrets: HashMap::new(),
+        };
+        Self::write(&store)?;
+        *UNLOCKED.lock().unwrap() = Some(store);
+        Ok(())
+    }
+
+    pub fn unlock(passphrase: &str) -> Result<()> {
+        let content = fs::read_to_string(Self::get_store_path()).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => SecretError::NoStore,
+            _ => SecretError::Io(e),
+        })?;
+        let sealed: SealedStore = serde_json::from_str(&content).map_err(|e| SecretError::Format(e.to_string()))?;
+        *UNLOCKED.lock().unwrap() = Some(open(&sealed, passphrase)?);
+        Ok(())
+    }
+
+    /// Forget the decrypted secrets and key
+    pub fn lock() {
+        *UNLOCKED.lock().unwrap() = None;
+    }
+
+    pub fn get(name: &str) -> Result<String> {
+        let unlocked = UNLOCKED.lock().unwrap();
+        let store = unlocked.as_ref().ok_or(SecretError::Locked)?;
+        store.secrets.get(name).cloned().ok_or_else(|| SecretError::NotFound(name.to_string()))
+    }
+
+    /// Names of the stored secrets (empty while locked)
+    pub fn names() -> Vec<String> {
+        let unlocked = UNLOCKED.lock().unwrap();
+        let mut names: Vec<String> = unlocked.iter().flat_map(|s| s.secrets.keys().cloned()).collect();
+        names.sort();
+        names
+    }
+
+    /// Add or replace a secret and re-encrypt the store
+    pub fn set(name: &str, value: &str) -> Result<()> {
+        Self::modify(|secrets| {
+            secrets.insert(name.to_string(), value.to_string());
+        })
+    }
+
+    pub fn remove(name: &str) -> Result<()> {
+        Self::modify(|secrets| {
+            secrets.remove(name);
+        })
+    }
+
+    fn modify(update: impl FnOnce(&mut HashMap<String, String>)) -> Result<()> {
+        let mut unlocked = UNLOCKED.lock().unwrap();
+        let store = unlocked.as_mut().ok_or(SecretError::Locked)?;
+        update(&mut store.secrets);
+        Self::write(store)
+    }
+
+    fn write(store: &UnlockedStore) -> Result<()> {
+        let sealed = seal(store)?;
+        fs::create_dir_all(AppConfig::get_config_dir())?;
+        let json = serde_json::to_string_pretty(&sealed).map_err(|e| SecretError::Format(e.to_string()))?;
+        fs::write(Self::get_store_path(), json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_sources() {
+        assert_eq!(KeySource::parse("sk-123"), KeySource::Plain("sk-123".to_string()));
+        assert_eq!(KeySource::parse("env: VELD_TEST_KEY"), KeySource::Env("VELD_TEST_KEY".to_string()));
+        assert_eq!(KeySource::parse("secret:claude"), KeySource::Secret("claude".to_string()));
+
+        std::env::set_var("VELD_TEST_KEY", " sk-env\n");
+        assert_eq!(KeySource::parse("env:VELD_TEST_KEY").resolve().unwrap(), "sk-env");
+        assert!(matches!(
+            KeySource::parse("env:VELD_TEST_MISSING").resolve(),
+            Err(SecretError::EnvNotSet(_))
+        ));
+
+        let path = std::env::temp_dir().join("veld-test-key.txt");
+        fs::write(&path, "sk-file\n").unwrap();
+        let source = KeySource::parse(&format!("file:{}", path.display()));
+        assert_eq!(source.resolve().unwrap(), "sk-file");
+        assert!(!source.label().contains("sk-file"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_sealed_store_roundtrip() {
+        let salt = b"0123456789abcdef".to_vec();
+        let store = UnlockedStore {
+            key: derive_key("correct horse", &salt, 1000),
+            salt,
+            iterations: 1000,
+            secrets: HashMap::from([("claude".to_string(), "sk-ant-123".to_string())]),
+        };
+        let sealed = seal(&store).unwrap();
+        assert!(!sealed.ciphertext.contains("sk-ant"));
+        assert_ne!(seal(&store).unwrap().nonce, sealed.nonce);
+
+        let opened = open(&sealed, "correct horse").unwrap();
+        assert_eq!(opened.secrets["claude"], "sk-ant-123");
+        assert!(matches!(open(&sealed, "wrong"), Err(SecretError::WrongPassphrase)));
+    }
+}
@@ -7,6 +7,7 @@ use dioxus_desktop::{
     trayicon::TrayIconEvent,
 };
 use crate::components::floating_input::FloatingInput;
+use crate::components::chat::OutgoingMessage;
 use crate::shortcuts::ShortcutManager;
 use crate::theme::{init_theme};
 use crate::routes::Route;
@@ -115,8 +116,13 @@ fn App() -> Element {
             FloatingInput {
                 is_visible: show_floating_input(),
                 on_close: Callback::new(move |_| show_floating_input.set(false)),
-                on_submit: Callback::new(|text: String| {
-                    println!("Tool selected and submitted: {}", text);
+                on_submit: Callback::new(|message: OutgoingMessage| {
+                    println!(
+                        "Tool selected and submitted: {} ({} attachments, {} prompt messages)",
+                        message.text,
+                        message.attachments.len(),
+                        message.inserted.len()
+                    );
                     // TODO: Implement AI tool handling
                 }),
             }
@@ -0,0 +1,548 @@
+//! Anthropic Messages API adapter
+//! Anthropic Messages API 协议适配
+
+use crate::config::GenerationParams;
+use crate::services::ai_client::{
+    AiError, AiResponse, ChatMessage, ContentBlock, Result, StopReason, StreamEvent, ToolDefinition, Usage,
+};
+use crate::services::sse::SseEvent;
+use serde::Deserialize;
+
+/// Resolve the Messages API endpoint from a configured base URL
+pub fn endpoint_url(base_url: &str) -> String {
+    // Base URL should already point to the correct endpoint (e.g., https://api.anthropic.com/v1/messages or https://api.kimi.com/coding/v1/messages)
+    if base_url.ends_with("/messages") || base_url.contains("/v1/messages") {
+        base_url.to_string()
+    } else {
+        format!("{}/v1/messages", base_url.trim_end_matches('/'))
+    }
+}
+
+/// Resolve the `/v1/models` listing endpoint (up to 1000 models, the API maximum)
+pub fn models_url(base_url: &str) -> String {
+    let endpoint = endpoint_url(base_url);
+    format!("{}/models?limit=1000", endpoint.trim_end_matches('/').trim_end_matches("/messages"))
+}
+
+/// Build the Messages API request body (with optional system, tools and generation parameters)
+///
+/// With extended thinking enabled, `temperature` is omitted (the API rejects it) and
+/// `max_tokens` is raised above the thinking budget so there is room for the answer.
+/// Thinking is left off when continuing a prefilled answer (the API rejects the combination).
+/// With `prompt_caching`, cache breakpoints are added (see [`add_cache_breakpoints`]).
+pub fn build_request_body(
+    model: &str,
+    messages: Vec<ChatMessage>,
+    tools: &[ToolDefinition],
+    params: &GenerationParams,
+    prompt_caching: bool,
+    stream: bool,
+) -> serde_json::Value {
+    let prefilled = messages.last().is_some_and(|m| m.role == "assistant");
+
+    // Join the system messages (e.g. a tool prompt and a summary of dropped turns)
+    // into the system parameter and filter messages to only user/assistant
+    let system_prompts: Vec<String> = messages.iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_text())
+        .filter(|text| !text.is_empty())
+        .collect();
+    let system_message = (!system_prompts.is_empty()).then(|| system_prompts.join("\n\n"));
+
+    let filtered_messages: Vec<_> = messages.into_iter()
+        .filter(|m| m.role == "user" || m.role == "assistant")
+        .map(|m| serde_json::json!({
+            "role": m.role,
+            "content": m.content
+        }))
+        .collect();
+
+    let mut request_body_json = serde_json::json!({
+        "model": model,
+        "max_tokens": params.max_tokens(),
+        "messages": filtered_messages,
+    });
+
+    if let Some(obj) = request_body_json.as_object_mut() {
+        // Add system parameter if exists
+        if let Some(system) = system_message {
+            obj.insert("system".to_string(), serde_json::Value::String(system));
+        }
+        if !tools.is_empty() {
+            obj.insert("tools".to_string(), serde_json::json!(tools));
+        }
+        if stream {
+            obj.insert("stream".to_string(), serde_json::Value::Bool(true));
+        }
+        match params.thinking_budget.filter(|_| !prefilled) {
+            Some(budget) => {
+                obj.insert("thinking".to_string(), serde_json::json!({ "type": "enabled", "budget_tokens": budget }));
+                if params.max_tokens() <= budget {
+                    obj.insert("max_tokens".to_string(), serde_json::json!(budget + GenerationParams::DEFAULT_MAX_TOKENS));
+                }
+            }
+            None => {
+                if let Some(temperature) = params.temperature {
+                    obj.insert("temperature".to_string(), serde_json::json!(temperature));
+                }
+            }
+        }
+        if let Some(top_p) = params.top_p {
+            obj.insert("top_p".to_string(), serde_json::json!(top_p));
+        }
+        if !params.stop_sequences.is_empty() {
+            obj.insert("stop_sequences".to_string(), serde_json::json!(params.stop_sequences));
+        }
+        if prompt_caching {
+            add_cache_breakpoints(obj);
+        }
+    }
+
+    request_body_json
+}
+
+/// Mark cacheable prefixes with `cache_control` (the API allows at most 4 breakpoints)
+///
+/// Breakpoints go on the last tool definition, the system prompt and the last two
+/// user turns: the newest one writes the cache for the next request, the previous one
+/// reads what the last request (e.g. the previous agent iteration) wrote.
+fn add_cache_breakpoints(body: &mut serde_json::Map<String, serde_json::Value>) {
+    let cache_control = serde_json::json!({ "type": "ephemeral" });
+
+    if let Some(last_tool) = body.get_mut("tools").and_then(|t| t.as_array_mut()).and_then(|t| t.last_mut()) {
+        last_tool["cache_control"] = cache_control.clone();
+    }
+
+    if let Some(system) = body.get_mut("system") {
+        if let Some(text) = system.as_str().filter(|t| !t.is_empty()) {
+            *system = serde_json::json!([{ "type": "text", "text": text, "cache_control": cache_control }]);
+        }
+    }
+
+    let Some(messages) = body.get_mut("messages").and_then(|m| m.as_array_mut()) else {
+        return;
+    };
+    for message in messages.iter_mut().rev().filter(|m| m["role"] == "user").take(2) {
+        let content = &mut message["content"];
+        if let Some(text) = content.as_str().filter(|t| !t.is_empty()) {
+            *content = serde_json::json!([{ "type": "text", "text": text }]);
+        }
+        if let Some(last_block) = content.as_array_mut().and_then(|blocks| blocks.last_mut()) {
+            last_block["cache_control"] = cache_control.clone();
+        }
+    }
+}
+
+/// Parse Anthropic Messages API response
+///
+/// Every content block is kept, blocks of unknown types as [`ContentBlock::Other`];
+/// only empty text blocks are skipped.
+pub fn parse_response(body: &str) -> Result<AiResponse> {
+    #[derive(Deserialize)]
+    struct ClaudeResponse {
+        content: Vec<serde_json::Value>,
+        #[serde(default)]
+        stop_reason: Option<String>,
+        #[serde(default)]
+        stop_sequence: Option<String>,
+        #[serde(default)]
+        usage: Usage,
+    }
+    let resp: ClaudeResponse =
+        serde_json::from_str(body).map_err(|e: serde_json::Error| {
+            AiError::Serialization(format!("Failed to parse response: {}", e))
+        })?;
+    let content = resp
+        .content
+        .into_iter()
+        .map(|block| serde_json::from_value(block.clone()).unwrap_or(ContentBlock::Other(block)))
+        .filter(|block| !is_empty_text(block))
+        .collect();
+    Ok(AiResponse {
+        content,
+        stop_reason: resp.stop_reason.as_deref().map(StopReason::from),
+        stop_sequence: resp.stop_sequence,
+        usage: resp.usage,
+    })
+}
+
+/// Empty text block (rejected by the API when the assistant turn is sent back)
+fn is_empty_text(block: &ContentBlock) -> bool {
+    matches!(block, ContentBlock::Text { text } if text.is_empty())
+}
+
+/// Overwrite the usage counters present in a `usage` object
+///
+/// `message_delta` reports cumulative counts, so later values replace earlier ones.
+fn merge_usage(usage: &mut Usage, value: &serde_json::Value) {
+    let fields = [
+        ("input_tokens", &mut usage.input_tokens),
+        ("output_tokens", &mut usage.output_tokens),
+        ("cache_creation_input_tokens", &mut usage.cache_creation_input_tokens),
+        ("cache_read_input_tokens", &mut usage.cache_read_input_tokens),
+    ];
+    for (key, field) in fields {
+        if let Some(n) = value[key].as_u64() {
+            *field = n;
+        }
+    }
+}
+
+/// Extract a readable message from an error body (`{"type":"error","error":{"message":...}}`)
+pub fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(String::from))
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// Builds an [`AiResponse`] from Anthropic SSE events
+#[derive(Default)]
+pub struct StreamAccumulator {
+    blocks: Vec<ContentBlock>,
+    /// Partial JSON of tool_use inputs, keyed by block index
+    partial_json: std::collections::HashMap<usize, String>,
+    stop_reason: Option<StopReason>,
+    stop_sequence: Option<String>,
+    usage: Usage,
+}
+
+impl StreamAccumulator {
+    /// Handle one SSE event; returns `true` once `message_stop` is received
+    pub fn handle_event<F>(&mut self, event: &SseEvent, on_event: &mut F) -> Result<bool>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let data: serde_json::Value = serde_json::from_str(&event.data).map_err(|e| {
+            AiError::Serialization(format!("Failed to parse stream event: {}", e))
+        })?;
+        let index = data["index"].as_u64().unwrap_or(0) as usize;
+
+        match data["type"].as_str().unwrap_or_default() {
+            "content_block_start" => {
+                let block = &data["content_block"];
+                let block = match block["type"].as_str().unwrap_or_default() {
+                    "tool_use" => ContentBlock::ToolUse {
+                        id: block["id"].as_str().unwrap_or_default().to_string(),
+                        name: block["name"].as_str().unwrap_or_default().to_string(),
+                        input: serde_json::json!({}),
+                    },
+                    "thinking" => ContentBlock::Thinking {
+                        thinking: block["thinking"].as_str().unwrap_or_default().to_string(),
+                        signature: block["signature"].as_str().unwrap_or_default().to_string(),
+                    },
+                    "redacted_thinking" => ContentBlock::RedactedThinking {
+                        data: block["data"].as_str().unwrap_or_default().to_string(),
+                    },
+                    "text" => ContentBlock::Text {
+                        text: block["text"].as_str().unwrap_or_default().to_string(),
+                    },
+                    // e.g. server_tool_use or web_search_tool_result, kept for the next request
+                    _ => ContentBlock::Other(block.clone()),
+                };
+                if index < self.blocks.len() {
+                    self.blocks[index] = block;
+                } else {
+                    while self.blocks.len() < index {
+                        self.blocks.push(ContentBlock::Text { text: String::new() });
+                    }
+                    self.blocks.push(block);
+                }
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                match delta["type"].as_str().unwrap_or_default() {
+                    "text_delta" => {
+                        let text = delta["text"].as_str().unwrap_or_default();
+                        match self.blocks.get_mut(index) {
+                            Some(ContentBlock::Text { text: block_text }) => block_text.push_str(text),
+                            _ => self.blocks.push(ContentBlock::Text { text: text.to_string() }),
+                        }
+                        on_event(StreamEvent::TextDelta(text.to_string()));
+                    }
+                    "thinking_delta" => {
+                        let thinking = delta["thinking"].as_str().unwrap_or_default();
+                        if let Some(ContentBlock::Thinking { thinking: block_thinking, .. }) = self.blocks.get_mut(index) {
+                            block_thinking.push_str(thinking);
+                        }
+                        on_event(StreamEvent::ThinkingDelta(thinking.to_string()));
+                    }
+                    "signature_delta" => {
+                        if let Some(ContentBlock::Thinking { signature, .. }) = self.blocks.get_mut(index) {
+                            signature.push_str(delta["signature"].as_str().unwrap_or_default());
+                        }
+                    }
+                    "input_json_delta" => {
+                        self.partial_json
+                            .entry(index)
+                            .or_default()
+                            .push_str(delta["partial_json"].as_str().unwrap_or_default());
+                    }
+                    _ => {}
+                }
+            }
+            "content_block_stop" => {
+                let json = self.partial_json.remove(&index).filter(|json| !json.trim().is_empty());
+                let input = match (self.blocks.get_mut(index), json) {
+                    (Some(ContentBlock::ToolUse { input, .. }), Some(json)) => Some((input, json)),
+                    // Server tool calls stream their input too
+                    (Some(ContentBlock::Other(block)), Some(json)) => Some((&mut block["input"], json)),
+                    _ => None,
+                };
+                if let Some((input, json)) = input {
+                    *input = serde_json::from_str(&json).map_err(|e| {
+                        AiError::Serialization(format!("Failed to parse tool input: {}", e))
+                    })?;
+                }
+            }
+            "message_start" => merge_usage(&mut self.usage, &data["message"]["usage"]),
+            "message_delta" => {
+                if let Some(reason) = data["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(StopReason::from(reason));
+                }
+                if let Some(sequence) = data["delta"]["stop_sequence"].as_str() {
+                    self.stop_sequence = Some(sequence.to_string());
+                }
+                merge_usage(&mut self.usage, &data["usage"]);
+            }
+            "message_stop" => return Ok(true),
+            "error" => {
+                let message = data["error"]["message"].as_str().unwrap_or("unknown error").to_string();
+                return Err(match data["error"]["type"].as_str() {
+                    Some("overloaded_error") => AiError::Overloaded(message),
+                    Some("rate_limit_error") => AiError::RateLimited { message, retry_after: None },
+                    _ => AiError::Api(format!("Stream error: {}", message)),
+                });
+            }
+            // ping
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// The response, without empty text blocks (including padding for out-of-order indices)
+    pub fn finish(self) -> AiResponse {
+        AiResponse {
+            content: self.blocks.into_iter().filter(|block| !is_empty_text(block)).collect(),
+            stop_reason: self.stop_reason,
+            stop_sequence: self.stop_sequence,
+            usage: self.usage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::context::{apply_plan, ContextPlan};
+
+    #[test]
+    fn test_models_url() {
+        assert_eq!(models_url("https://api.anthropic.com"), "https://api.anthropic.com/v1/models?limit=1000");
+        assert_eq!(models_url("https://api.kimi.com/coding/v1/messages"), "https://api.kimi.com/coding/v1/models?limit=1000");
+    }
+
+    #[test]
+    fn test_generation_params_in_body() {
+        let params = GenerationParams {
+            max_tokens: Some(2000),
+            temperature: Some(0.2),
+            stop_sequences: vec!["END".to_string()],
+            thinking_budget: Some(8000),
+            ..Default::default()
+        };
+        let body = build_request_body("claude", vec![], &[], &params, false, false);
+        assert_eq!(body["thinking"]["budget_tokens"], 8000);
+        assert_eq!(body["max_tokens"], 8000 + GenerationParams::DEFAULT_MAX_TOKENS);
+        assert!(body.get("temperature").is_none());
+        assert_eq!(body["stop_sequences"], serde_json::json!(["END"]));
+
+        let body = build_request_body("claude", vec![], &[], &GenerationParams::default(), false, false);
+        assert_eq!(body["max_tokens"], GenerationParams::DEFAULT_MAX_TOKENS);
+        assert!(body.get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_cache_breakpoints() {
+        let tool = |name: &str| ToolDefinition {
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: serde_json::json!({"type": "object"}),
+        };
+        let messages = vec![
+            ChatMessage { role: "system".to_string(), content: "Be brief".to_string().into() },
+            ChatMessage { role: "user".to_string(), content: "first".to_string().into() },
+            ChatMessage { role: "assistant".to_string(), content: "ok".to_string().into() },
+            ChatMessage { role: "user".to_string(), content: "second".to_string().into() },
+            ChatMessage { role: "assistant".to_string(), content: "ok".to_string().into() },
+            ChatMessage { role: "user".to_string(), content: "third".to_string().into() },
+        ];
+        let ephemeral = serde_json::json!({"type": "ephemeral"});
+
+        let body = build_request_body("claude", messages.clone(), &[tool("a"), tool("b")], &GenerationParams::default(), true, false);
+        assert!(body["tools"][0].get("cache_control").is_none());
+        assert_eq!(body["tools"][1]["cache_control"], ephemeral);
+        assert_eq!(body["system"][0]["cache_control"], ephemeral);
+        assert_eq!(body["messages"][0]["content"], "first");
+        assert_eq!(body["messages"][2]["content"][0]["cache_control"], ephemeral);
+        assert_eq!(body["messages"][4]["content"][0], serde_json::json!({"type": "text", "text": "third", "cache_control": ephemeral}));
+
+        let body = build_request_body("claude", messages, &[tool("a")], &GenerationParams::default(), false, false);
+        assert_eq!(body["system"], "Be brief");
+        assert!(body["tools"][0].get("cache_control").is_none());
+    }
+
+    #[test]
+    fn test_summary_joins_system_prompt() {
+        let messages = vec![
+            ChatMessage { role: "user".to_string(), content: "Weather in Oslo?".to_string().into() },
+            ChatMessage { role: "assistant".to_string(), content: "Rainy.".to_string().into() },
+            ChatMessage { role: "user".to_string(), content: "And tomorrow?".to_string().into() },
+        ];
+        let plan = ContextPlan { first_kept: 2, ..Default::default() };
+        let mut sent = apply_plan(&messages, &plan, Some("The user asked about Oslo."));
+        // The prompt tool loop puts its own system message first
+        sent.insert(0, ChatMessage { role: "system".to_string(), content: "Use the tools.".to_string().into() });
+
+        let body = build_request_body("claude", sent, &[], &GenerationParams::default(), false, false);
+        let system = body["system"].as_str().unwrap();
+        assert!(system.starts_with("Use the tools.\n\n"));
+        assert!(system.contains("The user asked about Oslo."));
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_stream_keeps_thinking_signature() {
+        let events = [
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Need a tool."}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig=="}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"Done."}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+
+        let mut accumulator = StreamAccumulator::default();
+        let mut thinking = Vec::new();
+        for data in events {
+            let event = SseEvent { event: None, data: data.to_string() };
+            accumulator
+                .handle_event(&event, &mut |e| {
+                    if let StreamEvent::ThinkingDelta(text) = e {
+                        thinking.push(text);
+                    }
+                })
+                .unwrap();
+        }
+
+        let response = accumulator.finish();
+        assert_eq!(thinking, vec!["Need a tool."]);
+        assert_eq!(response.text(), "Done.");
+        assert_eq!(
+            response.content[0],
+            ContentBlock::Thinking { thinking: "Need a tool.".to_string(), signature: "sig==".to_string() }
+        );
+        // Sent back unchanged in the next request
+        assert_eq!(
+            serde_json::to_value(&response.content[0]).unwrap(),
+            serde_json::json!({"type": "thinking", "thinking": "Need a tool.", "signature": "sig=="})
+        );
+    }
+
+    #[test]
+    fn test_stream_accumulates_tool_use() {
+        let events = [
+            r#"{"type":"message_start","message":{"usage":{"input_tokens":12,"cache_read_input_tokens":100,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me check."}}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"search","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"query\": \"ru"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"st\"}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":30}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+
+        let mut accumulator = StreamAccumulator::default();
+        let mut deltas = Vec::new();
+        let mut stopped = false;
+        for data in events {
+            let event = SseEvent { event: None, data: data.to_string() };
+            stopped = accumulator
+                .handle_event(&event, &mut |e| {
+                    if let StreamEvent::TextDelta(text) = e {
+                        deltas.push(text);
+                    }
+                })
+                .unwrap();
+        }
+
+        let response = accumulator.finish();
+        assert!(stopped);
+        assert_eq!(deltas, vec!["Let me check."]);
+        assert_eq!(response.text(), "Let me check.");
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(
+            response.tool_uses(),
+            vec![("toolu_1".to_string(), "search".to_string(), serde_json::json!({"query": "rust"}))]
+        );
+        assert_eq!(
+            response.usage,
+            Usage { input_tokens: 12, output_tokens: 30, cache_creation_input_tokens: 0, cache_read_input_tokens: 100 }
+        );
+    }
+
+    #[test]
+    fn test_stream_drops_empty_text_blocks() {
+        let events = [
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            // Index 1 never arrives
+            r#"{"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_1","name":"search","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"{}"}}"#,
+            r#"{"type":"content_block_stop","index":2}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+
+        let mut accumulator = StreamAccumulator::default();
+        for data in events {
+            let event = SseEvent { event: None, data: data.to_string() };
+            accumulator.handle_event(&event, &mut |_| {}).unwrap();
+        }
+
+        // Only the tool_use is echoed in the next request
+        let response = accumulator.finish();
+        assert_eq!(
+            response.content,
+            vec![ContentBlock::ToolUse { id: "toolu_1".to_string(), name: "search".to_string(), input: serde_json::json!({}) }]
+        );
+    }
+
+    #[test]
+    fn test_parse_response_keeps_all_blocks() {
+        let body = serde_json::json!({
+            "content": [
+                {"type": "thinking", "thinking": "Plan.", "signature": "sig"},
+                {"type": "text", "text": "First."},
+                {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {}},
+                {"type": "text", "text": "Second"}
+            ],
+            "stop_reason": "stop_sequence",
+            "stop_sequence": "END",
+            "usage": {"input_tokens": 9, "output_tokens": 7}
+        });
+
+        let response = parse_response(&body.to_string()).unwrap();
+        assert_eq!(response.content.len(), 4);
+        // The unknown block is echoed back unchanged
+        assert_eq!(serde_json::to_value(&response.content[2]).unwrap(), body["content"][2]);
+        assert_eq!(response.thinking(), "Plan.");
+        assert_eq!(response.text(), "First.\nSecond");
+        assert_eq!(response.stop_reason, Some(StopReason::StopSequence));
+        assert_eq!(response.stop_sequence.as_deref(), Some("END"));
+        assert_eq!(response.usage.output_tokens, 7);
+        assert!(!response.is_truncated());
+    }
+}
@@ -0,0 +1,214 @@
+//! Wire protocol adapters
+//! API 协议适配器 - 按 ApiFormat 构建请求、解析响应与流式事件
+
+pub mod anthropic;
+pub mod ollama;
+pub mod openai;
+
+use crate::config::{ApiFormat, AuthScheme, GenerationParams};
+use crate::services::ai_client::{user_message, AiResponse, ChatMessage, MessageContent, Result, StreamEvent, ToolDefinition};
+use crate::services::sse::SseDecoder;
+
+/// Resolve the request endpoint for an API format
+pub fn endpoint_url(format: &ApiFormat, base_url: &str) -> String {
+    match format {
+        ApiFormat::Anthropic => anthropic::endpoint_url(base_url),
+        ApiFormat::OpenAi => openai::endpoint_url(base_url),
+        ApiFormat::Ollama => ollama::endpoint_url(base_url),
+    }
+}
+
+/// Resolve the model listing endpoint for an API format
+pub fn models_url(format: &ApiFormat, base_url: &str) -> String {
+    match format {
+        ApiFormat::Anthropic => anthropic::models_url(base_url),
+        ApiFormat::OpenAi => openai::models_url(base_url),
+        ApiFormat::Ollama => ollama::tags_url(base_url),
+    }
+}
+
+/// Parse a model listing into model names
+pub fn parse_models(format: &ApiFormat, body: &str) -> Result<Vec<String>> {
+    match format {
+        ApiFormat::Anthropic | ApiFormat::OpenAi => openai::parse_models(body),
+        ApiFormat::Ollama => ollama::parse_tags(body),
+    }
+}
+
+/// Instruction sent after a cut-off answer by formats without assistant prefill
+const CONTINUE_PROMPT: &str = "Continue your previous answer exactly where it stopped. Do not repeat anything or add a preamble.";
+
+/// Build the request body for an API format
+///
+/// `prompt_caching` adds explicit cache breakpoints where the format supports them.
+/// `context_window` is sent where the server would otherwise use a smaller default (Ollama's `num_ctx`).
+/// Messages ending with an assistant message ask to continue that answer (see [`prepare_continuation`]).
+#[allow(clippy::too_many_arguments)]
+pub fn build_request_body(
+    format: &ApiFormat,
+    model: &str,
+    messages: Vec<ChatMessage>,
+    tools: &[ToolDefinition],
+    params: &GenerationParams,
+    prompt_caching: bool,
+    context_window: u64,
+    stream: bool,
+) -> serde_json::Value {
+    let messages = prepare_continuation(format, messages);
+    match format {
+        ApiFormat::Anthropic => anthropic::build_request_body(model, messages, tools, params, prompt_caching, stream),
+        ApiFormat::OpenAi => openai::build_request_body(model, messages, tools, params, stream),
+        ApiFormat::Ollama => ollama::build_request_body(model, messages, tools, params, context_window, stream),
+    }
+}
+
+/// Shape a conversation that ends with a cut-off assistant answer for continuation
+///
+/// Anthropic continues a trailing assistant message directly (prefill), which must
+/// not end with whitespace. Chat Completions and Ollama servers answer a new turn
+/// instead, so an instruction to carry on is appended.
+fn prepare_continuation(format: &ApiFormat, mut messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let Some(last) = messages.last_mut().filter(|m| m.role == "assistant") else {
+        return messages;
+    };
+    match format {
+        ApiFormat::Anthropic => {
+            if let MessageContent::Text(text) = &mut last.content {
+                text.truncate(text.trim_end().len());
+            }
+        }
+        ApiFormat::OpenAi | ApiFormat::Ollama => messages.push(user_message(CONTINUE_PROMPT.to_string())),
+    }
+    messages
+}
+
+/// Add authentication and protocol-specific headers (versioning)
+///
+/// The key is optional for local servers; when absent no auth header is sent.
+pub fn apply_headers(
+    format: &ApiFormat,
+    request: reqwest::RequestBuilder,
+    api_key: Option<&str>,
+    auth_scheme: &AuthScheme,
+) -> reqwest::RequestBuilder {
+    let request = match api_key.and_then(|key| auth_scheme.header(key)) {
+        Some((name, value)) => request.header(name, value),
+        None => request,
+    };
+    match format {
+        ApiFormat::Anthropic => request.header("anthropic-version", "2023-06-01"),
+        ApiFormat::OpenAi | ApiFormat::Ollama => request,
+    }
+}
+
+/// Parse a complete (non-streaming) response body
+pub fn parse_response(format: &ApiFormat, body: &str) -> Result<AiResponse> {
+    match format {
+        ApiFormat::Anthropic => anthropic::parse_response(body),
+        ApiFormat::OpenAi => openai::parse_response(body),
+        ApiFormat::Ollama => ollama::parse_response(body),
+    }
+}
+
+/// Extract a readable error message from an error response body
+pub fn error_message(format: &ApiFormat, body: &str) -> String {
+    match format {
+        ApiFormat::Anthropic => anthropic::error_message(body),
+        ApiFormat::OpenAi => openai::error_message(body),
+        ApiFormat::Ollama => ollama::error_message(body),
+    }
+}
+
+/// Protocol-specific stream state
+///
+/// Anthropic and OpenAI stream Server-Sent Events; Ollama streams newline-delimited JSON.
+enum StreamState {
+    Anthropic(SseDecoder, anthropic::StreamAccumulator),
+    OpenAi(SseDecoder, openai::StreamAccumulator),
+    Ollama(ollama::StreamAccumulator),
+}
+
+/// Decodes a streaming response body into an [`AiResponse`]
+pub struct StreamParser {
+    state: StreamState,
+}
+
+impl StreamParser {
+    pub fn new(format: &ApiFormat) -> Self {
+        let state = match format {
+            ApiFormat::Anthropic => StreamState::Anthropic(SseDecoder::new(), Default::default()),
+            ApiFormat::OpenAi => StreamState::OpenAi(SseDecoder::new(), Default::default()),
+            ApiFormat::Ollama => StreamState::Ollama(Default::default()),
+        };
+        Self { state }
+    }
+
+    /// Push a chunk of the response body; returns `true` once the stream is complete
+    pub fn push<F>(&mut self, chunk: &[u8], on_event: &mut F) -> Result<bool>
+    where
+        F: FnMut(StreamEvent),
+    {
+        match &mut self.state {
+            StreamState::Anthropic(decoder, acc) => {
+                for event in decoder.push(chunk) {
+                    if acc.handle_event(&event, on_event)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            StreamState::OpenAi(decoder, acc) => {
+                for event in decoder.push(chunk) {
+                    if acc.handle_event(&event, on_event)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            StreamState::Ollama(acc) => acc.push(chunk, on_event),
+        }
+    }
+
+    /// Flush any trailing event and build the final response
+    pub fn finish<F>(self, on_event: &mut F) -> Result<AiResponse>
+    where
+        F: FnMut(StreamEvent),
+    {
+        match self.state {
+            StreamState::Anthropic(mut decoder, mut acc) => {
+                if let Some(event) = decoder.finish() {
+                    acc.handle_event(&event, on_event)?;
+                }
+                Ok(acc.finish())
+            }
+            StreamState::OpenAi(mut decoder, mut acc) => {
+                if let Some(event) = decoder.finish() {
+                    acc.handle_event(&event, on_event)?;
+                }
+                acc.finish()
+            }
+            StreamState::Ollama(acc) => acc.finish(on_event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai_client::assistant_message;
+
+    #[test]
+    fn test_continue_cut_off_answer() {
+        let messages = vec![user_message("Count to 100".to_string()), assistant_message("1, 2, 3, \n".to_string())];
+        let params = GenerationParams { thinking_budget: Some(4000), ..Default::default() };
+
+        let body = build_request_body(&ApiFormat::Anthropic, "claude", messages.clone(), &[], &params, false, 200_000, true);
+        assert_eq!(body["messages"][1]["content"], "1, 2, 3,");
+        assert!(body.get("thinking").is_none());
+
+        let body = build_request_body(&ApiFormat::OpenAi, "gpt", messages, &[], &params, false, 128_000, true);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(body["messages"][2]["role"], "user");
+        assert_eq!(body["messages"][2]["content"], CONTINUE_PROMPT);
+    }
+}
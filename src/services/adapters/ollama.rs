@@ -0,0 +1,369 @@
+//! Ollama native API adapter
+//! Ollama 原生协议适配 (`/api/chat` 流式 NDJSON, `/api/tags` 本地模型列表)
+
+use crate::config::GenerationParams;
+use crate::services::ai_client::{
+    AiError, AiResponse, ChatMessage, ContentBlock, MediaSource, MessageContent, Result, StopReason, StreamEvent,
+    ToolDefinition, Usage,
+};
+use serde_json::{json, Value};
+
+/// Strip a trailing `/api/...` or `/v1` suffix so both endpoints can be derived
+fn server_root(base_url: &str) -> &str {
+    let base_url = base_url.trim_end_matches('/');
+    if let Some(pos) = base_url.find("/api/") {
+        &base_url[..pos]
+    } else {
+        base_url.trim_end_matches("/api").trim_end_matches("/v1")
+    }
+}
+
+/// Resolve the `/api/chat` endpoint from a configured base URL
+pub fn endpoint_url(base_url: &str) -> String {
+    format!("{}/api/chat", server_root(base_url))
+}
+
+/// Resolve the `/api/tags` (installed models) endpoint from a configured base URL
+pub fn tags_url(base_url: &str) -> String {
+    format!("{}/api/tags", server_root(base_url))
+}
+
+/// Build the `/api/chat` request body (generation parameters go in `options`)
+///
+/// `num_ctx` is set to `context_window`, otherwise Ollama truncates the prompt to its default.
+pub fn build_request_body(
+    model: &str,
+    messages: Vec<ChatMessage>,
+    tools: &[ToolDefinition],
+    params: &GenerationParams,
+    context_window: u64,
+    stream: bool,
+) -> Value {
+    let mut converted = Vec::new();
+    for message in messages {
+        match message.content {
+            MessageContent::Text(text) => converted.push(json!({
+                "role": message.role,
+                "content": text,
+            })),
+            MessageContent::Blocks(blocks) => convert_blocks(&message.role, blocks, &mut converted),
+        }
+    }
+
+    let mut body = json!({
+        "model": model,
+        "messages": converted,
+        "stream": stream,
+        "options": { "num_ctx": context_window, "num_predict": params.max_tokens() },
+    });
+    if let Some(temperature) = params.temperature {
+        body["options"]["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = params.top_p {
+        body["options"]["top_p"] = json!(top_p);
+    }
+    if !params.stop_sequences.is_empty() {
+        body["options"]["stop"] = json!(params.stop_sequences);
+    }
+    // Ollama has no thinking budget; any budget turns thinking on
+    if params.thinking_budget.is_some() {
+        body["think"] = json!(true);
+    }
+
+    if !tools.is_empty() {
+        let tools: Vec<Value> = tools
+            .iter()
+            .map(|t| json!({
+                "type": "function",
+                "function": {
+                    "name": t.name,
+                    "description": t.description,
+                    "parameters": t.input_schema,
+                }
+            }))
+            .collect();
+        body["tools"] = Value::Array(tools);
+    }
+
+    body
+}
+
+/// Convert a block-based message into Ollama chat messages
+fn convert_blocks(role: &str, blocks: Vec<ContentBlock>, out: &mut Vec<Value>) {
+    let mut text = Vec::new();
+    let mut images = Vec::new();
+    let mut tool_calls = Vec::new();
+
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text: t } => text.push(t),
+            // Ollama takes raw base64 images next to the text
+            ContentBlock::Image { source: MediaSource::Base64 { data, .. } } => images.push(data),
+            ContentBlock::Document { source: MediaSource::Text { data, .. }, title } => {
+                text.push(match title {
+                    Some(title) => format!("{}\n\n{}", title, data),
+                    None => data,
+                });
+            }
+            ContentBlock::Image { .. } | ContentBlock::Document { .. } => {
+                text.push("[Attachment not supported by this provider]".to_string());
+            }
+            // Ollama tool arguments are JSON objects, not strings
+            ContentBlock::ToolUse { name, input, .. } => tool_calls.push(json!({
+                "function": { "name": name, "arguments": input }
+            })),
+            ContentBlock::ToolResult { content, .. } => out.push(json!({
+                "role": "tool",
+                "content": content,
+            })),
+            ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } | ContentBlock::Other(_) => {}
+        }
+    }
+
+    if !text.is_empty() || !images.is_empty() || !tool_calls.is_empty() {
+        let mut message = json!({
+            "role": role,
+            "content": text.join("\n"),
+        });
+        if !images.is_empty() {
+            message["images"] = json!(images);
+        }
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = Value::Array(tool_calls);
+        }
+        out.push(message);
+    }
+}
+
+/// Map an Ollama `done_reason` to a [`StopReason`]
+fn map_done_reason(reason: &str) -> StopReason {
+    match reason {
+        "stop" => StopReason::EndTurn,
+        "length" => StopReason::MaxTokens,
+        other => StopReason::Other(other.to_string()),
+    }
+}
+
+/// Token counts from the final (`done`) object
+fn parse_usage(data: &Value) -> Usage {
+    Usage {
+        input_tokens: data["prompt_eval_count"].as_u64().unwrap_or(0),
+        output_tokens: data["eval_count"].as_u64().unwrap_or(0),
+        ..Usage::default()
+    }
+}
+
+/// Tool calls from an Ollama message (Ollama doesn't assign ids, so they are generated)
+fn tool_calls(message: &Value, offset: usize) -> Vec<ContentBlock> {
+    message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, call)| ContentBlock::ToolUse {
+            id: call["id"]
+                .as_str()
+                .map(String::from)
+                .unwrap_or_else(|| format!("ollama_call_{}", offset + i)),
+            name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+            input: match &call["function"]["arguments"] {
+                Value::String(args) => serde_json::from_str(args).unwrap_or_else(|_| json!({})),
+                Value::Null => json!({}),
+                args => args.clone(),
+            },
+        })
+        .collect()
+}
+
+/// Parse a (non-streaming) `/api/chat` response
+pub fn parse_response(body: &str) -> Result<AiResponse> {
+    let resp: Value = serde_json::from_str(body).map_err(|e: serde_json::Error| {
+        AiError::Serialization(format!("Failed to parse response: {}", e))
+    })?;
+
+    if let Some(error) = resp["error"].as_str() {
+        return Err(AiError::Api(error.to_string()));
+    }
+
+    let mut content = Vec::new();
+    if let Some(thinking) = resp["message"]["thinking"].as_str().filter(|t| !t.is_empty()) {
+        content.push(ContentBlock::Thinking { thinking: thinking.to_string(), signature: String::new() });
+    }
+    if let Some(text) = resp["message"]["content"].as_str() {
+        if !text.is_empty() {
+            content.push(ContentBlock::Text { text: text.to_string() });
+        }
+    }
+    let calls = tool_calls(&resp["message"], 0);
+    let has_calls = !calls.is_empty();
+    content.extend(calls);
+
+    Ok(AiResponse {
+        content,
+        stop_reason: if has_calls {
+            Some(StopReason::ToolUse)
+        } else {
+            resp["done_reason"].as_str().map(map_done_reason)
+        },
+        stop_sequence: None,
+        usage: parse_usage(&resp),
+    })
+}
+
+/// Extract a readable message from an error body (`{"error":"..."}`)
+pub fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"].as_str().map(String::from))
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// Parse the `/api/tags` response into installed model names
+pub fn parse_tags(body: &str) -> Result<Vec<String>> {
+    let resp: Value = serde_json::from_str(body).map_err(|e: serde_json::Error| {
+        AiError::Serialization(format!("Failed to parse model list: {}", e))
+    })?;
+
+    Ok(resp["models"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["name"].as_str().or_else(|| m["model"].as_str()).map(String::from))
+        .collect())
+}
+
+/// Builds an [`AiResponse`] from `/api/chat` NDJSON stream lines
+#[derive(Default)]
+pub struct StreamAccumulator {
+    buffer: Vec<u8>,
+    thinking: String,
+    text: String,
+    tool_calls: Vec<ContentBlock>,
+    stop_reason: Option<StopReason>,
+    usage: Usage,
+}
+
+impl StreamAccumulator {
+    /// Push a chunk of the response body; returns `true` once `"done": true` is received
+    pub fn push<F>(&mut self, chunk: &[u8], on_event: &mut F) -> Result<bool>
+    where
+        F: FnMut(StreamEvent),
+    {
+        self.buffer.extend_from_slice(chunk);
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            if self.handle_line(&String::from_utf8_lossy(&line), on_event)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Handle one NDJSON line; returns `true` on the final (`done`) object
+    fn handle_line<F>(&mut self, line: &str, on_event: &mut F) -> Result<bool>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(false);
+        }
+
+        let data: Value = serde_json::from_str(line).map_err(|e| {
+            AiError::Serialization(format!("Failed to parse stream line: {}", e))
+        })?;
+
+        if let Some(error) = data["error"].as_str() {
+            return Err(AiError::Api(format!("Stream error: {}", error)));
+        }
+
+        if let Some(thinking) = data["message"]["thinking"].as_str().filter(|t| !t.is_empty()) {
+            self.thinking.push_str(thinking);
+            on_event(StreamEvent::ThinkingDelta(thinking.to_string()));
+        }
+        if let Some(text) = data["message"]["content"].as_str() {
+            if !text.is_empty() {
+                self.text.push_str(text);
+                on_event(StreamEvent::TextDelta(text.to_string()));
+            }
+        }
+        let offset = self.tool_calls.len();
+        self.tool_calls.extend(tool_calls(&data["message"], offset));
+
+        if data["done"].as_bool().unwrap_or(false) {
+            self.stop_reason = data["done_reason"].as_str().map(map_done_reason);
+            self.usage = parse_usage(&data);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Flush a trailing unterminated line and build the final response
+    pub fn finish<F>(mut self, on_event: &mut F) -> Result<AiResponse>
+    where
+        F: FnMut(StreamEvent),
+    {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            self.handle_line(&String::from_utf8_lossy(&rest), on_event)?;
+        }
+
+        let mut content = Vec::new();
+        if !self.thinking.is_empty() {
+            content.push(ContentBlock::Thinking { thinking: self.thinking, signature: String::new() });
+        }
+        if !self.text.is_empty() {
+            content.push(ContentBlock::Text { text: self.text });
+        }
+        let has_calls = !self.tool_calls.is_empty();
+        content.extend(self.tool_calls);
+
+        Ok(AiResponse {
+            content,
+            stop_reason: if has_calls { Some(StopReason::ToolUse) } else { self.stop_reason },
+            stop_sequence: None,
+            usage: self.usage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints_from_base_url() {
+        assert_eq!(endpoint_url("http://localhost:11434"), "http://localhost:11434/api/chat");
+        assert_eq!(endpoint_url("http://localhost:11434/api/chat"), "http://localhost:11434/api/chat");
+        assert_eq!(tags_url("http://localhost:11434/v1/"), "http://localhost:11434/api/tags");
+    }
+
+    #[test]
+    fn test_request_options() {
+        let body = build_request_body("llama3", vec![], &[], &GenerationParams::default(), 4_096, true);
+        assert_eq!(body["options"]["num_ctx"], 4_096);
+        assert_eq!(body["options"]["num_predict"], GenerationParams::default().max_tokens());
+    }
+
+    #[test]
+    fn test_ndjson_stream() {
+        let mut accumulator = StreamAccumulator::default();
+        let mut deltas = Vec::new();
+        let mut on_event = |e| {
+            if let StreamEvent::TextDelta(text) = e {
+                deltas.push(text);
+            }
+        };
+
+        let first = b"{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n{\"message\":{\"content\":\"lo";
+        assert!(!accumulator.push(first, &mut on_event).unwrap());
+        let rest = b"\"},\"done\":false}\n{\"message\":{\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\",\"prompt_eval_count\":26,\"eval_count\":2}\n";
+        assert!(accumulator.push(rest, &mut on_event).unwrap());
+
+        let response = accumulator.finish(&mut on_event).unwrap();
+        assert_eq!(response.text(), "Hello");
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+        assert_eq!(deltas, vec!["Hel", "lo"]);
+        assert_eq!((response.usage.input_tokens, response.usage.output_tokens), (26, 2));
+    }
+}
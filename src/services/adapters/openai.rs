@@ -0,0 +1,489 @@
+//! OpenAI-compatible Chat Completions adapter
+//! OpenAI 兼容协议适配 (`/v1/chat/completions`)，适用于 OpenAI、DeepSeek、OpenRouter、vLLM、llama.cpp 等
+
+use crate::config::GenerationParams;
+use crate::services::ai_client::{
+    AiError, AiResponse, ChatMessage, ContentBlock, MessageContent, MediaSource, Result, StopReason, StreamEvent,
+    ToolDefinition, Usage,
+};
+use crate::services::sse::SseEvent;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Resolve the Chat Completions endpoint from a configured base URL
+pub fn endpoint_url(base_url: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    if base_url.ends_with("/chat/completions") {
+        base_url.to_string()
+    } else if base_url.ends_with("/v1") {
+        format!("{}/chat/completions", base_url)
+    } else {
+        format!("{}/v1/chat/completions", base_url)
+    }
+}
+
+/// Resolve the `/models` listing endpoint from a configured base URL
+pub fn models_url(base_url: &str) -> String {
+    let endpoint = endpoint_url(base_url);
+    format!("{}/models", endpoint.trim_end_matches("/chat/completions"))
+}
+
+/// Parse a model listing (`{"data":[{"id":...}]}`, also used by Anthropic)
+pub fn parse_models(body: &str) -> Result<Vec<String>> {
+    let resp: Value = serde_json::from_str(body).map_err(|e: serde_json::Error| {
+        AiError::Serialization(format!("Failed to parse model list: {}", e))
+    })?;
+
+    Ok(resp["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["id"].as_str().map(String::from))
+        .collect())
+}
+
+/// Build the Chat Completions request body
+///
+/// Content blocks are mapped to the OpenAI shape: `tool_use` blocks become
+/// assistant `tool_calls`, and `tool_result` blocks become `tool` role messages.
+pub fn build_request_body(
+    model: &str,
+    messages: Vec<ChatMessage>,
+    tools: &[ToolDefinition],
+    params: &GenerationParams,
+    stream: bool,
+) -> Value {
+    let mut converted = Vec::new();
+    for message in messages {
+        match message.content {
+            MessageContent::Text(text) => converted.push(json!({
+                "role": message.role,
+                "content": text,
+            })),
+            MessageContent::Blocks(blocks) => convert_blocks(&message.role, blocks, &mut converted),
+        }
+    }
+
+    let mut body = json!({
+        "model": model,
+        "max_tokens": params.max_tokens(),
+        "messages": converted,
+    });
+
+    if let Some(obj) = body.as_object_mut() {
+        if let Some(temperature) = params.temperature {
+            obj.insert("temperature".to_string(), json!(temperature));
+        }
+        if let Some(top_p) = params.top_p {
+            obj.insert("top_p".to_string(), json!(top_p));
+        }
+        if !params.stop_sequences.is_empty() {
+            obj.insert("stop".to_string(), json!(params.stop_sequences));
+        }
+        if !tools.is_empty() {
+            let tools: Vec<Value> = tools
+                .iter()
+                .map(|t| json!({
+                    "type": "function",
+                    "function": {
+                        "name": t.name,
+                        "description": t.description,
+                        "parameters": t.input_schema,
+                    }
+                }))
+                .collect();
+            obj.insert("tools".to_string(), Value::Array(tools));
+        }
+        if stream {
+            obj.insert("stream".to_string(), Value::Bool(true));
+            // Ask for a final chunk carrying token usage
+            obj.insert("stream_options".to_string(), json!({ "include_usage": true }));
+        }
+    }
+
+    body
+}
+
+/// Convert a block-based message into one or more Chat Completions messages
+///
+/// Messages with images or documents use the content-parts array form
+/// (`image_url` data URLs, `file` parts for PDFs); text documents become text parts.
+fn convert_blocks(role: &str, blocks: Vec<ContentBlock>, out: &mut Vec<Value>) {
+    let mut text = Vec::new();
+    let mut parts = Vec::new();
+    let mut has_media = false;
+    let mut tool_calls = Vec::new();
+
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text: t } => {
+                parts.push(json!({ "type": "text", "text": t }));
+                text.push(t);
+            }
+            ContentBlock::Image { source } => {
+                has_media = true;
+                if let Some(url) = source.data_url() {
+                    parts.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+                }
+            }
+            ContentBlock::Document { source: MediaSource::Text { data, .. }, title } => {
+                has_media = true;
+                let t = match title {
+                    Some(title) => format!("{}\n\n{}", title, data),
+                    None => data,
+                };
+                parts.push(json!({ "type": "text", "text": t }));
+            }
+            ContentBlock::Document { source, title } => {
+                has_media = true;
+                parts.push(json!({
+                    "type": "file",
+                    "file": {
+                        "filename": title.unwrap_or_else(|| "document.pdf".to_string()),
+                        "file_data": source.data_url(),
+                    }
+                }));
+            }
+            ContentBlock::ToolUse { id, name, input } => tool_calls.push(json!({
+                "id": id,
+                "type": "function",
+                "function": {
+                    "name": name,
+                    "arguments": input.to_string(),
+                }
+            })),
+            ContentBlock::ToolResult { tool_use_id, content, .. } => out.push(json!({
+                "role": "tool",
+                "tool_call_id": tool_use_id,
+                "content": content,
+            })),
+            // Reasoning is not sent back in Chat Completions
+            ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } | ContentBlock::Other(_) => {}
+        }
+    }
+
+    if role == "assistant" && !tool_calls.is_empty() {
+        let content = if text.is_empty() { Value::Null } else { Value::String(text.join("\n")) };
+        out.push(json!({
+            "role": "assistant",
+            "content": content,
+            "tool_calls": tool_calls,
+        }));
+    } else if has_media {
+        out.push(json!({
+            "role": role,
+            "content": parts,
+        }));
+    } else if !text.is_empty() {
+        out.push(json!({
+            "role": role,
+            "content": text.join("\n"),
+        }));
+    }
+}
+
+/// Map an OpenAI `finish_reason` to a [`StopReason`]
+fn map_finish_reason(reason: &str) -> StopReason {
+    match reason {
+        "stop" => StopReason::EndTurn,
+        "length" => StopReason::MaxTokens,
+        "tool_calls" | "function_call" => StopReason::ToolUse,
+        "content_filter" => StopReason::Refusal,
+        other => StopReason::Other(other.to_string()),
+    }
+}
+
+/// Parse a tool call's JSON arguments string (empty means no arguments)
+fn parse_arguments(arguments: &str) -> Result<Value> {
+    if arguments.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(arguments)
+        .map_err(|e| AiError::Serialization(format!("Failed to parse tool arguments: {}", e)))
+}
+
+/// Read a `usage` object, moving cached prompt tokens out of `input_tokens`
+///
+/// `prompt_tokens` includes cache hits (`prompt_tokens_details.cached_tokens`,
+/// or DeepSeek's `prompt_cache_hit_tokens`).
+fn parse_usage(usage: &Value) -> Option<Usage> {
+    let prompt = usage["prompt_tokens"].as_u64()?;
+    let cached = usage["prompt_tokens_details"]["cached_tokens"]
+        .as_u64()
+        .or_else(|| usage["prompt_cache_hit_tokens"].as_u64())
+        .unwrap_or(0)
+        .min(prompt);
+    Some(Usage {
+        input_tokens: prompt - cached,
+        output_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: cached,
+    })
+}
+
+/// Non-standard reasoning field of a message or delta (`reasoning_content` or `reasoning`)
+fn reasoning_text(message: &Value) -> Option<&str> {
+    message["reasoning_content"]
+        .as_str()
+        .or_else(|| message["reasoning"].as_str())
+        .filter(|r| !r.is_empty())
+}
+
+/// Parse a (non-streaming) Chat Completions response
+pub fn parse_response(body: &str) -> Result<AiResponse> {
+    let resp: Value = serde_json::from_str(body).map_err(|e: serde_json::Error| {
+        AiError::Serialization(format!("Failed to parse response: {}", e))
+    })?;
+
+    if resp.get("error").is_some() {
+        return Err(AiError::Api(error_message(body)));
+    }
+
+    let choice = &resp["choices"][0];
+    let message = &choice["message"];
+    let mut content = Vec::new();
+
+    // Reasoning models (DeepSeek, vLLM, OpenRouter...) return their reasoning separately
+    if let Some(reasoning) = reasoning_text(message) {
+        content.push(ContentBlock::Thinking { thinking: reasoning.to_string(), signature: String::new() });
+    }
+    if let Some(text) = message["content"].as_str() {
+        content.push(ContentBlock::Text { text: text.to_string() });
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        content.push(ContentBlock::ToolUse {
+            id: call["id"].as_str().unwrap_or_default().to_string(),
+            name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+            input: parse_arguments(call["function"]["arguments"].as_str().unwrap_or_default())?,
+        });
+    }
+
+    Ok(AiResponse {
+        content,
+        stop_reason: choice["finish_reason"].as_str().map(map_finish_reason),
+        stop_sequence: None,
+        usage: parse_usage(&resp["usage"]).unwrap_or_default(),
+    })
+}
+
+/// Extract a readable message from an error body (`{"error":{"message":...}}`)
+pub fn error_message(body: &str) -> String {
+    let value = match serde_json::from_str::<Value>(body) {
+        Ok(value) => value,
+        Err(_) => return body.to_string(),
+    };
+    value["error"]["message"]
+        .as_str()
+        .or_else(|| value["error"].as_str())
+        .or_else(|| value["message"].as_str())
+        .or_else(|| value["detail"].as_str())
+        .map(String::from)
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// Tool call being assembled from streamed fragments
+#[derive(Default)]
+struct PartialToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Builds an [`AiResponse`] from Chat Completions SSE chunks
+#[derive(Default)]
+pub struct StreamAccumulator {
+    text: String,
+    reasoning: String,
+    /// Tool calls keyed by their `index` in the delta
+    tool_calls: BTreeMap<u64, PartialToolCall>,
+    stop_reason: Option<StopReason>,
+    usage: Usage,
+}
+
+impl StreamAccumulator {
+    /// Handle one SSE event; returns `true` once `[DONE]` is received
+    pub fn handle_event<F>(&mut self, event: &SseEvent, on_event: &mut F) -> Result<bool>
+    where
+        F: FnMut(StreamEvent),
+    {
+        if event.data.trim() == "[DONE]" {
+            return Ok(true);
+        }
+
+        let data: Value = serde_json::from_str(&event.data).map_err(|e| {
+            AiError::Serialization(format!("Failed to parse stream event: {}", e))
+        })?;
+
+        if data.get("error").is_some() {
+            return Err(AiError::Api(format!("Stream error: {}", error_message(&event.data))));
+        }
+
+        // Sent in the last chunk (with empty `choices`) when `include_usage` is set
+        if let Some(usage) = parse_usage(&data["usage"]) {
+            self.usage = usage;
+        }
+
+        for choice in data["choices"].as_array().into_iter().flatten() {
+            let delta = &choice["delta"];
+
+            if let Some(reasoning) = reasoning_text(delta) {
+                self.reasoning.push_str(reasoning);
+                on_event(StreamEvent::ThinkingDelta(reasoning.to_string()));
+            }
+
+            if let Some(text) = delta["content"].as_str() {
+                if !text.is_empty() {
+                    self.text.push_str(text);
+                    on_event(StreamEvent::TextDelta(text.to_string()));
+                }
+            }
+
+            for call in delta["tool_calls"].as_array().into_iter().flatten() {
+                let index = call["index"].as_u64().unwrap_or(0);
+                let entry = self.tool_calls.entry(index).or_default();
+                if let Some(id) = call["id"].as_str() {
+                    entry.id = id.to_string();
+                }
+                if let Some(name) = call["function"]["name"].as_str() {
+                    entry.name.push_str(name);
+                }
+                if let Some(arguments) = call["function"]["arguments"].as_str() {
+                    entry.arguments.push_str(arguments);
+                }
+            }
+
+            if let Some(reason) = choice["finish_reason"].as_str() {
+                self.stop_reason = Some(map_finish_reason(reason));
+            }
+        }
+
+        Ok(false)
+    }
+
+    pub fn finish(self) -> Result<AiResponse> {
+        let mut content = Vec::new();
+        if !self.reasoning.is_empty() {
+            content.push(ContentBlock::Thinking { thinking: self.reasoning, signature: String::new() });
+        }
+        if !self.text.is_empty() {
+            content.push(ContentBlock::Text { text: self.text });
+        }
+        for (_, call) in self.tool_calls {
+            content.push(ContentBlock::ToolUse {
+                id: call.id,
+                name: call.name,
+                input: parse_arguments(&call.arguments)?,
+            });
+        }
+
+        Ok(AiResponse {
+            content,
+            stop_reason: self.stop_reason,
+            stop_sequence: None,
+            usage: self.usage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai_client::attachment_block;
+
+    #[test]
+    fn test_models_listing() {
+        assert_eq!(models_url("https://api.openai.com/v1"), "https://api.openai.com/v1/models");
+        assert_eq!(models_url("https://example.com/v1/chat/completions"), "https://example.com/v1/models");
+        assert_eq!(models_url("http://localhost:8000"), "http://localhost:8000/v1/models");
+
+        let body = r#"{"object":"list","data":[{"id":"gpt-4o","object":"model"},{"id":"o3"}]}"#;
+        assert_eq!(parse_models(body).unwrap(), vec!["gpt-4o", "o3"]);
+    }
+
+    #[test]
+    fn test_tool_blocks_map_to_openai_messages() {
+        let messages = vec![
+            ChatMessage { role: "system".to_string(), content: "Be brief".to_string().into() },
+            ChatMessage {
+                role: "assistant".to_string(),
+                content: vec![ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "search".to_string(),
+                    input: json!({"query": "rust"}),
+                }]
+                .into(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".to_string(),
+                    content: "found".to_string(),
+                    is_error: false,
+                }]
+                .into(),
+            },
+        ];
+
+        let body = build_request_body("gpt-4o", messages, &[], &GenerationParams::default(), false);
+        let sent = body["messages"].as_array().unwrap();
+        assert_eq!(sent[0], json!({"role": "system", "content": "Be brief"}));
+        assert_eq!(sent[1]["tool_calls"][0]["function"]["arguments"], "{\"query\":\"rust\"}");
+        assert_eq!(sent[2], json!({"role": "tool", "tool_call_id": "call_1", "content": "found"}));
+    }
+
+    #[test]
+    fn test_attachments_map_to_content_parts() {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: vec![
+                attachment_block("cat.png", "image/png", b"png"),
+                attachment_block("notes.txt", "text/plain", b"hello"),
+                ContentBlock::Text { text: "Describe".to_string() },
+            ]
+            .into(),
+        }];
+
+        let body = build_request_body("gpt-4o", messages, &[], &GenerationParams::default(), false);
+        let parts = body["messages"][0]["content"].as_array().unwrap();
+        assert_eq!(parts[0]["image_url"]["url"], "data:image/png;base64,cG5n");
+        assert_eq!(parts[1], json!({"type": "text", "text": "notes.txt\n\nhello"}));
+        assert_eq!(parts[2], json!({"type": "text", "text": "Describe"}));
+    }
+
+    #[test]
+    fn test_stream_accumulates_tool_calls() {
+        let chunks = [
+            r#"{"choices":[{"delta":{"content":"Hi"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_1","function":{"name":"search","arguments":"{\"q"}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\":1}"}}]},"finish_reason":"tool_calls"}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":50,"completion_tokens":7,"prompt_tokens_details":{"cached_tokens":20}}}"#,
+            "[DONE]",
+        ];
+
+        let mut accumulator = StreamAccumulator::default();
+        let mut deltas = Vec::new();
+        let mut done = false;
+        for data in chunks {
+            let event = SseEvent { event: None, data: data.to_string() };
+            done = accumulator
+                .handle_event(&event, &mut |e| {
+                    if let StreamEvent::TextDelta(text) = e {
+                        deltas.push(text);
+                    }
+                })
+                .unwrap();
+        }
+
+        let response = accumulator.finish().unwrap();
+        assert!(done);
+        assert_eq!(deltas, vec!["Hi"]);
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(
+            response.tool_uses(),
+            vec![("call_1".to_string(), "search".to_string(), json!({"q": 1}))]
+        );
+        assert_eq!(response.usage.input_tokens, 30);
+        assert_eq!(response.usage.cache_read_input_tokens, 20);
+        assert_eq!(response.usage.output_tokens, 7);
+    }
+}
@@ -1,9 +1,17 @@
 //! AI Client Service
-//! AI 客户端服务，支持 Anthropic Compatible API
+//! AI 客户端服务，支持 Anthropic Compatible API 与 OpenAI Compatible API
 
-use crate::config::AppConfig;
+use crate::config::{ApiFormat, AppConfig, AuthScheme, GenerationParams, ModelPrice, NetworkConfig, ProviderConfig, ProviderType, SecretError};
+use crate::services::adapters::{self, StreamParser};
+use crate::services::{http, replay};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::result::Result as StdResult;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
 /// AI client error type
 #[derive(Debug, thiserror::Error)]
@@ -14,46 +22,549 @@ pub enum AiError {
     ProviderNotFound(String),
     #[error("API key not configured for provider: {0}")]
     ApiKeyMissing(String),
+    #[error("Could not resolve API key: {0}")]
+    ApiKeySource(#[from] SecretError),
     #[error("HTTP error: {0}")]
     Http(String),
     #[error("API error: {0}")]
     Api(String),
+    #[error("Rate limited: {message}")]
+    RateLimited { message: String, retry_after: Option<Duration> },
+    #[error("Provider overloaded: {0}")]
+    Overloaded(String),
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+    #[error("Context too long: {0}")]
+    ContextTooLong(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Server error {status}: {message}")]
+    Server { status: u16, message: String },
     #[error("Serialization error: {0}")]
     Serialization(String),
+    #[error("Request cancelled")]
+    Cancelled,
 }
 
 pub type Result<T> = StdResult<T, AiError>;
 
+impl AiError {
+    /// Classify an error response by HTTP status and provider message
+    pub fn from_status(status: u16, message: String, retry_after: Option<Duration>) -> Self {
+        match status {
+            401 | 403 => AiError::Auth(message),
+            429 => AiError::RateLimited { message, retry_after },
+            503 | 529 => AiError::Overloaded(message),
+            400 | 413 | 422 if is_context_length_error(&message) => AiError::ContextTooLong(message),
+            400 | 404 | 413 | 422 => AiError::InvalidRequest(message),
+            500..=599 => AiError::Server { status, message },
+            _ => AiError::Api(format!("API returned {}: {}", status, message)),
+        }
+    }
+
+    /// Short category name for diagnostics
+    pub fn category(&self) -> &'static str {
+        match self {
+            AiError::NoActiveProvider | AiError::ProviderNotFound(_) => "Configuration",
+            AiError::ApiKeyMissing(_) => "Missing API key",
+            AiError::ApiKeySource(_) => "API key source",
+            AiError::Http(_) => "Network",
+            AiError::Api(_) => "API",
+            AiError::RateLimited { .. } => "Rate limited",
+            AiError::Overloaded(_) => "Overloaded",
+            AiError::Auth(_) => "Authentication",
+            AiError::ContextTooLong(_) => "Context too long",
+            AiError::InvalidRequest(_) => "Invalid request",
+            AiError::Server { .. } => "Server",
+            AiError::Serialization(_) => "Response format",
+            AiError::Cancelled => "Cancelled",
+        }
+    }
+
+    /// Transient failures that may succeed when retried
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            AiError::RateLimited { .. } | AiError::Overloaded(_) | AiError::Server { .. } | AiError::Http(_)
+        )
+    }
+
+    /// Server-requested delay before retrying (`retry-after` header)
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AiError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Whether an error message reports an oversized prompt (wording differs per provider)
+fn is_context_length_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "context_length_exceeded",
+        "context length",
+        "context window",
+        "prompt is too long",
+        "too many tokens",
+    ]
+    .iter()
+    .any(|hint| message.contains(hint))
+}
+
+/// Longest server-requested wait we are willing to sleep through before giving up
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Exponential backoff with jitter: 1s, 2s, 4s... (capped at 30s), randomized to 50-100%
+fn backoff_delay(attempt: u32) -> Duration {
+    let base = Duration::from_secs(1 << attempt.min(5)).min(Duration::from_secs(30));
+    base.mul_f64(0.5 + rand::random::<f64>() * 0.5)
+}
+
+/// Parse `retry-after-ms` / `retry-after` (seconds) response headers
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse::<f64>().ok());
+    header("retry-after-ms")
+        .map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0))
+        .or_else(|| header("retry-after").map(|secs| Duration::from_secs_f64(secs.max(0.0))))
+}
+
 /// Chat message
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
-    pub content: String,
+    pub content: MessageContent,
+}
+
+/// Message content: plain text or a list of typed content blocks
+/// (serializes to the Messages API `content` field as-is)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+impl MessageContent {
+    /// Concatenated text of all text parts
+    pub fn as_text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<Vec<ContentBlock>> for MessageContent {
+    fn from(blocks: Vec<ContentBlock>) -> Self {
+        MessageContent::Blocks(blocks)
+    }
+}
+
+impl PartialEq<&str> for MessageContent {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, MessageContent::Text(text) if text == other)
+    }
+}
+
+/// Typed content block (Anthropic Messages API format)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default)]
+        is_error: bool,
+    },
+    /// Extended thinking; the signature must be sent back unchanged in agent loops
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    /// Thinking encrypted by the provider (opaque, sent back as-is)
+    RedactedThinking {
+        data: String,
+    },
+    Image {
+        source: MediaSource,
+    },
+    /// PDF or plain text document
+    Document {
+        source: MediaSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    /// Block of a type not modelled here (e.g. `server_tool_use`), sent back as-is
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// Source of an image or document block (Anthropic `source` object)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MediaSource {
+    /// Base64-encoded binary data
+    Base64 { media_type: String, data: String },
+    /// Plain text document content
+    Text { media_type: String, data: String },
+}
+
+impl MediaSource {
+    pub fn media_type(&self) -> &str {
+        match self {
+            MediaSource::Base64 { media_type, .. } | MediaSource::Text { media_type, .. } => media_type,
+        }
+    }
+
+    /// `data:` URL of base64 content (None for text sources)
+    pub fn data_url(&self) -> Option<String> {
+        match self {
+            MediaSource::Base64 { media_type, data } => Some(format!("data:{};base64,{}", media_type, data)),
+            MediaSource::Text { .. } => None,
+        }
+    }
+}
+
+/// Build an image or document block from file bytes
+///
+/// `image/*` becomes an image block, `text/*` a text document and anything else
+/// (PDF) a base64 document.
+pub fn attachment_block(name: &str, media_type: &str, bytes: &[u8]) -> ContentBlock {
+    use base64::Engine;
+    let encoded = || base64::engine::general_purpose::STANDARD.encode(bytes);
+
+    if media_type.starts_with("image/") {
+        ContentBlock::Image {
+            source: MediaSource::Base64 { media_type: media_type.to_string(), data: encoded() },
+        }
+    } else if media_type.starts_with("text/") {
+        ContentBlock::Document {
+            source: MediaSource::Text {
+                media_type: "text/plain".to_string(),
+                data: String::from_utf8_lossy(bytes).into_owned(),
+            },
+            title: Some(name.to_string()),
+        }
+    } else {
+        ContentBlock::Document {
+            source: MediaSource::Base64 { media_type: media_type.to_string(), data: encoded() },
+            title: Some(name.to_string()),
+        }
+    }
+}
+
+/// Tool definition sent in the Messages API `tools` array
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
+}
+
+/// Structured assistant response
+#[derive(Debug, Clone, Default)]
+pub struct AiResponse {
+    /// Every content block in order (text, tool use, thinking...)
+    pub content: Vec<ContentBlock>,
+    pub stop_reason: Option<StopReason>,
+    /// The stop sequence that ended generation (Anthropic only)
+    pub stop_sequence: Option<String>,
+    pub usage: Usage,
+}
+
+/// Why the model stopped generating
+///
+/// Uses the Anthropic `stop_reason` vocabulary; other formats are mapped by their adapter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    EndTurn,
+    /// Cut off at the `max_tokens` limit; the answer can be continued
+    MaxTokens,
+    StopSequence,
+    ToolUse,
+    /// A long-running turn was paused by the provider
+    PauseTurn,
+    Refusal,
+    Other(String),
+}
+
+impl From<&str> for StopReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "end_turn" => StopReason::EndTurn,
+            "max_tokens" => StopReason::MaxTokens,
+            "stop_sequence" => StopReason::StopSequence,
+            "tool_use" => StopReason::ToolUse,
+            "pause_turn" => StopReason::PauseTurn,
+            "refusal" => StopReason::Refusal,
+            other => StopReason::Other(other.to_string()),
+        }
+    }
+}
+
+/// Token usage reported by the provider for one or more requests
+///
+/// `input_tokens` excludes cached prompt tokens, which are counted separately
+/// (Anthropic semantics; other formats are normalized by their adapter).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+}
+
+impl Usage {
+    /// Add another request's usage to this total
+    pub fn add(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Usage::default()
+    }
+
+    /// All prompt tokens, cached or not
+    pub fn total_input_tokens(&self) -> u64 {
+        self.input_tokens + self.cache_creation_input_tokens + self.cache_read_input_tokens
+    }
+
+    /// Share of prompt tokens read from the prompt cache (None without prompt tokens)
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let total = self.total_input_tokens();
+        (total > 0).then(|| self.cache_read_input_tokens as f64 / total as f64)
+    }
+
+    /// Estimated cost in USD for the given price table entry
+    pub fn cost(&self, price: &ModelPrice) -> f64 {
+        let per_token = |tokens: u64, price: f64| tokens as f64 * price / 1_000_000.0;
+        per_token(self.input_tokens, price.input)
+            + per_token(self.output_tokens, price.output)
+            + per_token(self.cache_creation_input_tokens, price.cache_write.unwrap_or(price.input))
+            + per_token(self.cache_read_input_tokens, price.cache_read.unwrap_or(price.input))
+    }
+}
+
+impl AiResponse {
+    /// The answer stopped at the `max_tokens` limit
+    pub fn is_truncated(&self) -> bool {
+        self.stop_reason == Some(StopReason::MaxTokens)
+    }
+
+    /// Concatenated text of all text blocks
+    pub fn text(&self) -> String {
+        MessageContent::Blocks(self.content.clone()).as_text()
+    }
+
+    /// Concatenated text of all thinking blocks
+    pub fn thinking(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Thinking { thinking, .. } => Some(thinking.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Tool use requests as (id, name, input)
+    pub fn tool_uses(&self) -> Vec<(String, String, serde_json::Value)> {
+        self.content
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::ToolUse { id, name, input } => Some((id.clone(), name.clone(), input.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Incremental event emitted while a streaming response is received
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// A chunk of assistant text
+    TextDelta(String),
+    /// A chunk of extended thinking (or reasoning) text
+    ThinkingDelta(String),
+    /// The request failed with a transient error and will be retried after `delay`
+    Retrying { attempt: u32, max_retries: u32, delay: Duration, reason: String },
+    /// The provider failed; falling through to the next provider in the fallback chain
+    Fallback { from: String, to: String, reason: String },
+    /// The provider that is answering this request (emitted before any content)
+    Provider { id: String, name: String },
+    /// Token usage of a completed request; `cost` is None when the model has no price configured
+    Usage { usage: Usage, cost: Option<f64> },
+}
+
+/// Result of a provider connection test (see [`AiClient::test_connection`])
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionTest {
+    /// Resolved request URL
+    pub endpoint: String,
+    pub auth_scheme: String,
+    pub model: String,
+    /// Time until the complete response was received
+    pub latency: Duration,
+    /// HTTP status (None when no response was received)
+    pub status: Option<u16>,
+    /// Error category and message (None = success)
+    pub error: Option<(String, String)>,
+}
+
+impl ConnectionTest {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Last connection test per provider id (kept in memory for the session)
+static CONNECTION_TESTS: LazyLock<Mutex<HashMap<String, ConnectionTest>>> = LazyLock::new(Default::default);
+
+/// Active provider resolved from the configuration
+struct ActiveProvider {
+    id: String,
+    name: String,
+    /// None for keyless local providers (e.g. Ollama)
+    api_key: Option<String>,
+    base_url: String,
+    model: String,
+    api_format: ApiFormat,
+    max_retries: u32,
+    auth_scheme: AuthScheme,
+    /// Shared client with this provider's timeouts and the network settings
+    client: reqwest::Client,
+    extra_headers: HashMap<String, String>,
+    price: Option<ModelPrice>,
+    generation: GenerationParams,
+    prompt_caching: bool,
+    context_window: u64,
+    /// Fixture served instead of calling the network (mock provider)
+    replay: Option<PathBuf>,
+    /// Fixture that every exchange is appended to (record mode)
+    record: Option<PathBuf>,
 }
 
-/// AI Client for making Anthropic-compatible API requests
+impl ActiveProvider {
+    /// POST `body` to the provider endpoint with auth and extra headers
+    ///
+    /// The mock provider answers from its fixture instead; in record mode the
+    /// exchange is also appended to the record fixture.
+    async fn post(&self, body: &serde_json::Value) -> Result<reqwest::Response> {
+        let response = match &self.replay {
+            Some(path) => replay::replay(path)?,
+            None => {
+                let request = self
+                    .client
+                    .post(adapters::endpoint_url(&self.api_format, &self.base_url))
+                    .header("Content-Type", "application/json")
+                    .json(body);
+                let request = adapters::apply_headers(&self.api_format, request, self.api_key.as_deref(), &self.auth_scheme);
+                http::with_extra_headers(request, &self.extra_headers)
+                    .send()
+                    .await
+                    .map_err(|e: reqwest::Error| AiError::Http(e.to_string()))?
+            }
+        };
+        Ok(match &self.record {
+            Some(path) => replay::record(path.clone(), body, response),
+            None => response,
+        })
+    }
+
+    /// Report a completed request's usage (with estimated cost) to `on_event`
+    fn report_usage<F>(&self, usage: Usage, on_event: &mut F)
+    where
+        F: FnMut(StreamEvent),
+    {
+        if usage.is_empty() {
+            return;
+        }
+        on_event(StreamEvent::Usage {
+            usage,
+            cost: self.price.as_ref().map(|p| usage.cost(p)),
+        });
+    }
+}
+
+/// AI Client for making chat requests (Anthropic Messages or OpenAI-compatible Chat Completions)
 pub struct AiClient;
 
 impl AiClient {
-    /// Get the active provider configuration
-    fn get_active_provider_config() -> Result<(String, String, String, String)> {
+    /// Get the active provider followed by its usable fallback providers
+    fn get_provider_chain() -> Result<Vec<ActiveProvider>> {
         let config = AppConfig::load().map_err(|e| AiError::Http(e.to_string()))?;
 
         let active_id = config
             .ai
             .active_provider
+            .clone()
             .ok_or(AiError::NoActiveProvider)?;
 
         let provider = config
             .ai
             .providers
-            .into_iter()
+            .iter()
             .find(|p| p.id == active_id)
+            .cloned()
             .ok_or_else(|| AiError::ProviderNotFound(active_id.clone()))?;
 
-        let api_key = provider
-            .api_key
-            .ok_or_else(|| AiError::ApiKeyMissing(active_id.clone()))?;
+        let mut chain = vec![Self::resolve_provider(provider, &config.network)?];
+        // Misconfigured fallbacks are skipped rather than failing the request
+        chain.extend(
+            config
+                .get_fallback_providers()
+                .into_iter()
+                .filter_map(|p| Self::resolve_provider(p.clone(), &config.network).ok()),
+        );
+        Ok(chain)
+    }
+
+    /// Resolve a provider's connection settings, checking the required fields
+    fn resolve_provider(provider: ProviderConfig, network: &NetworkConfig) -> Result<ActiveProvider> {
+        let active_id = provider.id.clone();
+
+        let api_key = provider.resolve_api_key()?;
+        let max_retries = provider.max_retries();
+        let client = http::client(network, provider.connect_timeout(network), provider.read_timeout(network))?;
+        let price = provider.model.as_deref().and_then(|m| provider.price_for(m)).cloned();
+        let prompt_caching = provider.prompt_caching();
+        let context_window = provider.context_window();
+        let auth_scheme = provider.auth_scheme();
+        let replay = (provider.provider_type == ProviderType::Mock)
+            .then(|| provider.base_url.as_deref().map(PathBuf::from))
+            .flatten();
+        let record = provider.record_fixture.as_deref().filter(|p| !p.trim().is_empty()).map(PathBuf::from);
+        if api_key.is_none() && provider.provider_type.requires_api_key() {
+            return Err(AiError::ApiKeyMissing(active_id));
+        }
 
         let base_url = provider.base_url.ok_or_else(|| {
             AiError::Api(format!("Base URL not configured for {}", active_id))
@@ -63,60 +574,332 @@ impl AiClient {
             .model
             .ok_or_else(|| AiError::Api(format!("Model not configured for {}", active_id)))?;
 
-        Ok((active_id, api_key, base_url, model))
+        Ok(ActiveProvider {
+            id: provider.id,
+            name: provider.name,
+            api_key,
+            base_url,
+            model,
+            api_format: provider.api_format,
+            max_retries,
+            auth_scheme,
+            client,
+            extra_headers: provider.extra_headers,
+            price,
+            generation: provider.generation,
+            prompt_caching,
+            context_window,
+            replay,
+            record,
+        })
+    }
+
+    /// Send a request through the provider's adapter, retrying transient failures
+    ///
+    /// Rate limits, overload, 5xx responses and connection errors are retried up to
+    /// the provider's `max_retries` with exponential backoff (or the server's
+    /// `retry-after`); each retry is reported as [`StreamEvent::Retrying`].
+    /// Returns the raw response once the status is 2xx.
+    async fn send_request<F>(
+        provider: &ActiveProvider,
+        body: &serde_json::Value,
+        cancel: &CancellationToken,
+        on_event: &mut F,
+    ) -> Result<reqwest::Response>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let mut attempt = 0;
+        loop {
+            let result = tokio::select! {
+                _ = cancel.cancelled() => return Err(AiError::Cancelled),
+                result = Self::send_once(provider, body) => result,
+            };
+            let error = match result {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+
+            let delay = error.retry_after().unwrap_or_else(|| backoff_delay(attempt));
+            if attempt >= provider.max_retries || !error.is_retryable() || delay > MAX_RETRY_AFTER {
+                return Err(error);
+            }
+
+            attempt += 1;
+            eprintln!("[AI] {} - retry {}/{} in {:?}", error, attempt, provider.max_retries, delay);
+            on_event(StreamEvent::Retrying {
+                attempt,
+                max_retries: provider.max_retries,
+                delay,
+                reason: error.to_string(),
+            });
+
+            tokio::select! {
+                _ = cancel.cancelled() => return Err(AiError::Cancelled),
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+
+    /// Send a request through the fallback chain
+    ///
+    /// Each provider gets its own request body (model, wire format and default
+    /// generation parameters differ); `overrides` are applied on top of the defaults.
+    /// A provider that still fails with a retryable error after its retries hands
+    /// over to the next one; this only happens before any content was received.
+    /// Returns the response together with the provider that answered.
+    async fn send_with_fallback<F>(
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDefinition],
+        overrides: &GenerationParams,
+        stream: bool,
+        cancel: &CancellationToken,
+        on_event: &mut F,
+    ) -> Result<(reqwest::Response, ActiveProvider)>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let mut chain = Self::get_provider_chain()?.into_iter().peekable();
+
+        while let Some(provider) = chain.next() {
+            let params = provider.generation.merged(overrides);
+            let body = adapters::build_request_body(
+                &provider.api_format,
+                &provider.model,
+                messages.clone(),
+                tools,
+                &params,
+                provider.prompt_caching,
+                provider.context_window,
+                stream,
+            );
+            match Self::send_request(&provider, &body, cancel, on_event).await {
+                Ok(response) => {
+                    on_event(StreamEvent::Provider {
+                        id: provider.id.clone(),
+                        name: provider.name.clone(),
+                    });
+                    return Ok((response, provider));
+                }
+                Err(e) if e.is_retryable() => {
+                    let Some(next) = chain.peek() else { return Err(e) };
+                    eprintln!("[AI] {} failed ({}), falling back to {}", provider.name, e, next.name);
+                    on_event(StreamEvent::Fallback {
+                        from: provider.name.clone(),
+                        to: next.name.clone(),
+                        reason: e.to_string(),
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        // get_provider_chain always contains at least the active provider
+        Err(AiError::NoActiveProvider)
     }
 
-    /// Send a chat completion request using Anthropic Messages API format
-    /// All configured providers must be Anthropic-compatible
-    pub async fn chat_completion(messages: Vec<ChatMessage>) -> Result<String> {
-        let (_provider_id, api_key, base_url, model) = Self::get_active_provider_config()?;
+    /// Single request attempt (errors on non-2xx status)
+    async fn send_once(provider: &ActiveProvider, body: &serde_json::Value) -> Result<reqwest::Response> {
+        let response = provider.post(body).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = parse_retry_after(response.headers());
+            let body = response
+                .text()
+                .await
+                .map_err(|e: reqwest::Error| AiError::Http(e.to_string()))?;
+            return Err(AiError::from_status(
+                status.as_u16(),
+                adapters::error_message(&provider.api_format, &body),
+                retry_after,
+            ));
+        }
+
+        Ok(response)
+    }
 
-        // All providers use Anthropic Messages API format
-        // Base URL should already point to the correct endpoint (e.g., https://api.anthropic.com/v1/messages or https://api.kimi.com/coding/v1/messages)
-        let url = if base_url.ends_with("/messages") || base_url.contains("/v1/messages") {
-            base_url
-        } else if base_url.ends_with("/anthropic") {
-            format!("{}/v1/messages", base_url)
-        } else if base_url.ends_with("/coding") {
-            format!("{}/v1/messages", base_url)
-        } else {
-            format!("{}/v1/messages", base_url)
+    /// Send a chat completion request using the active provider's API format
+    ///
+    /// `overrides` (e.g. per-session settings) take precedence over the provider's
+    /// default generation parameters.
+    /// Retries and fallbacks are reported to `on_event`. Cancelling `cancel` drops the
+    /// in-flight request and returns [`AiError::Cancelled`].
+    pub async fn chat_completion<F>(
+        messages: Vec<ChatMessage>,
+        overrides: &GenerationParams,
+        cancel: &CancellationToken,
+        mut on_event: F,
+    ) -> Result<AiResponse>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let (response, provider) = Self::send_with_fallback(messages, &[], overrides, false, cancel, &mut on_event).await?;
+        let body = tokio::select! {
+            _ = cancel.cancelled() => return Err(AiError::Cancelled),
+            body = response.text() => body.map_err(|e: reqwest::Error| AiError::Http(e.to_string()))?,
         };
 
-        // Extract system message (if any) and filter messages to only user/assistant
-        let system_message = messages.iter()
-            .find(|m| m.role == "system")
-            .map(|m| m.content.clone());
-
-        let filtered_messages: Vec<_> = messages.into_iter()
-            .filter(|m| m.role == "user" || m.role == "assistant")
-            .map(|m| serde_json::json!({
-                "role": m.role,
-                "content": m.content
-            }))
-            .collect();
-
-        // Build request body (with optional system parameter)
-        let mut request_body_json = serde_json::json!({
-            "model": model,
-            "max_tokens": 4096,
-            "messages": filtered_messages,
-        });
+        let response = adapters::parse_response(&provider.api_format, &body)?;
+        provider.report_usage(response.usage, &mut on_event);
+        Ok(response)
+    }
 
-        // Add system parameter if exists
-        if let Some(system) = system_message {
-            if let Some(obj) = request_body_json.as_object_mut() {
-                obj.insert("system".to_string(), serde_json::Value::String(system));
+    /// Send a streaming chat completion request (`"stream": true`)
+    ///
+    /// Text deltas are forwarded to `on_event` as they arrive.
+    /// Returns the complete text once the stream ends.
+    pub async fn chat_completion_stream<F>(
+        messages: Vec<ChatMessage>,
+        overrides: &GenerationParams,
+        cancel: &CancellationToken,
+        on_event: F,
+    ) -> Result<String>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let response = Self::stream_with_tools(messages, &[], overrides, cancel, on_event).await?;
+        Ok(response.text())
+    }
+
+    /// Send a streaming request with native tool definitions
+    ///
+    /// The provider's adapter decodes the stream (Anthropic SSE events or
+    /// Chat Completions chunks) into an [`AiResponse`] holding all text and
+    /// tool use blocks. Text deltas, retries, fallbacks and usage are forwarded to `on_event`.
+    /// Cancelling `cancel` aborts the HTTP request, even mid-stream.
+    pub async fn stream_with_tools<F>(
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDefinition],
+        overrides: &GenerationParams,
+        cancel: &CancellationToken,
+        mut on_event: F,
+    ) -> Result<AiResponse>
+    where
+        F: FnMut(StreamEvent),
+    {
+        let (response, provider) = Self::send_with_fallback(messages, tools, overrides, true, cancel, &mut on_event).await?;
+
+        let mut stream = response.bytes_stream();
+        let mut parser = StreamParser::new(&provider.api_format);
+
+        loop {
+            // Dropping the stream on cancellation closes the connection
+            let chunk = tokio::select! {
+                _ = cancel.cancelled() => return Err(AiError::Cancelled),
+                chunk = stream.next() => chunk,
+            };
+            let Some(chunk) = chunk else { break };
+            let chunk = chunk.map_err(|e: reqwest::Error| AiError::Http(e.to_string()))?;
+            if parser.push(&chunk, &mut on_event)? {
+                break;
+            }
+        }
+
+        let response = parser.finish(&mut on_event)?;
+        provider.report_usage(response.usage, &mut on_event);
+        Ok(response)
+    }
+
+    /// Send a minimal request to check a provider's key, base URL and model
+    ///
+    /// No retries or fallbacks are used, so the result reflects this provider only.
+    /// The result is remembered (see [`AiClient::connection_tests`]).
+    pub async fn test_connection(provider: &ProviderConfig) -> ConnectionTest {
+        let provider_id = provider.id.clone();
+        let mut test = ConnectionTest {
+            // Replaced by the endpoint actually contacted once the provider resolves
+            endpoint: provider.base_url.clone().unwrap_or_else(|| "(no base URL)".to_string()),
+            auth_scheme: match provider.api_key() {
+                Some(_) => provider.auth_scheme().label(),
+                None => AuthScheme::None.label(),
+            },
+            model: provider.model.clone().unwrap_or_default(),
+            latency: Duration::ZERO,
+            status: None,
+            error: None,
+        };
+
+        let started = Instant::now();
+        let network = AppConfig::load().map(|c| c.network).unwrap_or_default();
+        let result = match Self::resolve_provider(provider.clone(), &network) {
+            Ok(active) => {
+                test.endpoint = match &active.replay {
+                    Some(path) => format!("replay {}", path.display()),
+                    None => adapters::endpoint_url(&active.api_format, &active.base_url),
+                };
+                Self::send_test_request(&active, &mut test.status).await
             }
+            Err(e) => Err(e),
+        };
+        test.latency = started.elapsed();
+        if let Err(e) = result {
+            test.error = Some((e.category().to_string(), e.to_string()));
         }
 
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .header("anthropic-version", "2023-06-01")
-            .json(&request_body_json)
+        CONNECTION_TESTS.lock().unwrap().insert(provider_id, test.clone());
+        test
+    }
+
+    /// Send a tiny "ping" request, recording the HTTP status
+    async fn send_test_request(provider: &ActiveProvider, status: &mut Option<u16>) -> Result<()> {
+        let params = GenerationParams {
+            max_tokens: Some(16),
+            ..Default::default()
+        };
+        let body = adapters::build_request_body(
+            &provider.api_format,
+            &provider.model,
+            vec![user_message("ping".to_string())],
+            &[],
+            &params,
+            false,
+            provider.context_window,
+            false,
+        );
+        let response = provider.post(&body).await?;
+
+        let code = response.status();
+        *status = Some(code.as_u16());
+        let retry_after = parse_retry_after(response.headers());
+        let body = response
+            .text()
+            .await
+            .map_err(|e: reqwest::Error| AiError::Http(e.to_string()))?;
+        if !code.is_success() {
+            return Err(AiError::from_status(
+                code.as_u16(),
+                adapters::error_message(&provider.api_format, &body),
+                retry_after,
+            ));
+        }
+        adapters::parse_response(&provider.api_format, &body).map(|_| ())
+    }
+
+    /// Last connection test result per provider id
+    pub fn connection_tests() -> HashMap<String, ConnectionTest> {
+        CONNECTION_TESTS.lock().unwrap().clone()
+    }
+
+    /// List the models a provider offers (Anthropic/OpenAI `/v1/models`, Ollama `/api/tags`)
+    ///
+    /// Uses the provider as configured in the form, so it works before the provider is saved.
+    pub async fn list_models(provider: &ProviderConfig) -> Result<Vec<String>> {
+        let base_url = provider
+            .base_url
+            .clone()
+            .unwrap_or_else(|| provider.provider_type.default_base_url().to_string());
+        if provider.provider_type == ProviderType::Mock {
+            return replay::models(Path::new(&base_url));
+        }
+        let url = adapters::models_url(&provider.api_format, &base_url);
+
+        let network = AppConfig::load().map(|c| c.network).unwrap_or_default();
+        let client = http::client(&network, provider.connect_timeout(&network), provider.read_timeout(&network))?;
+        let api_key = provider.resolve_api_key()?;
+        let request = adapters::apply_headers(&provider.api_format, client.get(&url), api_key.as_deref(), &provider.auth_scheme());
+        let request = http::with_extra_headers(request, &provider.extra_headers);
+        let response = request
             .send()
             .await
             .map_err(|e: reqwest::Error| AiError::Http(e.to_string()))?;
@@ -126,38 +909,18 @@ impl AiClient {
             .text()
             .await
             .map_err(|e: reqwest::Error| AiError::Http(e.to_string()))?;
-
         if !status.is_success() {
-            return Err(AiError::Api(format!(
-                "API returned {}: {}",
+            return Err(AiError::from_status(
                 status.as_u16(),
-                body
-            )));
+                adapters::error_message(&provider.api_format, &body),
+                None,
+            ));
         }
 
-        // Parse Anthropic Messages API response
-        Self::parse_anthropic_response(&body)
-    }
-
-    /// Parse Anthropic Messages API response
-    fn parse_anthropic_response(body: &str) -> Result<String> {
-        #[derive(Deserialize)]
-        struct ClaudeResponse {
-            content: Vec<ClaudeContent>,
-        }
-        #[derive(Deserialize)]
-        struct ClaudeContent {
-            text: String,
-        }
-        let resp: ClaudeResponse =
-            serde_json::from_str(body).map_err(|e: serde_json::Error| {
-                AiError::Serialization(format!("Failed to parse response: {}", e))
-            })?;
-        Ok(resp
-            .content
-            .get(0)
-            .map(|c| c.text.clone())
-            .unwrap_or_default())
+        let mut models = adapters::parse_models(&provider.api_format, &body)?;
+        models.sort();
+        models.dedup();
+        Ok(models)
     }
 }
 
@@ -165,7 +928,7 @@ impl AiClient {
 pub fn user_message(content: String) -> ChatMessage {
     ChatMessage {
         role: "user".to_string(),
-        content,
+        content: content.into(),
     }
 }
 
@@ -173,7 +936,7 @@ pub fn user_message(content: String) -> ChatMessage {
 pub fn system_message(content: String) -> ChatMessage {
     ChatMessage {
         role: "system".to_string(),
-        content,
+        content: content.into(),
     }
 }
 
@@ -181,13 +944,14 @@ pub fn system_message(content: String) -> ChatMessage {
 pub fn assistant_message(content: String) -> ChatMessage {
     ChatMessage {
         role: "assistant".to_string(),
-        content,
+        content: content.into(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::replay::{Exchange, Fixture};
 
     #[test]
     fn test_create_messages() {
@@ -201,4 +965,133 @@ mod tests {
         let asst_msg = assistant_message("Hi there!".to_string());
         assert_eq!(asst_msg.role, "assistant");
     }
+
+    #[test]
+    fn test_error_classification() {
+        let retry_after = Some(Duration::from_secs(3));
+        let err = AiError::from_status(429, "slow down".to_string(), retry_after);
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), retry_after);
+
+        assert!(matches!(AiError::from_status(529, "Overloaded".to_string(), None), AiError::Overloaded(_)));
+        assert!(matches!(AiError::from_status(401, "bad key".to_string(), None), AiError::Auth(_)));
+        assert!(matches!(
+            AiError::from_status(400, "prompt is too long: 210000 tokens > 200000 maximum".to_string(), None),
+            AiError::ContextTooLong(_)
+        ));
+        let invalid = AiError::from_status(400, "max_tokens: must be positive".to_string(), None);
+        assert!(matches!(invalid, AiError::InvalidRequest(_)));
+        assert!(!invalid.is_retryable());
+
+        for attempt in 0..10 {
+            let delay = backoff_delay(attempt);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(30));
+        }
+    }
+
+    #[test]
+    fn test_auth_schemes() {
+        assert_eq!(
+            AuthScheme::Bearer.header("k"),
+            Some(("Authorization".to_string(), "Bearer k".to_string()))
+        );
+        assert_eq!(AuthScheme::XApiKey.header("k"), Some(("x-api-key".to_string(), "k".to_string())));
+        assert_eq!(AuthScheme::Header(" api-key ".to_string()).header("k"), Some(("api-key".to_string(), "k".to_string())));
+        assert_eq!(AuthScheme::None.header("k"), None);
+
+        let scheme: AuthScheme = serde_json::from_str(r#"{"header":"api-key"}"#).unwrap();
+        assert_eq!(scheme, AuthScheme::Header("api-key".to_string()));
+        assert_eq!(serde_json::to_string(&AuthScheme::XApiKey).unwrap(), r#""x_api_key""#);
+    }
+
+    #[test]
+    fn test_usage_cost() {
+        let mut usage = Usage { input_tokens: 1_000_000, output_tokens: 100_000, ..Default::default() };
+        usage.add(&Usage { cache_read_input_tokens: 2_000_000, ..Default::default() });
+        assert_eq!(usage.total_input_tokens(), 3_000_000);
+        assert!((usage.cache_hit_rate().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(Usage::default().cache_hit_rate(), None);
+
+        let price = ModelPrice { input: 3.0, output: 15.0, cache_write: None, cache_read: Some(0.3) };
+        assert!((usage.cost(&price) - (3.0 + 1.5 + 0.6)).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_mock_stream_with_tool_use() {
+        // Events split mid-line across chunks, as they arrive from the network
+        let stream = Exchange::stream([
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":9,\"output_tokens\":1}}}\n\ndata: {\"type\":\"content_block_start\",\"index\":0,",
+            "\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Checking.\"}}\n\n",
+            "data: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"weather\",\"input\":{}}}\n\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\": \\\"Oslo\\\"}\"}}\n\n",
+            "data: {\"type\":\"content_block_stop\",\"index\":1}\n\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":20}}\n\ndata: {\"type\":\"message_stop\"}\n\n",
+        ]);
+        let _guard = replay::testing::use_mock_provider(ApiFormat::Anthropic, Fixture { models: vec![], exchanges: vec![stream] }).await;
+
+        let mut events = Vec::new();
+        let response = AiClient::stream_with_tools(
+            vec![user_message("Weather in Oslo?".to_string())],
+            &[],
+            &GenerationParams::default(),
+            &CancellationToken::new(),
+            |e| events.push(e),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.text(), "Checking.");
+        assert_eq!(
+            response.tool_uses(),
+            vec![("toolu_1".to_string(), "weather".to_string(), serde_json::json!({"city": "Oslo"}))]
+        );
+        assert!(matches!(&events[0], StreamEvent::Provider { id, .. } if id == "mock"));
+        assert!(events.iter().any(|e| matches!(e, StreamEvent::TextDelta(t) if t == "Checking.")));
+        assert!(matches!(events.last(), Some(StreamEvent::Usage { usage, .. }) if usage.output_tokens == 20));
+    }
+
+    #[tokio::test]
+    async fn test_mock_retries_and_errors() {
+        let rate_limited = serde_json::json!({"type": "error", "error": {"type": "rate_limit_error", "message": "Slow down"}});
+        let exchanges = vec![
+            Exchange::error(429, rate_limited).with_header("retry-after-ms", "1"),
+            Exchange::json(serde_json::json!({
+                "content": [{"type": "text", "text": "pong"}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 5, "output_tokens": 1}
+            })),
+            Exchange::error(401, serde_json::json!({"type": "error", "error": {"type": "authentication_error", "message": "invalid x-api-key"}})),
+        ];
+        let _guard = replay::testing::use_mock_provider(ApiFormat::Anthropic, Fixture { models: vec![], exchanges }).await;
+        let messages = vec![user_message("ping".to_string())];
+        let cancel = CancellationToken::new();
+
+        let mut events = Vec::new();
+        let response = AiClient::chat_completion(messages.clone(), &GenerationParams::default(), &cancel, |e| events.push(e))
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "pong");
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+        assert!(matches!(&events[0], StreamEvent::Retrying { attempt: 1, delay, .. } if *delay == Duration::from_millis(1)));
+
+        let error = AiClient::chat_completion(messages, &GenerationParams::default(), &cancel, |_| {})
+            .await
+            .unwrap_err();
+        assert!(matches!(&error, AiError::Auth(message) if message.contains("invalid x-api-key")));
+    }
+
+    #[tokio::test]
+    async fn test_connection_shows_contacted_endpoint() {
+        let _guard = replay::testing::use_mock_provider(ApiFormat::Anthropic, Fixture::default()).await;
+        let mut provider = AppConfig::load().unwrap().get_usable_provider().unwrap().clone();
+
+        let test = AiClient::test_connection(&provider).await;
+        assert!(test.endpoint.starts_with("replay "));
+
+        // Nothing is contacted without a base URL
+        provider.base_url = None;
+        let test = AiClient::test_connection(&provider).await;
+        assert_eq!(test.endpoint, "(no base URL)");
+        assert_eq!(test.status, None);
+        assert!(!test.is_ok());
+    }
 }
@@ -0,0 +1,255 @@
+//! Context window management
+//! 上下文管理 - 估算 token、按策略裁剪或总结较早的对话
+
+use crate::config::{ContextConfig, ContextPolicy, GenerationParams, ProviderConfig};
+use crate::services::ai_client::{system_message, user_message, AiClient, ChatMessage, Result};
+use std::ops::Range;
+use tokio_util::sync::CancellationToken;
+
+/// Rough token count of a text
+///
+/// Tokenizers differ per model, so this errs on the high side: about 3.5
+/// characters per token for Latin text and one token per CJK (or other
+/// non-ASCII) character.
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    let (ascii, other) = text.chars().fold((0u64, 0u64), |(ascii, other), c| {
+        if c.is_ascii() { (ascii + 1, other) } else { (ascii, other + 1) }
+    });
+    (ascii * 2).div_ceil(7) + other
+}
+
+/// Rough token count of an attachment of `size` bytes
+pub fn estimate_attachment_tokens(media_type: &str, size: u64) -> u64 {
+    if media_type.starts_with("image/") {
+        // Images are resized by the provider; a large image costs about 1.6k tokens
+        1_600
+    } else if media_type.starts_with("text/") {
+        (size * 2).div_ceil(7)
+    } else {
+        // PDFs cost text plus a page image; assume ~2k tokens per 50 KB page
+        size.div_ceil(50_000).max(1) * 2_000
+    }
+}
+
+/// Estimated size of one message and whether it starts a new turn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MessageCost {
+    pub tokens: u64,
+    pub turn_start: bool,
+}
+
+impl MessageCost {
+    /// Cost of a message with the given text and attachments (media type, size in bytes)
+    pub fn new<'a>(role: &str, text: &str, attachments: impl IntoIterator<Item = (&'a str, u64)>) -> Self {
+        // Role and message framing
+        const OVERHEAD: u64 = 4;
+        let attachments: u64 = attachments
+            .into_iter()
+            .map(|(media_type, size)| estimate_attachment_tokens(media_type, size))
+            .sum();
+        MessageCost {
+            tokens: estimate_text_tokens(text) + attachments + OVERHEAD,
+            turn_start: role == "user",
+        }
+    }
+}
+
+/// Token budget for the conversation sent to `provider`
+///
+/// The model's context window minus the output reserve (`max_tokens`, at most a
+/// quarter of the window), less a 10% margin for tool definitions and estimation error.
+pub fn context_budget(provider: &ProviderConfig, context: &ContextConfig, generation: &GenerationParams) -> u64 {
+    context.max_context_tokens.unwrap_or_else(|| {
+        let window = provider.context_window();
+        let reserve = (provider.generation.merged(generation).max_tokens() as u64).min(window / 4);
+        window.saturating_sub(reserve) * 9 / 10
+    })
+}
+
+/// Which messages of a session fit into the context
+///
+/// Messages in `excluded()` (between the pinned messages and `first_kept`) are
+/// left out; whole turns are dropped, oldest first, and the latest turn is always kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContextPlan {
+    /// Leading messages that are always kept (pin-first policy)
+    pub pinned: usize,
+    /// First message of the kept tail
+    pub first_kept: usize,
+    /// Estimated tokens of the kept messages
+    pub estimated_tokens: u64,
+    pub budget: u64,
+}
+
+impl ContextPlan {
+    pub fn excluded(&self) -> Range<usize> {
+        self.pinned..self.first_kept
+    }
+
+    pub fn is_trimmed(&self) -> bool {
+        !self.excluded().is_empty()
+    }
+
+    /// Kept messages still over budget (the latest turn alone is too long)
+    pub fn is_over_budget(&self) -> bool {
+        self.estimated_tokens > self.budget
+    }
+}
+
+/// Fit messages with the given costs into `budget` tokens according to the policy
+///
+/// `reserved` tokens (e.g. the summary of older turns) are counted against the
+/// budget as soon as anything is dropped.
+pub fn plan_context(costs: &[MessageCost], budget: u64, context: &ContextConfig, reserved: u64) -> ContextPlan {
+    let pinned = match context.policy {
+        ContextPolicy::PinFirst => context.pinned_messages().min(costs.len()),
+        _ => 0,
+    };
+
+    let mut plan = ContextPlan {
+        pinned,
+        first_kept: pinned,
+        estimated_tokens: costs.iter().map(|c| c.tokens).sum(),
+        budget,
+    };
+    while plan.estimated_tokens > budget {
+        // Drop up to the start of the next turn
+        let Some(next_turn) = (plan.first_kept + 1..costs.len()).find(|&i| costs[i].turn_start) else {
+            break;
+        };
+        if !plan.is_trimmed() {
+            plan.estimated_tokens += reserved;
+        }
+        plan.estimated_tokens -= costs[plan.first_kept..next_turn].iter().map(|c| c.tokens).sum::<u64>();
+        plan.first_kept = next_turn;
+    }
+    plan
+}
+
+/// Messages to send: the pinned ones, the summary of the dropped turns (if any), then the kept tail
+pub fn apply_plan(messages: &[ChatMessage], plan: &ContextPlan, summary: Option<&str>) -> Vec<ChatMessage> {
+    let mut result = messages[..plan.pinned].to_vec();
+    if let Some(summary) = summary.filter(|_| plan.is_trimmed()) {
+        result.push(summary_message(summary));
+    }
+    result.extend_from_slice(&messages[plan.first_kept..]);
+    result
+}
+
+fn summary_message(summary: &str) -> ChatMessage {
+    system_message(format!(
+        "Summary of the earlier part of this conversation (those messages are no longer included):\n\n{}",
+        summary
+    ))
+}
+
+/// Output limit of a summary (also reserved in the budget before the first summary exists)
+pub const SUMMARY_MAX_TOKENS: u64 = 1_024;
+
+/// Summarize dropped turns with the active provider, extending an earlier summary if there is one
+pub async fn summarize(previous: Option<&str>, messages: &[ChatMessage], cancel: &CancellationToken) -> Result<String> {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("[Summary of what came before]\n{}\n\n", previous));
+    }
+    for message in messages {
+        transcript.push_str(&format!("[{}]\n{}\n\n", message.role, message.content.as_text()));
+    }
+
+    let request = vec![
+        system_message(
+            "You condense conversations. Summarize the transcript so the conversation can continue without it: \
+             keep the user's goals, decisions, facts, names, numbers, code identifiers and open questions. \
+             Be concise and write in the language of the conversation."
+                .to_string(),
+        ),
+        user_message(transcript),
+    ];
+    let params = GenerationParams { max_tokens: Some(SUMMARY_MAX_TOKENS as u32), ..Default::default() };
+    Ok(AiClient::chat_completion(request, &params, cancel, |_| {}).await?.text())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AppConfig, ProviderType};
+
+    /// `count` turns of a user and an assistant message of ~27 tokens each
+    fn turns(count: usize) -> Vec<MessageCost> {
+        let text = "word ".repeat(16);
+        (0..count)
+            .flat_map(|_| ["user", "assistant"].map(|role| MessageCost::new(role, &text, [])))
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcdefg"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+        assert_eq!(estimate_attachment_tokens("application/pdf", 120_000), 6_000);
+        let cost = MessageCost::new("user", "abcdefg", [("image/png", 3_000_000)]);
+        assert_eq!(cost, MessageCost { tokens: 2 + 1_600 + 4, turn_start: true });
+    }
+
+    #[test]
+    fn test_sliding_window_drops_whole_turns() {
+        let costs = turns(10);
+        let total: u64 = costs.iter().map(|c| c.tokens).sum();
+        let context = ContextConfig::default();
+
+        let plan = plan_context(&costs, total, &context, 0);
+        assert!(!plan.is_trimmed());
+
+        let plan = plan_context(&costs, total / 2, &context, 0);
+        assert_eq!(plan.excluded(), 0..10);
+        assert!(plan.estimated_tokens <= total / 2);
+
+        let messages: Vec<ChatMessage> = (0..20).map(|i| user_message(i.to_string())).collect();
+        let sent = apply_plan(&messages, &plan, None);
+        assert_eq!(sent, messages[10..]);
+
+        // The latest turn is kept even when it alone exceeds the budget
+        let plan = plan_context(&costs, 1, &context, 0);
+        assert_eq!(plan.first_kept, 18);
+        assert!(plan.is_over_budget());
+    }
+
+    #[test]
+    fn test_pin_first_and_summary() {
+        let costs = turns(10);
+        let total: u64 = costs.iter().map(|c| c.tokens).sum();
+        let messages: Vec<ChatMessage> = (0..20).map(|i| user_message(i.to_string())).collect();
+
+        let pin = ContextConfig { policy: ContextPolicy::PinFirst, ..Default::default() };
+        let plan = plan_context(&costs, total / 2, &pin, 0);
+        assert_eq!(plan.pinned, 2);
+        assert!(plan.first_kept > 2);
+        let sent = apply_plan(&messages, &plan, None);
+        assert_eq!(sent[..2], messages[..2]);
+        assert_eq!(sent[2], messages[plan.first_kept]);
+
+        let summarize = ContextConfig { policy: ContextPolicy::SummarizeOlder, ..Default::default() };
+        let without_reserve = plan_context(&costs, total / 2, &summarize, 0);
+        let plan = plan_context(&costs, total / 2, &summarize, 100);
+        assert!(plan.first_kept > without_reserve.first_kept);
+        let sent = apply_plan(&messages, &plan, Some("Earlier: greetings."));
+        assert_eq!(sent[0].role, "system");
+        assert!(sent[0].content.as_text().contains("Earlier: greetings."));
+        assert_eq!(sent.len(), messages.len() - plan.first_kept + 1);
+    }
+
+    #[test]
+    fn test_budget_of_unconfigured_ollama() {
+        let mut provider = AppConfig::default().ai.providers[0].clone();
+        provider.provider_type = ProviderType::Ollama;
+        provider.context_window = None;
+        let context = ContextConfig::default();
+
+        // The default max_tokens (4096) would use up the whole 4096-token window
+        assert_eq!(context_budget(&provider, &context, &GenerationParams::default()), (4_096 - 1_024) * 9 / 10);
+
+        provider.context_window = Some(32_000);
+        assert_eq!(context_budget(&provider, &context, &GenerationParams::default()), (32_000 - 4_096) * 9 / 10);
+    }
+}
@@ -0,0 +1,109 @@
+//! Shared HTTP client
+//! 共享 HTTP 客户端 - 代理、超时与自定义 CA 证书
+
+use crate::config::NetworkConfig;
+use crate::services::ai_client::{AiError, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+/// Clients built from the current network settings, keyed by (connect, read) timeout
+///
+/// reqwest applies timeouts per client, so providers with custom timeouts get their
+/// own client; all of them share the proxy and CA settings. The cache is dropped
+/// whenever the network settings change.
+#[derive(Default)]
+struct ClientCache {
+    network: NetworkConfig,
+    clients: HashMap<(Duration, Duration), Arc<reqwest::Client>>,
+}
+
+impl ClientCache {
+    fn get(&mut self, network: &NetworkConfig, connect_timeout: Duration, read_timeout: Duration) -> Result<Arc<reqwest::Client>> {
+        if self.network != *network {
+            self.network = network.clone();
+            self.clients.clear();
+        }
+        if let Some(client) = self.clients.get(&(connect_timeout, read_timeout)) {
+            return Ok(client.clone());
+        }
+
+        let client = Arc::new(build_client(network, connect_timeout, read_timeout)?);
+        self.clients.insert((connect_timeout, read_timeout), client.clone());
+        Ok(client)
+    }
+}
+
+static CLIENTS: LazyLock<Mutex<ClientCache>> = LazyLock::new(Default::default);
+
+/// Get the shared client for the given network settings and timeouts
+///
+/// `reqwest::Client` is a handle to a connection pool, so clones share connections.
+pub fn client(network: &NetworkConfig, connect_timeout: Duration, read_timeout: Duration) -> Result<reqwest::Client> {
+    let client = CLIENTS.lock().unwrap().get(network, connect_timeout, read_timeout)?;
+    Ok(reqwest::Client::clone(&client))
+}
+
+/// Build a client that is not cached (for long-lived connections that keep their own)
+pub fn build_client(network: &NetworkConfig, connect_timeout: Duration, read_timeout: Duration) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .read_timeout(read_timeout);
+
+    if let Some(url) = network.proxy_url.as_deref().filter(|u| !u.trim().is_empty()) {
+        let proxy = reqwest::Proxy::all(url.trim())
+            .map_err(|e| AiError::Http(format!("Invalid proxy URL {}: {}", url, e)))?
+            .no_proxy(reqwest::NoProxy::from_string(&network.no_proxy.join(",")));
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = network.ca_bundle_path.as_deref().filter(|p| !p.trim().is_empty()) {
+        let pem = std::fs::read(path.trim())
+            .map_err(|e| AiError::Http(format!("Failed to read CA bundle {}: {}", path, e)))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| AiError::Http(format!("Invalid CA bundle {}: {}", path, e)))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder.build().map_err(|e: reqwest::Error| AiError::Http(e.to_string()))
+}
+
+/// Add a provider's extra headers to a request
+pub fn with_extra_headers(request: reqwest::RequestBuilder, headers: &HashMap<String, String>) -> reqwest::RequestBuilder {
+    headers
+        .iter()
+        .fold(request, |request, (name, value)| request.header(name.as_str(), value.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_cache_rebuilds_on_change() {
+        let mut cache = ClientCache::default();
+        let timeouts = (Duration::from_secs(5), Duration::from_secs(30));
+        let network = NetworkConfig::default();
+        let first = cache.get(&network, timeouts.0, timeouts.1).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get(&network, timeouts.0, timeouts.1).unwrap()));
+        let slow = cache.get(&network, timeouts.0, Duration::from_secs(300)).unwrap();
+        assert!(!Arc::ptr_eq(&first, &slow));
+
+        let proxied = NetworkConfig {
+            proxy_url: Some("http://proxy.local:8080".to_string()),
+            no_proxy: vec!["localhost".to_string()],
+            ..Default::default()
+        };
+        let rebuilt = cache.get(&proxied, timeouts.0, timeouts.1).unwrap();
+        assert!(!Arc::ptr_eq(&first, &rebuilt));
+        assert!(Arc::ptr_eq(&rebuilt, &cache.get(&proxied, timeouts.0, timeouts.1).unwrap()));
+
+        let bad = NetworkConfig {
+            ca_bundle_path: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(cache.get(&bad, timeouts.0, timeouts.1).is_err());
+    }
+}
@@ -1,11 +1,16 @@
 //! MCP Agent Service
 //! MCP 代理服务，负责工具调用与 AI 交互循环
 
-use crate::config::AppConfig;
-use crate::services::ai_client::{AiClient, ChatMessage};
-use crate::services::mcp_client::{McpClient, McpTool};
+use crate::config::{AppConfig, GenerationParams};
+use crate::services::ai_client::{
+    assistant_message, system_message, user_message, AiClient, AiError, AiResponse, ChatMessage, ContentBlock,
+    StreamEvent, ToolDefinition, Usage,
+};
+use crate::services::mcp_client::McpTool;
+use crate::services::mcp_manager::{self, McpConnection};
 use serde_json::Value;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 /// Agent step for progressive rendering
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -18,8 +23,24 @@ pub enum AgentStep {
     ToolCall { name: String, args: serde_json::Value },
     /// Tool execution result
     ToolResult { name: String, result: String },
+    /// Incremental text of the answer (streaming)
+    Delta(String),
+    /// Incremental extended thinking text (streaming); shown as a `Thinking` step
+    ThinkingDelta(String),
+    /// A transient AI error; the request is retried after `delay_secs`
+    Retrying { attempt: u32, max_retries: u32, delay_secs: u64, reason: String },
+    /// Provider failed, switching to the next one in the fallback chain
+    Fallback { from: String, to: String, reason: String },
+    /// Provider answering the current AI request
+    Provider { id: String, name: String },
+    /// The answer hit the `max_tokens` limit and was cut off
+    Truncated,
+    /// Token usage of one AI request (one per agent iteration)
+    Usage { usage: Usage, cost: Option<f64> },
     /// Final answer
     Final(String),
+    /// The run failed (not sent when cancelled); `category` classifies the error
+    Error { category: String, message: String },
 }
 
 /// MCP agent error type
@@ -28,15 +49,44 @@ pub enum AgentError {
     #[error("MCP client error: {0}")]
     McpClient(String),
     #[error("AI error: {0}")]
-    Ai(String),
+    Ai(AiError),
+    #[error("Maximum iterations reached")]
+    MaxIterations,
     #[error("No tools available")]
     NoToolsAvailable,
     #[error("Tool parse error: {0}")]
     ToolParse(String),
+    #[error("Cancelled")]
+    Cancelled,
 }
 
 pub type Result<T> = std::result::Result<T, AgentError>;
 
+impl From<AiError> for AgentError {
+    fn from(e: AiError) -> Self {
+        match e {
+            AiError::Cancelled => AgentError::Cancelled,
+            e => AgentError::Ai(e),
+        }
+    }
+}
+
+impl AgentError {
+    /// Short category name shown with the error
+    pub fn category(&self) -> &'static str {
+        match self {
+            AgentError::McpClient(_) | AgentError::NoToolsAvailable => "MCP",
+            AgentError::Ai(e) => e.category(),
+            AgentError::MaxIterations => "Iteration limit",
+            AgentError::ToolParse(_) => "Tool call",
+            AgentError::Cancelled => "Cancelled",
+        }
+    }
+}
+
+/// Maximum number of AI round-trips per agent run
+const MAX_ITERATIONS: usize = 10;
+
 /// Tool call request from AI
 #[derive(Debug, Clone, serde::Deserialize)]
 struct ToolCall {
@@ -45,99 +95,176 @@ struct ToolCall {
 }
 
 /// Process chat with MCP tool support
-/// Sends AgentStep updates through the channel for progressive rendering
+/// Sends AgentStep updates through the channel for progressive rendering.
+/// Cancelling `cancel` aborts the AI request or pending tool call and returns [`AgentError::Cancelled`].
+/// `generation` overrides the provider's default generation parameters for every request of the run.
+/// A failure other than cancellation is also sent as [`AgentStep::Error`].
 pub async fn chat_with_tools(
     messages: Vec<ChatMessage>,
+    generation: GenerationParams,
     tx: mpsc::UnboundedSender<AgentStep>,
+    cancel: CancellationToken,
+) -> Result<String> {
+    let result = run_agent(messages, &generation, &tx, &cancel).await;
+    if let Err(e) = &result {
+        if !matches!(e, AgentError::Cancelled) {
+            let _ = tx.send(AgentStep::Error { category: e.category().to_string(), message: e.to_string() });
+        }
+    }
+    result
+}
+
+async fn run_agent(
+    messages: Vec<ChatMessage>,
+    generation: &GenerationParams,
+    tx: &mpsc::UnboundedSender<AgentStep>,
+    cancel: &CancellationToken,
 ) -> Result<String> {
-    // Load enabled MCP servers
+    // Start or stop pooled MCP servers to match the config
     let config = AppConfig::load().map_err(|e| AgentError::McpClient(e.to_string()))?;
+    mcp_manager::sync(&config.mcp, &config.network);
     let enabled_servers = config.get_enabled_mcps();
 
     if enabled_servers.is_empty() {
         // No MCP servers, just do normal chat
-        let response = AiClient::chat_completion(messages)
-            .await
-            .map_err(|e| AgentError::Ai(e.to_string()))?;
-        let _ = tx.send(AgentStep::Final(response.clone()));
-        return Ok(response);
+        return plain_chat(messages, generation, tx, cancel).await;
     }
 
-    // Connect to all MCP servers and collect tools
     let _ = tx.send(AgentStep::Connecting(format!("连接到 {} 个MCP服务器...", enabled_servers.len())));
 
-    let (sync_tx, sync_rx) = std::sync::mpsc::channel();
-    let server_configs: Vec<_> = enabled_servers.iter().map(|s| {
-        (s.name.clone(), s.command.clone(), s.args.clone(), s.env.clone())
-    }).collect();
-
-    std::thread::spawn(move || {
-        let mut results: Vec<(String, McpClient, Vec<McpTool>)> = Vec::new();
-
-        for (name, command, args, env) in server_configs {
-            eprintln!("[MCP] Connecting to {}...", name);
-            match McpClient::connect(&command, &args, env.as_ref()) {
-                Ok(mut client) => {
-                    match client.list_tools() {
-                        Ok(tools) => {
-                            eprintln!("[MCP] {} loaded {} tools", name, tools.len());
-                            results.push((name, client, tools));
-                        }
-                        Err(e) => {
-                            eprintln!("[MCP] Failed to list tools for {}: {}", name, e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("[MCP] Failed to connect to {}: {}", name, e);
-                }
-            }
-        }
-
-        let _ = sync_tx.send(results);
-    });
-
-    // Wait with timeout (90 seconds for npx to download packages on first run)
-    let results = match sync_rx.recv_timeout(std::time::Duration::from_secs(90)) {
-        Ok(r) => r,
-        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-            let _ = tx.send(AgentStep::Connecting("连接超时，切换到普通对话".to_string()));
-            let response = AiClient::chat_completion(messages)
-                .await
-                .map_err(|e| AgentError::Ai(e.to_string()))?;
-            let _ = tx.send(AgentStep::Final(response.clone()));
-            return Ok(response);
-        }
-        Err(_) => {
-            let _ = tx.send(AgentStep::Connecting("连接失败，切换到普通对话".to_string()));
-            let response = AiClient::chat_completion(messages)
-                .await
-                .map_err(|e| AgentError::Ai(e.to_string()))?;
-            let _ = tx.send(AgentStep::Final(response.clone()));
-            return Ok(response);
-        }
+    // Servers still starting are awaited (each request has its own timeout)
+    let connections = tokio::select! {
+        _ = cancel.cancelled() => return Err(AgentError::Cancelled),
+        connections = mcp_manager::connections() => connections,
     };
 
-    let mut all_tools: Vec<McpTool> = Vec::new();
-    let mut clients: Vec<McpClient> = Vec::new();
+    if connections.is_empty() {
+        let _ = tx.send(AgentStep::Connecting("连接失败，切换到普通对话".to_string()));
+        return plain_chat(messages, generation, tx, cancel).await;
+    }
 
-    for (name, client, tools) in results {
-        let _ = tx.send(AgentStep::Connecting(format!("{}: 加载了 {} 个工具", name, tools.len())));
-        all_tools.extend(tools);
-        clients.push(client);
+    let mut all_tools: Vec<McpTool> = Vec::new();
+    for connection in &connections {
+        let _ = tx.send(AgentStep::Connecting(format!("{}: 加载了 {} 个工具", connection.server, connection.tools.len())));
+        all_tools.extend(connection.tools.iter().cloned());
     }
 
     if all_tools.is_empty() {
         let _ = tx.send(AgentStep::Connecting("没有加载到工具，切换到普通对话".to_string()));
-        let response = AiClient::chat_completion(messages)
-            .await
-            .map_err(|e| AgentError::Ai(e.to_string()))?;
-        let _ = tx.send(AgentStep::Final(response.clone()));
-        return Ok(response);
+        return plain_chat(messages, generation, tx, cancel).await;
+    }
+
+    if chain_supports_tools(&config) {
+        run_native_tool_loop(messages, &all_tools, &connections, generation, tx, cancel).await
+    } else {
+        run_prompt_tool_loop(messages, &all_tools, &connections, generation, tx, cancel).await
+    }
+}
+
+/// Whether to use native tool_use rather than prompt-parsed tool calls
+///
+/// A request may fall back to any provider of the chain, so native tools are only
+/// used when the active provider and all fallback providers support them.
+fn chain_supports_tools(config: &AppConfig) -> bool {
+    config.get_usable_provider().into_iter().chain(config.get_fallback_providers()).all(|p| p.supports_tools())
+}
+
+/// Agent loop using native Messages API `tool_use` / `tool_result` content blocks
+async fn run_native_tool_loop(
+    messages: Vec<ChatMessage>,
+    tools: &[McpTool],
+    connections: &[McpConnection],
+    generation: &GenerationParams,
+    tx: &mpsc::UnboundedSender<AgentStep>,
+    cancel: &CancellationToken,
+) -> Result<String> {
+    let tool_definitions: Vec<ToolDefinition> = tools.iter().map(to_tool_definition).collect();
+    let mut current_messages = messages;
+
+    for iteration in 0..MAX_ITERATIONS {
+        let response = AiClient::stream_with_tools(current_messages.clone(), &tool_definitions, generation, cancel, |event| {
+            forward_stream_event(tx, event)
+        })
+        .await
+        .map_err(|e| {
+            eprintln!("[MCP] AI error: {}", e);
+            AgentError::from(e)
+        })?;
+
+        let tool_uses = response.tool_uses();
+        if tool_uses.is_empty() {
+            // No tool call, return final response
+            let text = response.text();
+            report_truncation(&response, tx);
+            let _ = tx.send(AgentStep::Final(text.clone()));
+            return Ok(text);
+        }
+
+        eprintln!("[MCP] [ITERATION {}] {} tool call(s) requested", iteration + 1, tool_uses.len());
+
+        // Text streamed before the tool calls becomes the thinking content
+        let preamble = response.text();
+        let _ = tx.send(AgentStep::Thinking {
+            short: format!("思考中 (第{}轮)...", iteration + 1),
+            content: if preamble.trim().is_empty() { None } else { Some(preamble) },
+        });
+
+        let mut results = Vec::new();
+        for (id, name, input) in tool_uses {
+            let _ = tx.send(AgentStep::ToolCall {
+                name: name.clone(),
+                args: input.clone(),
+            });
+
+            let tool_call = ToolCall { name: name.clone(), arguments: input };
+            let (content, is_error) = match run_tool_call(tool_call, connections, cancel).await {
+                Ok(result) => (
+                    tool_result_text(&result),
+                    result["isError"].as_bool().unwrap_or(false),
+                ),
+                Err(AgentError::Cancelled) => return Err(AgentError::Cancelled),
+                Err(e) => (e.to_string(), true),
+            };
+
+            let _ = tx.send(AgentStep::ToolResult {
+                name,
+                result: content.clone(),
+            });
+            results.push(ContentBlock::ToolResult {
+                tool_use_id: id,
+                content,
+                is_error,
+            });
+        }
+
+        // Echo the assistant turn (with its tool_use blocks), then answer with matching tool_result blocks
+        current_messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: response.content.into(),
+        });
+        current_messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: results.into(),
+        });
     }
 
+    // Max iterations reached
+    eprintln!("[MCP] Maximum iterations reached");
+    let _ = tx.send(AgentStep::Final("达到最大迭代次数".to_string()));
+    Err(AgentError::MaxIterations)
+}
+
+/// Fallback agent loop: tool definitions in the system prompt, tool calls parsed from text
+async fn run_prompt_tool_loop(
+    messages: Vec<ChatMessage>,
+    tools: &[McpTool],
+    connections: &[McpConnection],
+    generation: &GenerationParams,
+    tx: &mpsc::UnboundedSender<AgentStep>,
+    cancel: &CancellationToken,
+) -> Result<String> {
     // Build system prompt with tool definitions
-    let tools_prompt = build_tools_prompt(&all_tools);
+    let tools_prompt = build_tools_prompt(tools);
 
     // Build system instructions - strict format enforcement
     let system_instructions = format!(
@@ -156,24 +283,21 @@ pub async fn chat_with_tools(
     );
 
     // Add tools context to messages (system message for Anthropic API)
-    let mut enhanced_messages: Vec<ChatMessage> = messages.clone();
-    enhanced_messages.insert(0, ChatMessage {
-        role: "system".to_string(),
-        content: system_instructions,
-    });
+    let mut enhanced_messages: Vec<ChatMessage> = messages;
+    enhanced_messages.insert(0, system_message(system_instructions));
 
     // Agent loop
-    let max_iterations = 10;
     let mut current_messages = enhanced_messages;
 
-    for iteration in 0..max_iterations {
+    for iteration in 0..MAX_ITERATIONS {
         // Get AI response
-        let response = AiClient::chat_completion(current_messages.clone())
+        let ai_response = AiClient::chat_completion(current_messages.clone(), generation, cancel, |event| forward_stream_event(tx, event))
             .await
             .map_err(|e| {
                 eprintln!("[MCP] AI error: {}", e);
-                AgentError::Ai(e.to_string())
+                AgentError::from(e)
             })?;
+        let response = ai_response.text();
 
         let response_preview = if response.len() > 100 {
             format!("{}...", &response[..100])
@@ -199,8 +323,12 @@ pub async fn chat_with_tools(
                     args: tool_call.arguments.clone(),
                 });
 
-                // Execute tool call
-                let tool_result = execute_tool_call(&tool_call, &mut clients)?;
+                // Execute tool call; a failure is fed back to the model like in the native loop
+                let (label, tool_result) = match run_tool_call(tool_call.clone(), connections, cancel).await {
+                    Ok(result) => ("Tool result", serde_json::to_string(&result).unwrap_or_default()),
+                    Err(AgentError::Cancelled) => return Err(AgentError::Cancelled),
+                    Err(e) => ("Tool error", e.to_string()),
+                };
 
                 // Send tool result step
                 let _ = tx.send(AgentStep::ToolResult {
@@ -209,22 +337,17 @@ pub async fn chat_with_tools(
                 });
 
                 // Add assistant message with tool call
-                current_messages.push(ChatMessage {
-                    role: "assistant".to_string(),
-                    content: response,
-                });
+                current_messages.push(assistant_message(response));
 
                 // Add tool result as user message
-                current_messages.push(ChatMessage {
-                    role: "user".to_string(),
-                    content: format!("Tool result: {}", tool_result),
-                });
+                current_messages.push(user_message(format!("{}: {}", label, tool_result)));
 
                 // Continue loop
             }
             Err(e) => {
                 eprintln!("[MCP] [ITERATION {}] No tool call: {}", iteration + 1, e);
                 // No tool call, return final response
+                report_truncation(&ai_response, tx);
                 let _ = tx.send(AgentStep::Final(response.clone()));
                 return Ok(response);
             }
@@ -234,7 +357,66 @@ pub async fn chat_with_tools(
     // Max iterations reached
     eprintln!("[MCP] Maximum iterations reached");
     let _ = tx.send(AgentStep::Final("达到最大迭代次数".to_string()));
-    Err(AgentError::Ai("Maximum iterations reached".to_string()))
+    Err(AgentError::MaxIterations)
+}
+
+/// Convert an MCP tool into a Messages API tool definition
+fn to_tool_definition(tool: &McpTool) -> ToolDefinition {
+    // The API requires an object schema; some servers omit inputSchema entirely
+    let input_schema = if tool.input_schema.is_object() {
+        tool.input_schema.clone()
+    } else {
+        serde_json::json!({ "type": "object", "properties": {} })
+    };
+
+    ToolDefinition {
+        name: tool.name.clone(),
+        description: tool.description.clone(),
+        input_schema,
+    }
+}
+
+/// Forward an AI client event to the UI as an agent step
+fn forward_stream_event(tx: &mpsc::UnboundedSender<AgentStep>, event: StreamEvent) {
+    let step = match event {
+        StreamEvent::TextDelta(text) => AgentStep::Delta(text),
+        StreamEvent::ThinkingDelta(text) => AgentStep::ThinkingDelta(text),
+        StreamEvent::Retrying { attempt, max_retries, delay, reason } => AgentStep::Retrying {
+            attempt,
+            max_retries,
+            delay_secs: delay.as_secs_f64().ceil() as u64,
+            reason,
+        },
+        StreamEvent::Fallback { from, to, reason } => AgentStep::Fallback { from, to, reason },
+        StreamEvent::Provider { id, name } => AgentStep::Provider { id, name },
+        StreamEvent::Usage { usage, cost } => AgentStep::Usage { usage, cost },
+    };
+    let _ = tx.send(step);
+}
+
+/// Send [`AgentStep::Truncated`] if the answer stopped at the `max_tokens` limit
+fn report_truncation(response: &AiResponse, tx: &mpsc::UnboundedSender<AgentStep>) {
+    if response.is_truncated() {
+        eprintln!("[MCP] Answer truncated at max_tokens");
+        let _ = tx.send(AgentStep::Truncated);
+    } else if let Some(sequence) = &response.stop_sequence {
+        eprintln!("[MCP] Answer ended at stop sequence {:?}", sequence);
+    }
+}
+
+/// Plain streaming chat without tools
+/// Text deltas are forwarded as `AgentStep::Delta`, followed by the final answer
+async fn plain_chat(
+    messages: Vec<ChatMessage>,
+    generation: &GenerationParams,
+    tx: &mpsc::UnboundedSender<AgentStep>,
+    cancel: &CancellationToken,
+) -> Result<String> {
+    let response = AiClient::stream_with_tools(messages, &[], generation, cancel, |event| forward_stream_event(tx, event)).await?;
+    report_truncation(&response, tx);
+    let text = response.text();
+    let _ = tx.send(AgentStep::Final(text.clone()));
+    Ok(text)
 }
 
 /// Build tools prompt for AI (generic MCP tool schema handling)
@@ -405,20 +587,177 @@ fn parse_tool_call(response: &str) -> Result<ToolCall> {
     Err(AgentError::ToolParse("No tool call found".to_string()))
 }
 
-/// Execute a tool call
-fn execute_tool_call(tool_call: &ToolCall, clients: &mut [McpClient]) -> Result<String> {
-    // Find client with the tool and execute
-    for client in clients.iter_mut() {
-        match client.call_tool(&tool_call.name, tool_call.arguments.clone()) {
-            Ok(result) => {
-                return Ok(serde_json::to_string(&result).unwrap_or_default());
-            }
-            Err(_) => continue,
+/// Execute a tool call, abandoning it if the run is cancelled
+async fn run_tool_call(tool_call: ToolCall, connections: &[McpConnection], cancel: &CancellationToken) -> Result<Value> {
+    tokio::select! {
+        result = execute_tool_call(&tool_call, connections) => result,
+        _ = cancel.cancelled() => Err(AgentError::Cancelled),
+    }
+}
+
+/// Execute a tool call on the server providing the tool, returning the raw MCP `tools/call` result
+async fn execute_tool_call(tool_call: &ToolCall, connections: &[McpConnection]) -> Result<Value> {
+    let connection = connections
+        .iter()
+        .find(|c| c.tools.iter().any(|t| t.name == tool_call.name))
+        .ok_or_else(|| AgentError::McpClient(format!("Tool not found: {}", tool_call.name)))?;
+
+    connection
+        .client
+        .call_tool(&tool_call.name, tool_call.arguments.clone())
+        .await
+        .map_err(AgentError::McpClient)
+}
+
+/// Extract the text content of an MCP tool result (falls back to the raw JSON)
+fn tool_result_text(result: &Value) -> String {
+    let texts: Vec<&str> = result["content"]
+        .as_array()
+        .map(|items| items.iter().filter_map(|item| item["text"].as_str()).collect())
+        .unwrap_or_default();
+
+    if texts.is_empty() {
+        serde_json::to_string(result).unwrap_or_default()
+    } else {
+        texts.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiFormat, ProviderConfig, ProviderType};
+    use crate::services::replay::{self, Exchange, Fixture};
+
+    /// Anthropic SSE response streaming the given events
+    fn sse(events: &[Value]) -> Exchange {
+        Exchange::stream(events.iter().map(|e| format!("data: {}\n\n", e)))
+    }
+
+    #[tokio::test]
+    async fn test_native_tool_loop() {
+        let tool_use = sse(&[
+            serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {}}}),
+            serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"city\":\"Oslo\"}"}}),
+            serde_json::json!({"type": "content_block_stop", "index": 0}),
+            serde_json::json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 10}}),
+            serde_json::json!({"type": "message_stop"}),
+        ]);
+        let answer = sse(&[
+            serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "No weather tool."}}),
+            serde_json::json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 4}}),
+            serde_json::json!({"type": "message_stop"}),
+        ]);
+        let _guard = replay::testing::use_mock_provider(
+            ApiFormat::Anthropic,
+            Fixture { models: vec![], exchanges: vec![tool_use, answer] },
+        ).await;
+
+        let tools = [McpTool {
+            name: "weather".to_string(),
+            description: "Current weather".to_string(),
+            input_schema: serde_json::json!({"type": "object"}),
+        }];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        // No MCP servers: the tool call fails and the error is fed back to the model
+        let text = run_native_tool_loop(
+            vec![user_message("Weather in Oslo?".to_string())],
+            &tools,
+            &[],
+            &GenerationParams::default(),
+            &tx,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(text, "No weather tool.");
+
+        drop(tx);
+        let mut steps = Vec::new();
+        while let Some(step) = rx.recv().await {
+            steps.push(step);
+        }
+        assert!(steps.iter().any(|s| matches!(s, AgentStep::ToolCall { name, args } if name == "weather" && args["city"] == "Oslo")));
+        assert!(steps.iter().any(|s| matches!(s, AgentStep::ToolResult { result, .. } if result.contains("Tool not found"))));
+        assert_eq!(steps.iter().filter(|s| matches!(s, AgentStep::Usage { .. })).count(), 2);
+        assert!(matches!(steps.last(), Some(AgentStep::Final(text)) if text == "No weather tool."));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_tool_loop_reports_tool_errors() {
+        let text = |text: &str| Exchange::json(serde_json::json!({
+            "content": [{"type": "text", "text": text}],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }));
+        let exchanges = vec![
+            text(r#"{"tool_call": {"name": "weather", "arguments": {"city": "Oslo"}}}"#),
+            text("No weather tool."),
+        ];
+        let _guard = replay::testing::use_mock_provider(ApiFormat::Anthropic, Fixture { models: vec![], exchanges }).await;
+
+        let tools = [McpTool {
+            name: "weather".to_string(),
+            description: "Current weather".to_string(),
+            input_schema: serde_json::json!({"type": "object"}),
+        }];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        // No MCP servers: the tool call fails and the error is fed back to the model
+        let text = run_prompt_tool_loop(
+            vec![user_message("Weather in Oslo?".to_string())],
+            &tools,
+            &[],
+            &GenerationParams::default(),
+            &tx,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(text, "No weather tool.");
+
+        drop(tx);
+        let mut steps = Vec::new();
+        while let Some(step) = rx.recv().await {
+            steps.push(step);
         }
+        assert!(steps.iter().any(|s| matches!(s, AgentStep::ToolResult { result, .. } if result.contains("Tool not found"))));
+    }
+
+    #[test]
+    fn test_tool_mode_follows_fallback_chain() {
+        let mut config = AppConfig::default();
+        let mut provider = config.ai.providers[0].clone();
+        provider.provider_type = ProviderType::Ollama;
+        config.ai.providers = ["local", "backup"]
+            .map(|id| ProviderConfig { id: id.to_string(), ..provider.clone() })
+            .to_vec();
+        config.ai.active_provider = Some("local".to_string());
+        config.ai.fallback_providers = vec!["backup".to_string()];
+        assert!(chain_supports_tools(&config));
+
+        config.ai.providers[1].supports_tools = Some(false);
+        assert!(!chain_supports_tools(&config));
     }
 
-    Err(AgentError::McpClient(format!(
-        "Tool not found: {}",
-        tool_call.name
-    )))
+    #[tokio::test]
+    async fn test_plain_chat_without_mcp_servers() {
+        let exchanges = vec![Exchange::error(
+            400,
+            serde_json::json!({"type": "error", "error": {"type": "invalid_request_error", "message": "max_tokens: must be positive"}}),
+        )];
+        let _guard = replay::testing::use_mock_provider(ApiFormat::Anthropic, Fixture { models: vec![], exchanges }).await;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let error = chat_with_tools(vec![user_message("Hi".to_string())], GenerationParams::default(), tx, CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(&error, AgentError::Ai(AiError::InvalidRequest(message)) if message.contains("max_tokens")));
+
+        let mut last = None;
+        while let Some(step) = rx.recv().await {
+            last = Some(step);
+        }
+        assert!(matches!(last, Some(AgentStep::Error { category, message }) if category == "Invalid request" && message == error.to_string()));
+    }
 }
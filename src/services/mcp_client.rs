@@ -1,18 +1,41 @@
 //! MCP (Model Context Protocol) client
-//! 简洁的 MCP stdio 客户端实现
+//! 简洁的 MCP 客户端实现 - stdio、Streamable HTTP 与 HTTP+SSE 传输
 
+use crate::config::{KeySource, McpServerConfig, McpTransport, NetworkConfig};
+use crate::services::http;
+use crate::services::sse::{SseDecoder, SseEvent};
+use futures_util::{Stream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::io::{BufRead, BufReader, Write};
-use std::process::{Child, ChildStdin, ChildStdout, ChildStderr, Command, Stdio};
-use std::thread;
-
-#[cfg(target_os = "windows")]
-use std::os::windows::process::CommandExt;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 #[cfg(target_os = "windows")]
 const CREATE_NO_WINDOW: u32 = 0x08000000;
 
+/// Timeout of the `initialize` request (npx may download the server package on first run)
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Timeout of ordinary requests such as `tools/list`
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Timeout of `tools/call` (tools may do real work)
+const TOOL_CALL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Session header of the Streamable HTTP transport
+const SESSION_HEADER: &str = "mcp-session-id";
+/// Delay before a Streamable HTTP server's event stream is reopened
+const STREAM_REOPEN_DELAY: Duration = Duration::from_secs(1);
+
 /// MCP tool definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpTool {
@@ -21,19 +44,189 @@ pub struct McpTool {
     pub input_schema: Value,
 }
 
-/// MCP client for stdio transport
+/// Resource offered by a server
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Parameterized resource (RFC 6570 URI template, e.g. `file:///{path}`)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResourceTemplate {
+    pub uri_template: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Contents of a read resource: `text`, or base64 `blob` for binary data
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResourceContents {
+    pub uri: String,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub blob: Option<String>,
+}
+
+/// Prompt template offered by a server
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Message of a prompt returned by `prompts/get`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct McpPromptMessage {
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: McpPromptContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum McpPromptContent {
+    Text {
+        text: String,
+    },
+    Image {
+        /// Base64 image data
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// Embedded resource
+    Resource {
+        resource: McpResourceContents,
+    },
+    /// Content this client doesn't handle (e.g. audio)
+    #[serde(other)]
+    Unsupported,
+}
+
+/// Notification sent by a server (e.g. `notifications/resources/updated`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpNotification {
+    pub method: String,
+    pub params: Value,
+}
+
+/// How messages reach the server
+enum Transport {
+    Stdio {
+        _child: Child,
+        stdin: tokio::sync::Mutex<ChildStdin>,
+    },
+    /// Every message is POSTed; the HTTP response carries the server's answer.
+    /// Other server messages arrive on an optional GET event stream
+    StreamableHttp {
+        http: reqwest::Client,
+        url: String,
+        headers: HeaderMap,
+        session_id: Mutex<Option<String>>,
+    },
+    /// Messages are POSTed to `endpoint`; answers arrive on the event stream
+    Sse {
+        http: reqwest::Client,
+        endpoint: String,
+        headers: HeaderMap,
+    },
+}
+
+impl Transport {
+    /// Protocol version requested at initialize: Streamable HTTP was introduced in 2025-03-26
+    fn protocol_version(&self) -> &'static str {
+        match self {
+            Transport::StreamableHttp { .. } => "2025-03-26",
+            Transport::Stdio { .. } | Transport::Sse { .. } => "2024-11-05",
+        }
+    }
+}
+
+/// Transport and request state, shared with the task reading the server's messages
+struct Connection {
+    transport: Transport,
+    /// Requests waiting for their response, by JSON-RPC id
+    pending: Mutex<HashMap<i64, oneshot::Sender<Result<Value, String>>>>,
+    notifications: broadcast::Sender<McpNotification>,
+    closed: CancellationToken,
+}
+
+/// MCP client, independent of the transport
+///
+/// Responses are routed to the waiting request by `id`, notifications are
+/// broadcast and server requests are answered, so requests can be issued
+/// concurrently through `&self`.
 pub struct McpClient {
-    _child: Option<Child>,
-    _stdin: Option<ChildStdin>,
-    _stdout: Option<ChildStdout>,
-    _stderr: Option<ChildStderr>,
-    request_id: i64,
-    initialized: bool,
+    connection: Arc<Connection>,
+    next_id: AtomicI64,
+    /// Capabilities the server announced in its `initialize` result
+    capabilities: Value,
+    /// Task reading the server's stdout or event stream
+    reader: Option<JoinHandle<()>>,
+    /// Messages the server sends on its own (notifications) reach the client;
+    /// not the case for a Streamable HTTP server without a GET event stream
+    receives_notifications: bool,
 }
 
 impl McpClient {
-    /// Create new MCP client by spawning server process
-    pub fn connect(command: &str, args: &[String], env: Option<&std::collections::HashMap<String, String>>) -> Result<Self, String> {
+    /// Connect to the server over its configured transport and initialize the MCP session
+    pub async fn connect(server: &McpServerConfig, network: &NetworkConfig) -> Result<Self, String> {
+        let mut client = match &server.transport {
+            McpTransport::Stdio => Self::spawn(&server.command, &server.args, server.env.as_ref())?,
+            McpTransport::StreamableHttp { url, headers } => Self::new(Transport::StreamableHttp {
+                http: http_client(network)?,
+                url: url.trim().to_string(),
+                headers: header_map(headers)?,
+                session_id: Mutex::new(None),
+            }),
+            McpTransport::Sse { url, headers } => Self::open_sse(url.trim(), header_map(headers)?, http_client(network)?).await?,
+        };
+        client.initialize().await?;
+        if matches!(server.transport, McpTransport::StreamableHttp { .. }) {
+            client.open_server_stream().await;
+        }
+        Ok(client)
+    }
+
+    fn new(transport: Transport) -> Self {
+        Self {
+            connection: Arc::new(Connection {
+                transport,
+                pending: Mutex::new(HashMap::new()),
+                notifications: broadcast::channel(64).0,
+                closed: CancellationToken::new(),
+            }),
+            next_id: AtomicI64::new(0),
+            capabilities: Value::Null,
+            reader: None,
+            receives_notifications: true,
+        }
+    }
+
+    /// Spawn a stdio server process
+    fn spawn(command: &str, args: &[String], env: Option<&HashMap<String, String>>) -> Result<Self, String> {
         // On Windows, npx is npx.cmd (Command::new only searches for .exe)
         let actual_command = if cfg!(target_os = "windows") && command == "npx" {
             "npx.cmd"
@@ -59,6 +252,7 @@ impl McpClient {
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
             .map_err(|e| format!("Failed to spawn MCP server ({}): {}", actual_command, e))?;
 
@@ -66,130 +260,140 @@ impl McpClient {
         let stdout = child.stdout.take().ok_or("Failed to get stdout".to_string())?;
         let stderr = child.stderr.take().ok_or("Failed to get stderr".to_string())?;
 
-        // Spawn thread to log stderr output
-        thread::spawn(move || {
-            let reader = BufReader::new(stderr);
-            for line in reader.lines() {
-                if let Ok(msg) = line {
-                    eprintln!("[MCP STDERR] {}", msg);
-                }
+        // Log stderr output
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(msg)) = lines.next_line().await {
+                eprintln!("[MCP STDERR] {}", msg);
             }
         });
 
-        Ok(Self {
-            _child: Some(child),
-            _stdin: Some(stdin),
-            _stdout: Some(stdout),
-            _stderr: None, // Ownership transferred to thread
-            request_id: 0,
-            initialized: false,
-        })
+        let mut client = Self::new(Transport::Stdio { _child: child, stdin: tokio::sync::Mutex::new(stdin) });
+        client.reader = Some(tokio::spawn(read_lines(stdout, client.connection.clone())));
+        Ok(client)
     }
 
-    /// Write a message to stdin (line-delimited JSON format)
-    fn write_message(&mut self, message: &str) -> Result<(), String> {
-        if let Some(stdin) = &mut self._stdin {
-            // Simple line-delimited JSON format
-            let content = format!("{}\n", message);
-            stdin.write_all(content.as_bytes())
-                .map_err(|e| format!("Failed to write: {}", e))?;
-            stdin.flush()
-                .map_err(|e| format!("Failed to flush: {}", e))?;
-            Ok(())
-        } else {
-            Err("stdin not available".to_string())
+    /// Open the event stream of a legacy HTTP+SSE server and wait for its `endpoint` event
+    async fn open_sse(url: &str, headers: HeaderMap, http: reqwest::Client) -> Result<Self, String> {
+        let response = http
+            .get(url)
+            .headers(headers.clone())
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .map_err(|e| format!("Failed to connect to MCP server {}: {}", url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("MCP server {} returned HTTP {}", url, response.status()));
         }
-    }
 
-    /// Read a message from stdout (line-delimited JSON format)
-    fn read_message(&mut self) -> Result<String, String> {
-        if let Some(stdout) = &mut self._stdout {
-            let mut reader = BufReader::new(stdout);
-            let mut line = String::new();
+        let mut body = Box::pin(response.bytes_stream());
+        let mut decoder = SseDecoder::new();
+        let mut early = Vec::new();
+        let wait_endpoint = async {
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk.map_err(|e| format!("Failed to read: {}", e))?;
+                let mut events = decoder.push(&chunk).into_iter();
+                while let Some(event) = events.next() {
+                    if event.event.as_deref() == Some("endpoint") {
+                        early.extend(events);
+                        return Ok(event.data);
+                    }
+                    early.push(event);
+                }
+            }
+            Err("MCP server closed the event stream before sending its endpoint".to_string())
+        };
+        let endpoint = tokio::time::timeout(INITIALIZE_TIMEOUT, wait_endpoint)
+            .await
+            .map_err(|_| format!("MCP server {} sent no endpoint", url))??;
+        // The endpoint is usually relative to the stream URL
+        let endpoint = reqwest::Url::parse(url)
+            .and_then(|base| base.join(endpoint.trim()))
+            .map_err(|e| format!("Invalid MCP endpoint {}: {}", endpoint, e))?;
 
-            reader.read_line(&mut line)
-                .map_err(|e| format!("Failed to read: {}", e))?;
+        let mut client = Self::new(Transport::Sse { http, endpoint: endpoint.to_string(), headers });
+        client.reader = Some(tokio::spawn(read_events(body, decoder, early, client.connection.clone())));
+        Ok(client)
+    }
 
-            Ok(line.trim().to_string())
-        } else {
-            Err("stdout not available".to_string())
+    /// Listen on the GET event stream of a Streamable HTTP server, if it offers one
+    async fn open_server_stream(&mut self) {
+        match self.connection.open_server_stream().await {
+            Ok(Some(response)) => {
+                self.reader = Some(tokio::spawn(read_server_stream(response, self.connection.clone())));
+            }
+            Ok(None) => self.receives_notifications = false,
+            Err(e) => {
+                eprintln!("[MCP] {}", e);
+                self.receives_notifications = false;
+            }
         }
     }
 
-    /// Send JSON-RPC request and get response
-    fn send_request(&mut self, method: &str, params: Option<Value>) -> Result<Value, String> {
-        let id = self.request_id;
-        self.request_id += 1;
+    /// Send a JSON-RPC request and wait up to `timeout` for its response
+    async fn request(&self, method: &str, params: Option<Value>, timeout: Duration) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.connection.pending.lock().unwrap().insert(id, tx);
+        // Forget the request if this future is dropped (e.g. the run was cancelled)
+        let _pending = PendingGuard { connection: &self.connection, id };
+        if self.connection.closed.is_cancelled() {
+            return Err("MCP server closed the connection".to_string());
+        }
 
         // Convert None to empty object {} for MCP compatibility
-        let params_value = params.unwrap_or_else(|| json!({}));
-
         let request = json!({
             "jsonrpc": "2.0",
             "id": id,
             "method": method,
-            "params": params_value
+            "params": params.unwrap_or_else(|| json!({}))
         });
+        let exchange = async {
+            self.connection.deliver(request).await?;
+            rx.await.unwrap_or_else(|_| Err("MCP server closed the connection".to_string()))
+        };
 
-        eprintln!("[MCP] Sending: {}", request);
-
-        self.write_message(&request.to_string())?;
-
-        let response_str = self.read_message()?;
-        eprintln!("[MCP] Received: {}", response_str);
-
-        let response: Value = serde_json::from_str(&response_str)
-            .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-
-        // Check for error
-        if let Some(err) = response.get("error") {
-            return Err(format!("MCP error: {}", err));
+        match tokio::time::timeout(timeout, exchange).await {
+            Ok(result) => result,
+            Err(_) => {
+                let _ = self
+                    .send_notification("notifications/cancelled", Some(json!({"requestId": id, "reason": "timeout"})))
+                    .await;
+                Err(format!("MCP request {} timed out after {}s", method, timeout.as_secs()))
+            }
         }
-
-        Ok(response["result"].clone())
     }
 
     /// Send notification (no response expected)
-    fn send_notification(&mut self, method: &str, params: Option<Value>) -> Result<(), String> {
+    async fn send_notification(&self, method: &str, params: Option<Value>) -> Result<(), String> {
         // Convert None to empty object {} for MCP compatibility
-        let params_value = params.unwrap_or_else(|| json!({}));
-
         let notification = json!({
             "jsonrpc": "2.0",
             "method": method,
-            "params": params_value
+            "params": params.unwrap_or_else(|| json!({}))
         });
-
-        eprintln!("[MCP] Sending notification: {}", notification);
-        self.write_message(&notification.to_string())
+        self.connection.deliver(notification).await
     }
 
     /// Initialize MCP session (must be called first)
-    fn initialize(&mut self) -> Result<(), String> {
-        // Send initialize request
-        self.send_request("initialize", Some(json!({
-            "protocolVersion": "2024-11-05",
+    async fn initialize(&mut self) -> Result<(), String> {
+        let result = self.request("initialize", Some(json!({
+            "protocolVersion": self.connection.transport.protocol_version(),
             "capabilities": {},
             "clientInfo": {
                 "name": "veld",
                 "version": "0.1.0"
             }
-        })))?;
+        })), INITIALIZE_TIMEOUT).await?;
+        self.capabilities = result["capabilities"].clone();
 
         // Send initialized notification (REQUIRED by MCP spec)
-        self.send_notification("notifications/initialized", None)?;
-
-        self.initialized = true;
-        Ok(())
+        self.send_notification("notifications/initialized", None).await
     }
 
     /// List available tools
-    pub fn list_tools(&mut self) -> Result<Vec<McpTool>, String> {
-        if !self.initialized {
-            self.initialize()?;
-        }
-
-        let result = self.send_request("tools/list", None)?;
+    pub async fn list_tools(&self) -> Result<Vec<McpTool>, String> {
+        let result = self.request("tools/list", None, REQUEST_TIMEOUT).await?;
 
         let tools = result["tools"]
             .as_array()
@@ -206,27 +410,694 @@ impl McpClient {
     }
 
     /// Call a tool
-    pub fn call_tool(&mut self, name: &str, arguments: Value) -> Result<Value, String> {
-        if !self.initialized {
-            self.initialize()?;
-        }
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, String> {
+        self.request("tools/call", Some(json!({
+            "name": name,
+            "arguments": arguments
+        })), TOOL_CALL_TIMEOUT).await
+    }
+
+    /// Whether the server offers resources
+    pub fn supports_resources(&self) -> bool {
+        self.capabilities.get("resources").is_some()
+    }
+
+    /// Whether the server sends `notifications/resources/updated` for subscribed resources
+    /// (and they can reach the client)
+    pub fn supports_resource_subscriptions(&self) -> bool {
+        self.receives_notifications && self.capabilities["resources"]["subscribe"] == true
+    }
+
+    /// Whether the server offers prompts
+    pub fn supports_prompts(&self) -> bool {
+        self.capabilities.get("prompts").is_some()
+    }
+
+    /// List available prompts
+    pub async fn list_prompts(&self) -> Result<Vec<McpPrompt>, String> {
+        self.list_all("prompts/list", "prompts").await
+    }
 
-        let result = self.send_request("tools/call", Some(json!({
+    /// Get the messages of a prompt filled in with `arguments`
+    pub async fn get_prompt(&self, name: &str, arguments: &HashMap<String, String>) -> Result<Vec<McpPromptMessage>, String> {
+        let result = self.request("prompts/get", Some(json!({
             "name": name,
             "arguments": arguments
-        })))?;
+        })), REQUEST_TIMEOUT).await?;
+        serde_json::from_value(result["messages"].clone()).map_err(|e| format!("Invalid prompts/get response: {}", e))
+    }
+
+    /// List available resources
+    pub async fn list_resources(&self) -> Result<Vec<McpResource>, String> {
+        self.list_all("resources/list", "resources").await
+    }
+
+    /// List available resource templates
+    pub async fn list_resource_templates(&self) -> Result<Vec<McpResourceTemplate>, String> {
+        self.list_all("resources/templates/list", "resourceTemplates").await
+    }
+
+    /// Read a resource (a directory-like resource may return several contents)
+    pub async fn read_resource(&self, uri: &str) -> Result<Vec<McpResourceContents>, String> {
+        let result = self.request("resources/read", Some(json!({"uri": uri})), REQUEST_TIMEOUT).await?;
+        serde_json::from_value(result["contents"].clone()).map_err(|e| format!("Invalid resources/read response: {}", e))
+    }
 
-        Ok(result)
+    /// Ask for `notifications/resources/updated` when the resource changes
+    pub async fn subscribe_resource(&self, uri: &str) -> Result<(), String> {
+        self.request("resources/subscribe", Some(json!({"uri": uri})), REQUEST_TIMEOUT).await.map(|_| ())
     }
 
-    /// Close the client connection
-    pub fn close(&mut self) {
-        self._child.take();
+    pub async fn unsubscribe_resource(&self, uri: &str) -> Result<(), String> {
+        self.request("resources/unsubscribe", Some(json!({"uri": uri})), REQUEST_TIMEOUT).await.map(|_| ())
+    }
+
+    /// Collect the items under `key` of every page of a paginated list request
+    async fn list_all<T: DeserializeOwned>(&self, method: &str, key: &str) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.as_ref().map(|cursor| json!({"cursor": cursor}));
+            let mut result = self.request(method, params, REQUEST_TIMEOUT).await?;
+            let page: Vec<T> = serde_json::from_value(result[key].take())
+                .map_err(|e| format!("Invalid {} response: {}", method, e))?;
+            items.extend(page);
+            match result["nextCursor"].as_str() {
+                // A server repeating its cursor would otherwise be asked forever
+                Some(next) if cursor.as_deref() != Some(next) => cursor = Some(next.to_string()),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// Notifications received from now on
+    pub fn notifications(&self) -> broadcast::Receiver<McpNotification> {
+        self.connection.notifications.subscribe()
+    }
+
+    /// Wait until the connection is lost (process exited, stream ended or server unreachable)
+    pub async fn closed(&self) {
+        self.connection.closed.cancelled().await
     }
 }
 
 impl Drop for McpClient {
     fn drop(&mut self) {
+        // A stdio server process is killed once the connection is dropped (`kill_on_drop`)
+        if let Some(reader) = &self.reader {
+            reader.abort();
+        }
+    }
+}
+
+/// Removes a request from the pending map when it finishes or is abandoned
+struct PendingGuard<'a> {
+    connection: &'a Connection,
+    id: i64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.connection.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.id);
+    }
+}
+
+impl Connection {
+    /// Send a message, then answer the server requests that came back with it
+    async fn deliver(&self, message: Value) -> Result<(), String> {
+        let mut outgoing = vec![message];
+        while let Some(message) = outgoing.pop() {
+            eprintln!("[MCP] Sending: {}", message);
+            for incoming in self.send(&message).await? {
+                outgoing.extend(self.receive(incoming));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write one message; returns the messages found in the HTTP response (Streamable HTTP only)
+    async fn send(&self, message: &Value) -> Result<Vec<Value>, String> {
+        match &self.transport {
+            // Line-delimited JSON
+            Transport::Stdio { stdin, .. } => {
+                let content = format!("{}\n", message);
+                let mut stdin = stdin.lock().await;
+                stdin.write_all(content.as_bytes()).await.map_err(|e| format!("Failed to write: {}", e))?;
+                stdin.flush().await.map_err(|e| format!("Failed to flush: {}", e))?;
+                Ok(Vec::new())
+            }
+            Transport::StreamableHttp { http, url, headers, session_id } => {
+                let session = session_id.lock().unwrap().clone();
+                let mut request = http
+                    .post(url)
+                    .headers(headers.clone())
+                    .header(ACCEPT, "application/json, text/event-stream")
+                    .json(message);
+                if let Some(session) = &session {
+                    request = request.header(SESSION_HEADER, session);
+                }
+                let response = request.send().await.map_err(|e| self.fail(format!("Failed to reach MCP server: {}", e)))?;
+
+                if let Some(id) = response.headers().get(SESSION_HEADER).and_then(|v| v.to_str().ok()) {
+                    *session_id.lock().unwrap() = Some(id.to_string());
+                }
+                if response.status() == reqwest::StatusCode::NOT_FOUND && session.is_some() {
+                    return Err(self.fail("MCP session expired".to_string()));
+                }
+                read_http_messages(response).await
+            }
+            Transport::Sse { http, endpoint, headers } => {
+                let response = http
+                    .post(endpoint)
+                    .headers(headers.clone())
+                    .json(message)
+                    .send()
+                    .await
+                    .map_err(|e| self.fail(format!("Failed to reach MCP server: {}", e)))?;
+                if !response.status().is_success() {
+                    return Err(self.fail(format!("MCP server returned HTTP {}", response.status())));
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Handle a message from the server; returns the answer to a server request
+    fn receive(&self, message: Value) -> Option<Value> {
+        eprintln!("[MCP] Received: {}", message);
+        match (message.get("method").and_then(Value::as_str), message.get("id")) {
+            // Server-initiated request: answer ping, reject anything else
+            (Some(method), Some(id)) => Some(if method == "ping" {
+                json!({"jsonrpc": "2.0", "id": id, "result": {}})
+            } else {
+                json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": format!("Method not found: {}", method)}})
+            }),
+            (Some(method), None) => {
+                eprintln!("[MCP] Notification: {}", method);
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                // Nobody listening is fine
+                let _ = self.notifications.send(McpNotification { method: method.to_string(), params });
+                None
+            }
+            (None, Some(id)) => {
+                let waiting = id.as_i64().and_then(|id| self.pending.lock().unwrap().remove(&id));
+                match waiting {
+                    Some(waiting) => {
+                        let result = match message.get("error") {
+                            Some(err) => Err(format!("MCP error: {}", err)),
+                            None => Ok(message["result"].clone()),
+                        };
+                        let _ = waiting.send(result);
+                    }
+                    None => eprintln!("[MCP] Response to unknown request {}", id),
+                }
+                None
+            }
+            (None, None) => {
+                eprintln!("[MCP] Ignoring message without method or id");
+                None
+            }
+        }
+    }
+
+    /// Handle messages read by a reader task, answering server requests
+    async fn receive_all(&self, messages: Vec<Value>) {
+        for message in messages {
+            if let Some(answer) = self.receive(message) {
+                if let Err(e) = self.deliver(answer).await {
+                    eprintln!("[MCP] Failed to answer server request: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Open the GET event stream of a Streamable HTTP server
+    ///
+    /// None if the server doesn't offer one (HTTP 405) or for other transports.
+    async fn open_server_stream(&self) -> Result<Option<reqwest::Response>, String> {
+        let Transport::StreamableHttp { http, url, headers, session_id } = &self.transport else {
+            return Ok(None);
+        };
+        let session = session_id.lock().unwrap().clone();
+        let mut request = http.get(url).headers(headers.clone()).header(ACCEPT, "text/event-stream");
+        if let Some(session) = &session {
+            request = request.header(SESSION_HEADER, session);
+        }
+        let response = request.send().await.map_err(|e| format!("Failed to open MCP event stream: {}", e))?;
+        match response.status() {
+            reqwest::StatusCode::METHOD_NOT_ALLOWED => Ok(None),
+            status if status.is_success() => Ok(Some(response)),
+            status => Err(format!("MCP server returned HTTP {} for its event stream", status)),
+        }
+    }
+
+    /// Mark the connection as lost and fail the requests still waiting; later ones see `closed`
+    fn close(&self) {
+        self.closed.cancel();
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Close the connection after a transport error
+    fn fail(&self, error: String) -> String {
         self.close();
+        error
+    }
+}
+
+/// Read line-delimited messages from a server's stdout until it closes
+async fn read_lines(stdout: ChildStdout, connection: Arc<Connection>) {
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => connection.receive_all(parse_messages(&line)).await,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("[MCP] Failed to read: {}", e);
+                break;
+            }
+        }
+    }
+    connection.close();
+}
+
+/// Read `message` events from a legacy SSE stream until it ends
+async fn read_events<S, B>(body: Pin<Box<S>>, decoder: SseDecoder, early: Vec<SseEvent>, connection: Arc<Connection>)
+where
+    S: Stream<Item = reqwest::Result<B>>,
+    B: AsRef<[u8]>,
+{
+    read_stream(body, decoder, early, &connection).await;
+    connection.close();
+}
+
+/// Read the GET event stream of a Streamable HTTP server, reopening it whenever it ends
+///
+/// The server may end the stream at any time; that doesn't end the session.
+async fn read_server_stream(response: reqwest::Response, connection: Arc<Connection>) {
+    let mut response = Some(response);
+    loop {
+        if let Some(response) = response.take() {
+            read_stream(Box::pin(response.bytes_stream()), SseDecoder::new(), Vec::new(), &connection).await;
+        }
+        tokio::select! {
+            _ = connection.closed.cancelled() => return,
+            _ = tokio::time::sleep(STREAM_REOPEN_DELAY) => {}
+        }
+        match connection.open_server_stream().await {
+            Ok(Some(next)) => response = Some(next),
+            Ok(None) => return,
+            Err(e) => eprintln!("[MCP] {}", e),
+        }
+    }
+}
+
+/// Handle the `message` events of an event stream until it ends
+async fn read_stream<S, B>(mut body: Pin<Box<S>>, mut decoder: SseDecoder, early: Vec<SseEvent>, connection: &Connection)
+where
+    S: Stream<Item = reqwest::Result<B>>,
+    B: AsRef<[u8]>,
+{
+    let mut events = early;
+    loop {
+        for event in events.drain(..) {
+            if event.event.as_deref().is_none_or(|name| name == "message") {
+                connection.receive_all(parse_messages(&event.data)).await;
+            }
+        }
+        match body.next().await {
+            Some(Ok(chunk)) => events = decoder.push(chunk.as_ref()),
+            Some(Err(e)) => {
+                eprintln!("[MCP] Failed to read: {}", e);
+                break;
+            }
+            None => break,
+        }
+    }
+}
+
+/// Messages in a Streamable HTTP response: a JSON body, or an event stream read up to the first response
+async fn read_http_messages(response: reqwest::Response) -> Result<Vec<Value>, String> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("MCP server returned HTTP {}: {}", status, body.trim()));
+    }
+    let is_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream {
+        // Empty for accepted notifications and responses (202)
+        let body = response.text().await.map_err(|e| format!("Failed to read: {}", e))?;
+        return Ok(parse_messages(&body));
+    }
+
+    let is_response = |message: &Value| message.get("method").is_none() && message.get("id").is_some();
+    let mut body = response.bytes_stream();
+    let mut decoder = SseDecoder::new();
+    let mut messages = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read: {}", e))?;
+        for event in decoder.push(&chunk) {
+            messages.extend(parse_messages(&event.data));
+        }
+        // The server may keep the stream open after answering
+        if messages.iter().any(is_response) {
+            return Ok(messages);
+        }
+    }
+    messages.extend(decoder.finish().into_iter().flat_map(|event| parse_messages(&event.data)));
+    Ok(messages)
+}
+
+/// Parse a JSON-RPC message or batch (blank input yields nothing)
+fn parse_messages(text: &str) -> Vec<Value> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    match serde_json::from_str(text) {
+        Ok(Value::Array(batch)) => batch,
+        Ok(message) => vec![message],
+        Err(e) => {
+            eprintln!("[MCP] Failed to parse JSON: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// HTTP client of a remote server connection
+///
+/// Requests have their own timeouts, so the read timeout only ends event
+/// streams that stay silent for longer than the longest request may take.
+fn http_client(network: &NetworkConfig) -> Result<reqwest::Client, String> {
+    let connect_timeout = network.connect_timeout_secs.unwrap_or(NetworkConfig::DEFAULT_CONNECT_TIMEOUT_SECS);
+    http::build_client(network, Duration::from_secs(connect_timeout), TOOL_CALL_TIMEOUT).map_err(|e| e.to_string())
+}
+
+/// Configured headers with key references resolved
+fn header_map(headers: &HashMap<String, String>) -> Result<HeaderMap, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| format!("Invalid header {}: {}", name, e))?;
+            let value = HeaderValue::from_str(&resolve_header_value(value)?)
+                .map_err(|e| format!("Invalid value of header {}: {}", name, e))?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Resolve a key reference in a header value, keeping a scheme before it (`Bearer secret:mcp-token`)
+fn resolve_header_value(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    let (scheme, reference) = match value.rsplit_once(' ') {
+        Some((scheme, reference)) => (format!("{} ", scheme), reference),
+        None => (String::new(), value),
+    };
+    match KeySource::parse(reference) {
+        KeySource::Plain(_) => Ok(value.to_string()),
+        source => source.resolve().map(|key| scheme + &key).map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+
+    /// Fake server: before answering `initialize` it sends a notification and a ping;
+    /// `tools/list` reports whether the ping was answered; the next request is never
+    /// answered, and the one after that makes it exit.
+    #[cfg(unix)]
+    const FAKE_SERVER: &str = r#"
+        read init
+        echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","data":"starting"}}'
+        echo '{"jsonrpc":"2.0","id":"srv-1","method":"ping"}'
+        echo '{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":"2024-11-05","capabilities":{}}}'
+        read a; read b; read c
+        case "$a$b$c" in *'"result":{}'*) tool=pong ;; *) tool=none ;; esac
+        echo '{"jsonrpc":"2.0","id":1,"result":{"tools":[{"name":"'$tool'","description":"","inputSchema":{}}]}}'
+        read slow; read cancelled; read last
+    "#;
+
+    fn server(transport: McpTransport, command: &str, args: &[&str]) -> McpServerConfig {
+        McpServerConfig {
+            name: "test".to_string(),
+            transport,
+            command: command.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: None,
+            enabled: true,
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_interleaved_messages_and_timeouts() {
+        let config = server(McpTransport::Stdio, "sh", &["-c", FAKE_SERVER]);
+        let client = McpClient::connect(&config, &NetworkConfig::default()).await.unwrap();
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "pong");
+
+        let error = client.request("tools/call", None, Duration::from_millis(200)).await.unwrap_err();
+        assert!(error.contains("timed out"), "{}", error);
+        assert!(client.connection.pending.lock().unwrap().is_empty());
+
+        let error = client.request("tools/list", None, REQUEST_TIMEOUT).await.unwrap_err();
+        assert!(error.contains("closed"), "{}", error);
+    }
+
+    /// Fake server with two pages of resources, a template, and a subscription that is
+    /// updated right away
+    #[cfg(unix)]
+    const RESOURCE_SERVER: &str = r#"
+        read init
+        echo '{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":"2024-11-05","capabilities":{"resources":{"subscribe":true}}}}'
+        read initialized; read list
+        echo '{"jsonrpc":"2.0","id":1,"result":{"resources":[{"uri":"file:///a.txt","name":"a.txt","mimeType":"text/plain"}],"nextCursor":"p2"}}'
+        read list
+        case "$list" in
+            *'"cursor":"p2"'*) echo '{"jsonrpc":"2.0","id":2,"result":{"resources":[{"uri":"file:///b.png","name":"b.png"}]}}' ;;
+            *) echo '{"jsonrpc":"2.0","id":2,"error":{"code":-32602,"message":"no cursor"}}' ;;
+        esac
+        read templates
+        echo '{"jsonrpc":"2.0","id":3,"result":{"resourceTemplates":[{"uriTemplate":"file:///{path}","name":"file"}]}}'
+        read read
+        echo '{"jsonrpc":"2.0","id":4,"result":{"contents":[{"uri":"file:///a.txt","mimeType":"text/plain","text":"hello"}]}}'
+        read subscribe
+        echo '{"jsonrpc":"2.0","id":5,"result":{}}'
+        echo '{"jsonrpc":"2.0","method":"notifications/resources/updated","params":{"uri":"file:///a.txt"}}'
+        read forever
+    "#;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_resources() {
+        let config = server(McpTransport::Stdio, "sh", &["-c", RESOURCE_SERVER]);
+        let client = McpClient::connect(&config, &NetworkConfig::default()).await.unwrap();
+        assert!(client.supports_resources() && client.supports_resource_subscriptions());
+
+        let resources = client.list_resources().await.unwrap();
+        let uris: Vec<_> = resources.iter().map(|r| r.uri.as_str()).collect();
+        assert_eq!(uris, ["file:///a.txt", "file:///b.png"]);
+        let templates = client.list_resource_templates().await.unwrap();
+        assert_eq!(templates[0].uri_template, "file:///{path}");
+
+        let contents = client.read_resource("file:///a.txt").await.unwrap();
+        assert_eq!(contents[0].text.as_deref(), Some("hello"));
+
+        let mut notifications = client.notifications();
+        client.subscribe_resource("file:///a.txt").await.unwrap();
+        let notification = tokio::time::timeout(Duration::from_secs(5), notifications.recv()).await.unwrap().unwrap();
+        assert_eq!(notification.method, "notifications/resources/updated");
+        assert_eq!(notification.params["uri"], "file:///a.txt");
+    }
+
+    /// Fake server with one prompt whose messages echo its `code` argument
+    #[cfg(unix)]
+    const PROMPT_SERVER: &str = r#"
+        read init
+        echo '{"jsonrpc":"2.0","id":0,"result":{"protocolVersion":"2024-11-05","capabilities":{"prompts":{}}}}'
+        read initialized; read list
+        echo '{"jsonrpc":"2.0","id":1,"result":{"prompts":[{"name":"review","arguments":[{"name":"code","required":true},{"name":"style"}]}]}}'
+        read get
+        case "$get" in
+            *'"code":"x"'*) echo '{"jsonrpc":"2.0","id":2,"result":{"messages":[
+                {"role":"user","content":{"type":"text","text":"Review x"}},
+                {"role":"user","content":{"type":"resource","resource":{"uri":"file:///x","text":"x"}}},
+                {"role":"assistant","content":{"type":"audio","data":"","mimeType":"audio/wav"}}]}}' | tr -d '\n' ; echo ;;
+            *) echo '{"jsonrpc":"2.0","id":2,"error":{"code":-32602,"message":"missing code"}}' ;;
+        esac
+        read forever
+    "#;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prompts() {
+        let config = server(McpTransport::Stdio, "sh", &["-c", PROMPT_SERVER]);
+        let client = McpClient::connect(&config, &NetworkConfig::default()).await.unwrap();
+        assert!(client.supports_prompts() && !client.supports_resources());
+
+        let prompts = client.list_prompts().await.unwrap();
+        assert_eq!(prompts[0].name, "review");
+        assert!(prompts[0].arguments[0].required && !prompts[0].arguments[1].required);
+
+        let arguments = HashMap::from([("code".to_string(), "x".to_string())]);
+        let messages = client.get_prompt("review", &arguments).await.unwrap();
+        assert_eq!(messages[0].content, McpPromptContent::Text { text: "Review x".to_string() });
+        assert!(matches!(&messages[1].content, McpPromptContent::Resource { resource } if resource.uri == "file:///x"));
+        assert_eq!((messages[2].role.as_str(), &messages[2].content), ("assistant", &McpPromptContent::Unsupported));
+    }
+
+    /// Read one HTTP request: (request line, lowercase headers, body)
+    fn read_request(stream: &mut TcpStream) -> (String, HashMap<String, String>, Value) {
+        let mut reader = std::io::BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(':') {
+                Some((name, value)) => headers.insert(name.to_lowercase(), value.trim().to_string()),
+                None => break,
+            };
+        }
+        let length = headers.get("content-length").map_or(0, |l| l.parse().unwrap());
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        (request_line, headers, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn respond(stream: &mut TcpStream, status: &str, headers: &str, body: &str) {
+        let response = format!("HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, headers, body.len(), body);
+        stream.write_all(response.as_bytes()).unwrap();
+    }
+
+    /// Answer of the local test server to a JSON-RPC request
+    fn answer(request: &Value, headers: &HashMap<String, String>) -> Value {
+        let result = match request["method"].as_str() {
+            Some("tools/list") => json!({"tools": [{"name": "whoami", "description": "", "inputSchema": {}}]}),
+            Some("tools/call") => json!({"content": [{"type": "text", "text": headers.get("authorization").cloned().unwrap_or_default()}]}),
+            _ => json!({"protocolVersion": request["params"]["protocolVersion"], "capabilities": {}}),
+        };
+        json!({"jsonrpc": "2.0", "id": request["id"], "result": result})
+    }
+
+    /// Local Streamable HTTP server; `tools/list` is answered on an event stream after a notification,
+    /// and the GET event stream sends a resource update
+    fn serve_streamable_http() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let (request_line, headers, request) = read_request(&mut stream);
+                if request_line.starts_with("GET") {
+                    assert_eq!(headers.get(SESSION_HEADER).map(String::as_str), Some("s1"));
+                    let update = json!({"jsonrpc": "2.0", "method": "notifications/resources/updated", "params": {"uri": "file:///x"}});
+                    respond(&mut stream, "200 OK", "Content-Type: text/event-stream\r\n", &format!("data: {}\n\n", update));
+                } else if request.get("id").is_none() {
+                    respond(&mut stream, "202 Accepted", "", "");
+                } else if request["method"] == "initialize" {
+                    assert_eq!(request["params"]["protocolVersion"], "2025-03-26");
+                    respond(&mut stream, "200 OK", "Content-Type: application/json\r\nMcp-Session-Id: s1\r\n", &answer(&request, &headers).to_string());
+                } else if headers.get(SESSION_HEADER).map(String::as_str) != Some("s1") {
+                    respond(&mut stream, "400 Bad Request", "", "missing session");
+                } else {
+                    let events = format!(
+                        "event: message\ndata: {}\n\ndata: {}\n\n",
+                        json!({"jsonrpc": "2.0", "method": "notifications/progress", "params": {}}),
+                        answer(&request, &headers)
+                    );
+                    respond(&mut stream, "200 OK", "Content-Type: text/event-stream\r\n", &events);
+                }
+            }
+        });
+        url
+    }
+
+    /// Local legacy HTTP+SSE server: answers to POSTed requests are pushed on the event stream
+    fn serve_sse() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sse", listener.local_addr().unwrap());
+        let (events_tx, events_rx) = mpsc::channel::<Value>();
+        std::thread::spawn(move || {
+            let mut events_rx = Some(events_rx);
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let (request_line, headers, request) = read_request(&mut stream);
+                if request_line.starts_with("GET") {
+                    let events_rx = events_rx.take().unwrap();
+                    std::thread::spawn(move || {
+                        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n").unwrap();
+                        stream.write_all(b"event: endpoint\ndata: /messages?session=1\n\n").unwrap();
+                        for message in events_rx {
+                            stream.write_all(format!("event: message\ndata: {}\n\n", message).as_bytes()).unwrap();
+                        }
+                    });
+                } else {
+                    assert!(request_line.starts_with("POST /messages?session=1"), "{}", request_line);
+                    if request["method"] == "initialize" {
+                        assert_eq!(request["params"]["protocolVersion"], "2024-11-05");
+                    }
+                    if request["method"] == "shutdown" {
+                        respond(&mut stream, "503 Service Unavailable", "", "");
+                        continue;
+                    }
+                    if request.get("id").is_some() {
+                        events_tx.send(answer(&request, &headers)).unwrap();
+                    }
+                    respond(&mut stream, "202 Accepted", "", "");
+                }
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_http_transports() {
+        std::env::set_var("VELD_TEST_MCP_TOKEN", "t0ken");
+        let headers = HashMap::from([("Authorization".to_string(), "Bearer env:VELD_TEST_MCP_TOKEN".to_string())]);
+        let transports = [
+            McpTransport::StreamableHttp { url: serve_streamable_http(), headers: headers.clone() },
+            McpTransport::Sse { url: serve_sse(), headers },
+        ];
+        for transport in transports {
+            let client = McpClient::connect(&server(transport, "", &[]), &NetworkConfig::default()).await.unwrap();
+            let mut notifications = client.notifications();
+            let tools = client.list_tools().await.unwrap();
+            assert_eq!(tools[0].name, "whoami");
+            let result = client.call_tool("whoami", json!({})).await.unwrap();
+            assert_eq!(result["content"][0]["text"], "Bearer t0ken");
+
+            if matches!(client.connection.transport, Transport::StreamableHttp { .. }) {
+                assert!(client.receives_notifications);
+                // Reopened after the test server ends it
+                let update = loop {
+                    let notification = tokio::time::timeout(Duration::from_secs(5), notifications.recv()).await.unwrap().unwrap();
+                    if notification.method == "notifications/resources/updated" {
+                        break notification;
+                    }
+                };
+                assert_eq!(update.params["uri"], "file:///x");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sse_post_error_closes_connection() {
+        let client = McpClient::connect(&server(McpTransport::Sse { url: serve_sse(), headers: HashMap::new() }, "", &[]), &NetworkConfig::default())
+            .await
+            .unwrap();
+        let error = client.request("shutdown", None, REQUEST_TIMEOUT).await.unwrap_err();
+        assert!(error.contains("503"), "{}", error);
+        // The pool restarts closed connections
+        tokio::time::timeout(Duration::from_secs(1), client.closed()).await.unwrap();
     }
 }
@@ -0,0 +1,356 @@
+//! App-wide MCP server pool
+//! MCP 服务管理 - 启动一次、缓存工具列表、崩溃后退避重启、资源与提示词
+
+use crate::config::{McpConfig, McpServerConfig, McpTransport, NetworkConfig};
+use crate::services::mcp_client::{
+    McpClient, McpNotification, McpPrompt, McpPromptMessage, McpResource, McpResourceContents, McpResourceTemplate,
+    McpTool,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tokio_util::sync::CancellationToken;
+
+/// First restart delay; doubled after each consecutive failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A server that stayed up this long starts over with the initial backoff
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Live status of an enabled MCP server
+#[derive(Debug, Clone, PartialEq)]
+pub enum McpServerStatus {
+    /// Spawning the process and initializing the session
+    Starting,
+    /// Connected, with this many tools
+    Running { tools: usize },
+    /// Failed or exited; restarted after `retry_in`
+    Restarting { error: String, retry_in: Duration },
+}
+
+/// A running server shared by all chats
+#[derive(Clone)]
+pub struct McpConnection {
+    pub server: String,
+    pub client: Arc<McpClient>,
+    pub tools: Vec<McpTool>,
+}
+
+/// Resources and resource templates of a connected server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerResources {
+    pub server: String,
+    pub resources: Vec<McpResource>,
+    pub templates: Vec<McpResourceTemplate>,
+}
+
+/// Prompts of a connected server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerPrompts {
+    pub server: String,
+    pub prompts: Vec<McpPrompt>,
+}
+
+/// Change to a server's resources
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceUpdate {
+    /// Resources were added or removed
+    ListChanged { server: String },
+    /// A subscribed resource changed
+    Updated { server: String, uri: String },
+}
+
+/// Supervised server: its config, the token stopping its supervisor, and the live connection
+struct Server {
+    config: McpServerConfig,
+    /// Network settings of a remote server's connection
+    network: NetworkConfig,
+    stop: CancellationToken,
+    status: McpServerStatus,
+    connection: Option<McpConnection>,
+}
+
+static SERVERS: LazyLock<Mutex<HashMap<String, Server>>> = LazyLock::new(Default::default);
+static STATUS: LazyLock<watch::Sender<BTreeMap<String, McpServerStatus>>> =
+    LazyLock::new(|| watch::Sender::new(BTreeMap::new()));
+static RESOURCE_UPDATES: LazyLock<broadcast::Sender<ResourceUpdate>> = LazyLock::new(|| broadcast::channel(64).0);
+
+/// Start, restart or stop servers to match the enabled servers of `config`
+///
+/// Servers whose configuration did not change keep running (and keep their state);
+/// remote servers are also restarted when the `network` settings change.
+pub fn sync(config: &McpConfig, network: &NetworkConfig) {
+    let enabled: HashMap<&str, &McpServerConfig> =
+        config.servers.iter().filter(|s| s.enabled).map(|s| (s.name.as_str(), s)).collect();
+
+    let mut servers = SERVERS.lock().unwrap();
+    servers.retain(|name, server| {
+        let keep = enabled.get(name.as_str()).is_some_and(|config| {
+            **config == server.config && (config.transport == McpTransport::Stdio || *network == server.network)
+        });
+        if !keep {
+            eprintln!("[MCP] Stopping {}", name);
+            server.stop.cancel();
+        }
+        keep
+    });
+    for (name, config) in enabled {
+        if servers.contains_key(name) {
+            continue;
+        }
+        let stop = CancellationToken::new();
+        tokio::spawn(supervise((*config).clone(), network.clone(), stop.clone()));
+        servers.insert(
+            name.to_string(),
+            Server {
+                config: (*config).clone(),
+                network: network.clone(),
+                stop,
+                status: McpServerStatus::Starting,
+                connection: None,
+            },
+        );
+    }
+    publish(&servers);
+}
+
+/// Status of every enabled server, updated as servers start, fail and restart
+pub fn status() -> watch::Receiver<BTreeMap<String, McpServerStatus>> {
+    STATUS.subscribe()
+}
+
+/// Connected servers, after waiting for the ones still starting
+pub async fn connections() -> Vec<McpConnection> {
+    let _ = status()
+        .wait_for(|status| !status.values().any(|s| *s == McpServerStatus::Starting))
+        .await;
+    SERVERS.lock().unwrap().values().filter_map(|server| server.connection.clone()).collect()
+}
+
+/// Resources of the connected servers that offer them, by server name
+///
+/// A server failing to list its resources is left out.
+pub async fn resources() -> Vec<ServerResources> {
+    let mut connections = connections().await;
+    connections.sort_by(|a, b| a.server.cmp(&b.server));
+
+    let mut all = Vec::new();
+    for connection in connections.into_iter().filter(|c| c.client.supports_resources()) {
+        let resources = match connection.client.list_resources().await {
+            Ok(resources) => resources,
+            Err(e) => {
+                eprintln!("[MCP] {} failed to list resources: {}", connection.server, e);
+                continue;
+            }
+        };
+        // Templates are optional; servers without any may not implement the method
+        let templates = connection.client.list_resource_templates().await.unwrap_or_default();
+        all.push(ServerResources { server: connection.server, resources, templates });
+    }
+    all
+}
+
+/// Prompts of the connected servers that offer them, by server name
+pub async fn prompts() -> Vec<ServerPrompts> {
+    let mut connections = connections().await;
+    connections.sort_by(|a, b| a.server.cmp(&b.server));
+
+    let mut all = Vec::new();
+    for connection in connections.into_iter().filter(|c| c.client.supports_prompts()) {
+        match connection.client.list_prompts().await {
+            Ok(prompts) => all.push(ServerPrompts { server: connection.server, prompts }),
+            Err(e) => eprintln!("[MCP] {} failed to list prompts: {}", connection.server, e),
+        }
+    }
+    all
+}
+
+/// Get the messages of a prompt of a running server
+pub async fn get_prompt(server: &str, name: &str, arguments: &HashMap<String, String>) -> Result<Vec<McpPromptMessage>, String> {
+    client(server)?.get_prompt(name, arguments).await
+}
+
+/// Read a resource of a running server
+pub async fn read_resource(server: &str, uri: &str) -> Result<Vec<McpResourceContents>, String> {
+    client(server)?.read_resource(uri).await
+}
+
+/// Get [`ResourceUpdate::Updated`] when the resource changes (no-op if the server doesn't support it)
+pub async fn subscribe_resource(server: &str, uri: &str) -> Result<(), String> {
+    let client = client(server)?;
+    if !client.supports_resource_subscriptions() {
+        return Ok(());
+    }
+    client.subscribe_resource(uri).await
+}
+
+pub async fn unsubscribe_resource(server: &str, uri: &str) -> Result<(), String> {
+    let client = client(server)?;
+    if !client.supports_resource_subscriptions() {
+        return Ok(());
+    }
+    client.unsubscribe_resource(uri).await
+}
+
+/// Resource changes reported by any server from now on
+pub fn resource_updates() -> broadcast::Receiver<ResourceUpdate> {
+    RESOURCE_UPDATES.subscribe()
+}
+
+fn client(server: &str) -> Result<Arc<McpClient>, String> {
+    SERVERS
+        .lock()
+        .unwrap()
+        .get(server)
+        .and_then(|s| s.connection.as_ref())
+        .map(|c| c.client.clone())
+        .ok_or_else(|| format!("MCP server {} is not running", server))
+}
+
+fn publish(servers: &HashMap<String, Server>) {
+    STATUS.send_replace(servers.iter().map(|(name, server)| (name.clone(), server.status.clone())).collect());
+}
+
+/// Update this supervisor's server, unless it has been stopped (and possibly replaced)
+fn update(name: &str, stop: &CancellationToken, status: McpServerStatus, connection: Option<McpConnection>) {
+    let mut servers = SERVERS.lock().unwrap();
+    if stop.is_cancelled() {
+        return;
+    }
+    if let Some(server) = servers.get_mut(name) {
+        server.status = status;
+        server.connection = connection;
+        publish(&servers);
+    }
+}
+
+/// Pass a server's resource notifications on to [`resource_updates`]
+async fn forward_resource_updates(server: &str, mut notifications: broadcast::Receiver<McpNotification>) {
+    loop {
+        let notification = match notifications.recv().await {
+            Ok(notification) => notification,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let server = server.to_string();
+        let update = match notification.method.as_str() {
+            "notifications/resources/list_changed" => ResourceUpdate::ListChanged { server },
+            "notifications/resources/updated" => match notification.params["uri"].as_str() {
+                Some(uri) => ResourceUpdate::Updated { server, uri: uri.to_string() },
+                None => continue,
+            },
+            _ => continue,
+        };
+        let _ = RESOURCE_UPDATES.send(update);
+    }
+}
+
+async fn connect(config: &McpServerConfig, network: &NetworkConfig) -> Result<(McpClient, Vec<McpTool>), String> {
+    eprintln!("[MCP] Connecting to {}...", config.name);
+    let client = McpClient::connect(config, network).await?;
+    let tools = client.list_tools().await?;
+    Ok((client, tools))
+}
+
+/// Keep one server running until `stop` is cancelled, restarting it with backoff
+async fn supervise(config: McpServerConfig, network: NetworkConfig, stop: CancellationToken) {
+    let name = config.name.clone();
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let connected = tokio::select! {
+            _ = stop.cancelled() => return,
+            connected = connect(&config, &network) => connected,
+        };
+        let error = match connected {
+            Ok((client, tools)) => {
+                eprintln!("[MCP] {} loaded {} tools", name, tools.len());
+                let connection = McpConnection { server: name.clone(), client: Arc::new(client), tools };
+                update(&name, &stop, McpServerStatus::Running { tools: connection.tools.len() }, Some(connection.clone()));
+
+                let started = Instant::now();
+                tokio::select! {
+                    _ = stop.cancelled() => return,
+                    _ = connection.client.closed() => {}
+                    _ = forward_resource_updates(&name, connection.client.notifications()) => {}
+                }
+                if started.elapsed() >= STABLE_AFTER {
+                    backoff = INITIAL_BACKOFF;
+                }
+                "Server exited".to_string()
+            }
+            Err(e) => e,
+        };
+
+        eprintln!("[MCP] {} failed: {} (restarting in {}s)", name, error, backoff.as_secs());
+        update(&name, &stop, McpServerStatus::Restarting { error, retry_in: backoff }, None);
+        tokio::select! {
+            _ = stop.cancelled() => return,
+            _ = tokio::time::sleep(backoff) => {}
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        update(&name, &stop, McpServerStatus::Starting, None);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::{ApiFormat, McpTransport};
+    use crate::services::replay::{self, Fixture};
+
+    /// Fake server that lists one tool, then runs the shell command `then`
+    fn fake_server(name: &str, then: &str) -> McpServerConfig {
+        let script = format!(
+            r#"read init; echo '{{"jsonrpc":"2.0","id":0,"result":{{}}}}'
+               read initialized; read list
+               echo '{{"jsonrpc":"2.0","id":1,"result":{{"tools":[{{"name":"echo","description":"","inputSchema":{{}}}}]}}}}'
+               {}"#,
+            then
+        );
+        McpServerConfig {
+            name: name.to_string(),
+            transport: McpTransport::Stdio,
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script],
+            env: None,
+            enabled: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pool_follows_config_and_restarts() {
+        // Other tests sync the pool through `chat_with_tools`
+        let _guard = replay::testing::use_mock_provider(ApiFormat::Anthropic, Fixture::default()).await;
+
+        let stable = fake_server("stable", "read forever");
+        let crashing = fake_server("crashing", "exit");
+        let disabled = McpServerConfig { enabled: false, ..fake_server("disabled", "") };
+        let network = NetworkConfig::default();
+        sync(&McpConfig { servers: vec![stable.clone(), crashing.clone(), disabled] }, &network);
+
+        let running = connections().await;
+        assert!(running.iter().any(|c| c.server == "stable" && c.tools[0].name == "echo"));
+        assert!(!status().borrow().contains_key("disabled"));
+
+        // The crashing server is scheduled for a restart; the stable one keeps its process
+        let client = running.iter().find(|c| c.server == "stable").unwrap().client.clone();
+        let mut rx = status();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            rx.wait_for(|s| matches!(s.get("crashing"), Some(McpServerStatus::Restarting { .. }))),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        // Network settings only matter to remote servers
+        let proxied = NetworkConfig { proxy_url: Some("http://proxy.local:8080".to_string()), ..Default::default() };
+        sync(&McpConfig { servers: vec![stable.clone()] }, &proxied);
+        assert!(status().borrow().keys().eq(["stable"]));
+        let again = connections().await;
+        assert!(Arc::ptr_eq(&again[0].client, &client));
+
+        sync(&McpConfig { servers: vec![] }, &network);
+        assert!(status().borrow().is_empty());
+    }
+}
@@ -2,10 +2,17 @@
 //! 服务模块
 
 pub mod ai_client;
+pub mod adapters;
+pub mod context;
+pub mod http;
 pub mod mcp_client;
 pub mod mcp_agent;
+pub mod mcp_manager;
+pub mod replay;
+pub mod sse;
 
-pub use ai_client::{AiClient, AiError, ChatMessage, user_message, system_message, assistant_message};
+pub use ai_client::{AiClient, AiError, ChatMessage, ConnectionTest, ContentBlock, StreamEvent, Usage, attachment_block, user_message, system_message, assistant_message};
 pub use mcp_client::{McpClient, McpTool};
 pub use mcp_agent::{chat_with_tools, AgentStep};
+pub use mcp_manager::McpServerStatus;
 
@@ -0,0 +1,304 @@
+//! Recorded HTTP fixtures for the mock provider
+//! 回放与录制 - 用脚本或录制的响应离线测试
+
+use crate::services::ai_client::{AiError, Result};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Script of exchanges served in order by the mock provider
+///
+/// Each request consumes the next exchange, so a tool_use sequence is simply the
+/// tool_use response followed by the final answer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Fixture {
+    /// Model ids returned when listing the mock provider's models
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub exchanges: Vec<Exchange>,
+}
+
+/// One request/response exchange
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Exchange {
+    /// Request body as sent (recorded for inspection; not matched when replaying)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    pub body: FixtureBody,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+/// Response body: a list of chunks delivered one at a time (streaming), a raw string or JSON
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum FixtureBody {
+    Chunks(Vec<String>),
+    Text(String),
+    Json(Value),
+}
+
+impl Exchange {
+    /// Successful JSON response (non-streaming requests)
+    pub fn json(body: Value) -> Self {
+        Exchange { request: None, status: 200, headers: HashMap::new(), body: FixtureBody::Json(body) }
+    }
+
+    /// Successful streaming response delivered in the given chunks
+    pub fn stream<S: Into<String>>(chunks: impl IntoIterator<Item = S>) -> Self {
+        Exchange {
+            request: None,
+            status: 200,
+            headers: HashMap::new(),
+            body: FixtureBody::Chunks(chunks.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Error response with the given status and body
+    pub fn error(status: u16, body: Value) -> Self {
+        Exchange { request: None, status, headers: HashMap::new(), body: FixtureBody::Json(body) }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Build the HTTP response this exchange describes
+    fn into_response(self) -> Result<reqwest::Response> {
+        let chunks: Vec<String> = match self.body {
+            FixtureBody::Chunks(chunks) => chunks,
+            FixtureBody::Text(text) => vec![text],
+            FixtureBody::Json(value) => vec![value.to_string()],
+        };
+        let body = reqwest::Body::wrap_stream(futures_util::stream::iter(
+            chunks.into_iter().map(|chunk| Ok::<_, std::io::Error>(chunk.into_bytes())),
+        ));
+
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        builder
+            .body(body)
+            .map(reqwest::Response::from)
+            .map_err(|e| AiError::Api(format!("Invalid fixture response: {}", e)))
+    }
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| AiError::Api(format!("Failed to read fixture {}: {}", path.display(), e)))?;
+        serde_json::from_str(&content)
+            .map_err(|e| AiError::Api(format!("Invalid fixture {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| AiError::Api(e.to_string()))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| AiError::Serialization(e.to_string()))?;
+        fs::write(path, json).map_err(|e| AiError::Api(format!("Failed to write fixture {}: {}", path.display(), e)))
+    }
+}
+
+/// Exchanges already served per fixture file
+static CURSORS: LazyLock<Mutex<HashMap<PathBuf, usize>>> = LazyLock::new(Default::default);
+
+/// Serve the next exchange of a fixture as an HTTP response
+pub fn replay(path: &Path) -> Result<reqwest::Response> {
+    let fixture = Fixture::load(path)?;
+    let mut cursors = CURSORS.lock().unwrap();
+    let served = cursors.entry(path.to_path_buf()).or_insert(0);
+    let exchange = fixture.exchanges.get(*served).cloned().ok_or_else(|| {
+        AiError::Api(format!("Fixture {} has no exchange left ({} served)", path.display(), served))
+    })?;
+    *served += 1;
+    exchange.into_response()
+}
+
+/// Start replaying a fixture from its first exchange again
+pub fn reset(path: &Path) {
+    CURSORS.lock().unwrap().remove(path);
+}
+
+/// Model ids listed by a mock provider's fixture
+pub fn models(path: &Path) -> Result<Vec<String>> {
+    Fixture::load(path).map(|fixture| fixture.models)
+}
+
+/// Pass a response through while recording it to a fixture file
+///
+/// The exchange is appended once the body has been read to the end, so an
+/// interrupted (e.g. cancelled) response is not recorded. Streamed bodies are
+/// stored line by line, which every stream parser accepts.
+pub fn record(path: PathBuf, request: &Value, response: reqwest::Response) -> reqwest::Response {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let recorded_headers: HashMap<String, String> = ["content-type", "retry-after", "retry-after-ms"]
+        .iter()
+        .filter_map(|name| Some((name.to_string(), headers.get(*name)?.to_str().ok()?.to_string())))
+        .collect();
+
+    let received = Arc::new(Mutex::new((Vec::<u8>::new(), 0usize)));
+    let tee = received.clone();
+    let body = response.bytes_stream().inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            let mut tee = tee.lock().unwrap();
+            tee.0.extend_from_slice(chunk);
+            tee.1 += 1;
+        }
+    });
+
+    let request = request.clone();
+    let finish = futures_util::stream::once(async move {
+        let (bytes, chunk_count) = std::mem::take(&mut *received.lock().unwrap());
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        let body = if chunk_count > 1 {
+            FixtureBody::Chunks(text.split_inclusive('\n').map(String::from).collect())
+        } else {
+            serde_json::from_str(&text).map(FixtureBody::Json).unwrap_or(FixtureBody::Text(text))
+        };
+        let exchange = Exchange { request: Some(request), status: status.as_u16(), headers: recorded_headers, body };
+
+        let mut fixture = Fixture::load(&path).unwrap_or_default();
+        fixture.exchanges.push(exchange);
+        if let Err(e) = fixture.save(&path) {
+            eprintln!("[AI] Failed to record fixture: {}", e);
+        }
+    })
+    .filter_map(|()| futures_util::future::ready(None));
+
+    let mut builder = http::Response::builder().status(status);
+    for (name, value) in &headers {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(reqwest::Body::wrap_stream(body.chain(finish)))
+        .expect("status and headers come from a valid response")
+        .into()
+}
+
+/// Helpers for running [`AiClient`](crate::services::AiClient) against a mock provider in tests
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use crate::config::{ApiFormat, AppConfig, ProviderConfig, ProviderType};
+    use std::sync::OnceLock;
+    use tokio::sync::MutexGuard;
+
+    static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Make the mock provider (speaking `format`) the only provider and script its exchanges
+    ///
+    /// All tests share one temporary config dir (via `VELD_CONFIG_DIR`), so the returned
+    /// guard must be held for the duration of the test.
+    pub async fn use_mock_provider(format: ApiFormat, fixture: Fixture) -> MutexGuard<'static, ()> {
+        let guard = LOCK.lock().await;
+        let dir = CONFIG_DIR.get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("veld-test-{}", std::process::id()));
+            std::env::set_var("VELD_CONFIG_DIR", &dir);
+            dir
+        });
+
+        let fixture_path = dir.join("fixture.json");
+        fixture.save(&fixture_path).unwrap();
+        reset(&fixture_path);
+
+        let mut config = AppConfig::default();
+        config.ai.providers = vec![ProviderConfig {
+            id: "mock".to_string(),
+            name: "Mock".to_string(),
+            provider_type: ProviderType::Mock,
+            api_key: None,
+            base_url: Some(fixture_path.display().to_string()),
+            model: Some("mock".to_string()),
+            enabled: true,
+            api_format: format,
+            supports_tools: None,
+            max_retries: None,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            prices: HashMap::new(),
+            generation: Default::default(),
+            auth_scheme: None,
+            extra_headers: HashMap::new(),
+            prompt_caching: None,
+            record_fixture: None,
+            context_window: None,
+        }];
+        config.ai.active_provider = Some("mock".to_string());
+        config.ai.fallback_providers.clear();
+        config.mcp.servers.clear();
+        config.save().unwrap();
+        guard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay_in_order() {
+        let path = std::env::temp_dir().join(format!("veld-replay-{}.json", std::process::id()));
+        let fixture = Fixture {
+            models: vec!["mock-large".to_string()],
+            exchanges: vec![
+                Exchange::stream(["data: a\n\n", "data: b\n\n"]),
+                Exchange::error(429, serde_json::json!({"error": "slow down"})).with_header("retry-after", "1"),
+            ],
+        };
+        fixture.save(&path).unwrap();
+        assert_eq!(Fixture::load(&path).unwrap(), fixture);
+        assert_eq!(models(&path).unwrap(), ["mock-large"]);
+
+        let chunks: Vec<_> = replay(&path).unwrap().bytes_stream().map(|c| c.unwrap()).collect().await;
+        assert_eq!(chunks, ["data: a\n\n", "data: b\n\n"]);
+        let response = replay(&path).unwrap();
+        assert_eq!(response.status(), 429);
+        assert_eq!(response.headers()["retry-after"], "1");
+        assert!(replay(&path).is_err());
+
+        reset(&path);
+        assert_eq!(replay(&path).unwrap().status(), 200);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_roundtrip() {
+        let source = std::env::temp_dir().join(format!("veld-record-src-{}.json", std::process::id()));
+        let target = std::env::temp_dir().join(format!("veld-record-{}.json", std::process::id()));
+        let _ = fs::remove_file(&target);
+        Fixture { models: vec![], exchanges: vec![Exchange::stream(["data: a\n\ndata: ", "b\n\n"])] }
+            .save(&source)
+            .unwrap();
+
+        let request = serde_json::json!({"model": "mock"});
+        let response = record(target.clone(), &request, replay(&source).unwrap());
+        assert_eq!(response.text().await.unwrap(), "data: a\n\ndata: b\n\n");
+
+        let recorded = Fixture::load(&target).unwrap();
+        assert_eq!(recorded.exchanges.len(), 1);
+        assert_eq!(recorded.exchanges[0].request, Some(request));
+        assert_eq!(
+            recorded.exchanges[0].body,
+            FixtureBody::Chunks(vec!["data: a\n".into(), "\n".into(), "data: b\n".into(), "\n".into()])
+        );
+        fs::remove_file(source).unwrap();
+        fs::remove_file(target).unwrap();
+    }
+}
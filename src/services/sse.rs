@@ -0,0 +1,114 @@
+//! Server-Sent Events decoder
+//! SSE 流解析器，用于 AI 流式响应
+
+/// A single decoded SSE event
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    /// Value of the `event:` field (if any)
+    pub event: Option<String>,
+    /// Concatenated `data:` lines (joined with `\n`)
+    pub data: String,
+}
+
+/// Incremental SSE decoder
+///
+/// Feed raw bytes as they arrive from the network; complete events are returned
+/// once their terminating blank line has been received. Partial lines (including
+/// split UTF-8 sequences) are buffered until the next chunk.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    current: SseEvent,
+    has_data: bool,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push a chunk of bytes and return all events completed by it
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line_bytes: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line_bytes);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flush a trailing event that was not terminated by a blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&rest).trim_end_matches('\r').to_string();
+            self.process_line(&line);
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        // Comment line (e.g. keep-alive)
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.current.event = Some(value.to_string()),
+            "data" => {
+                if self.has_data {
+                    self.current.data.push('\n');
+                }
+                self.current.data.push_str(value);
+                self.has_data = true;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = std::mem::take(&mut self.current);
+        let had_data = std::mem::replace(&mut self.has_data, false);
+        if had_data { Some(event) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"event: ping\ndata: {\"ty").is_empty());
+
+        let events = decoder.push(b"pe\":\"ping\"}\r\n\r\ndata: a\ndata: b\n\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.as_deref(), Some("ping"));
+        assert_eq!(events[0].data, "{\"type\":\"ping\"}");
+        assert_eq!(events[1].event, None);
+        assert_eq!(events[1].data, "a\nb");
+    }
+
+    #[test]
+    fn test_comments_and_trailing_event() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b": keep-alive\n\ndata: [DONE]").is_empty());
+        assert_eq!(decoder.finish().map(|e| e.data), Some("[DONE]".to_string()));
+    }
+}